@@ -1,7 +1,7 @@
 use base64::Engine;
 use image::imageops::colorops::{dither, ColorMap};
-use image::{imageops::FilterType, DynamicImage, Rgba, RgbaImage};
-use serde::Serialize;
+use image::{imageops::FilterType, DynamicImage, ImageBuffer, Pixel, Rgba, Rgba32FImage, RgbaImage};
+use serde::{Deserialize, Serialize};
 use tauri::{AppHandle, Emitter};
 use tauri_plugin_dialog::DialogExt;
 
@@ -16,7 +16,7 @@ async fn open_image(app: AppHandle) -> Result<Option<String>, String> {
     let file = app
         .dialog()
         .file()
-        .add_filter("Images", &["png", "jpg", "jpeg", "webp", "gif", "bmp"])
+        .add_filter("Images", &["png", "jpg", "jpeg", "webp", "gif", "bmp", "pcx", "tga", "tif", "tiff", "qoi", "ase", "aseprite"])
         .blocking_pick_file();
 
     let resolved = file
@@ -26,6 +26,18 @@ async fn open_image(app: AppHandle) -> Result<Option<String>, String> {
     Ok(resolved)
 }
 
+/// Pick a folder of images (one image per animation frame)
+#[tauri::command]
+async fn open_folder(app: AppHandle) -> Result<Option<String>, String> {
+    let folder = app.dialog().file().blocking_pick_folder();
+
+    let resolved = folder
+        .and_then(|path| path.into_path().ok())
+        .map(|path| path.to_string_lossy().to_string());
+
+    Ok(resolved)
+}
+
 #[derive(Serialize)]
 struct ConversionResult {
     preview_base64: String,
@@ -36,286 +48,3111 @@ struct ConversionResult {
     unique_tile_count: usize,
     tile_to_unique: Vec<usize>,
     was_pre_resized: bool,
+    merged_tile_count: usize,
+    reused_tile_count: usize,
+    flicker: Option<FlickerFrameResult>,  // Second frame when the flicker mode is on
+    palette_band_rows: u32,  // Band height in tile rows when palettes are built per band (0 = off)
+    band_palette_changes: Vec<usize>,  // Palette entries reloaded at the start of each band
+    raster_bands: Vec<RasterBandUsage>,  // Raster split bands (empty when none are declared)
+    sgx: Option<SgxLayerResult>,  // VDC2 layer in SuperGrafx mode (the main fields hold the VDC1 layer)
+    sprite_overlay: Option<SpriteOverlayResult>,  // Color boost sprites over the background
+    grid_offset: Option<(i32, i32)>,  // Offset (dx, dy) chosen by the grid alignment search, added to the pan
+    pixel_grid: Option<PixelGrid>,  // Native pixel grid of a pixel art source
+    warnings: Vec<String>,
+    pixel_indices: Vec<u8>,  // Color index of every pixel in the indexed palettes mode (empty otherwise)
 }
 
-/// Resize mask from source dimensions to target dimensions using nearest neighbor
-/// When keep_ratio is true, applies the same transformation as the image (resize + center)
-fn resize_mask(mask: &[u8], src_width: u32, src_height: u32, dst_width: u32, dst_height: u32, keep_ratio: bool) -> Vec<u8> {
-    // Start with white (no dithering) background
-    let mut result = vec![255u8; (dst_width * dst_height) as usize];
+/// VDC2 (background) layer of a SuperGrafx conversion, sharing the palettes of the VDC1 layer
+#[derive(Serialize)]
+struct SgxLayerResult {
+    preview_base64: String,
+    tile_palette_map: Vec<usize>,
+    empty_tiles: Vec<bool>,
+    unique_tile_count: usize,
+    tile_to_unique: Vec<usize>,
+    composite_preview_base64: String,  // VDC1 layer over the VDC2 layer, as displayed
+}
 
-    if !keep_ratio {
-        // Simple stretch to fill
-        for y in 0..dst_height {
-            for x in 0..dst_width {
-                let src_x = (x as f32 * src_width as f32 / dst_width as f32) as u32;
-                let src_y = (y as f32 * src_height as f32 / dst_height as f32) as u32;
-                let src_idx = (src_y * src_width + src_x) as usize;
-                let dst_idx = (y * dst_width + x) as usize;
-
-                if src_idx < mask.len() {
-                    result[dst_idx] = mask[src_idx];
-                }
-            }
-        }
+/// Palette and tile usage of one raster split band
+#[derive(Serialize)]
+struct RasterBandUsage {
+    start_line: u32,
+    end_line: u32,  // Exclusive
+    palettes: Vec<usize>,  // BG palette slots used by the band's tiles
+    tile_count: usize,  // Non-empty tiles
+    unique_tile_count: usize,
+}
+
+/// Second frame of a flicker conversion (the main result fields hold the first frame)
+#[derive(Serialize)]
+struct FlickerFrameResult {
+    preview_base64: String,
+    palettes: Vec<Vec<String>>,
+    tile_palette_map: Vec<usize>,
+    empty_tiles: Vec<bool>,
+    unique_tile_count: usize,
+    tile_to_unique: Vec<usize>,
+    blended_preview_base64: String,  // Average of both frames, as seen at 60 Hz
+    shared_unique_tile_count: usize,  // Unique tiles when both frames share one tile set
+}
+
+/// Size of a square-pixel source fitted in the target while keeping its ratio, in target
+/// pixels. pixel_aspect is the displayed width of a target pixel over its height (1.0 = square).
+fn fit_dimensions(src_width: u32, src_height: u32, dst_width: u32, dst_height: u32, pixel_aspect: f32) -> (u32, u32) {
+    let src_ratio = src_width as f32 / src_height as f32;
+    // Target ratio as displayed on screen
+    let dst_ratio = dst_width as f32 * pixel_aspect / dst_height as f32;
+
+    if src_ratio > dst_ratio {
+        // Source is wider - fit to width
+        let w = dst_width;
+        let h = (dst_width as f32 * pixel_aspect / src_ratio).round() as u32;
+        (w, h.clamp(1, dst_height))
     } else {
-        // Calculate scaled dimensions keeping aspect ratio (same logic as resize_to_target)
-        let src_ratio = src_width as f32 / src_height as f32;
-        let dst_ratio = dst_width as f32 / dst_height as f32;
-
-        let (scaled_width, scaled_height) = if src_ratio > dst_ratio {
-            // Source is wider - fit to width
-            let w = dst_width;
-            let h = (dst_width as f32 / src_ratio).round() as u32;
-            (w, h.min(dst_height))
-        } else {
-            // Source is taller - fit to height
-            let h = dst_height;
-            let w = (dst_height as f32 * src_ratio).round() as u32;
-            (w.min(dst_width), h)
-        };
+        // Source is taller - fit to height
+        let h = dst_height;
+        let w = (dst_height as f32 * src_ratio / pixel_aspect).round() as u32;
+        (w.clamp(1, dst_width), h)
+    }
+}
 
-        // Calculate offsets to center
-        let offset_x = (dst_width - scaled_width) / 2;
-        let offset_y = (dst_height - scaled_height) / 2;
-
-        // Map pixels from destination to source, considering offset and scaling
-        for y in 0..dst_height {
-            for x in 0..dst_width {
-                // Check if this pixel is within the scaled image area
-                if x >= offset_x && x < offset_x + scaled_width && y >= offset_y && y < offset_y + scaled_height {
-                    // Calculate source position
-                    let local_x = x - offset_x;
-                    let local_y = y - offset_y;
-                    let src_x = (local_x as f32 * src_width as f32 / scaled_width as f32) as u32;
-                    let src_y = (local_y as f32 * src_height as f32 / scaled_height as f32) as u32;
-                    let src_idx = (src_y.min(src_height - 1) * src_width + src_x.min(src_width - 1)) as usize;
-                    let dst_idx = (y * dst_width + x) as usize;
-
-                    if src_idx < mask.len() {
-                        result[dst_idx] = mask[src_idx];
-                    }
-                }
-                // Pixels outside the scaled area remain white (no dithering)
-            }
-        }
+/// Source region and placement chosen by the user: Aseprite layer and frame, crop rectangle in
+/// source pixels (0 width or height = whole image), scale factor (0 = fit or stretch) and pan in
+/// target pixels
+#[derive(Deserialize, Clone, Copy, Default)]
+struct SourceTransform {
+    layer: Option<u32>,  // Aseprite layer or group to convert (None = visible layers)
+    frame: u32,  // Aseprite frame to convert
+    crop_x: u32,
+    crop_y: u32,
+    crop_width: u32,
+    crop_height: u32,
+    scale: f32,  // Target pixels per source pixel, vertically (the width follows the pixel aspect ratio)
+    offset_x: i32,
+    offset_y: i32,
+    pixel_art: bool,  // Detect the native pixel grid of upscaled pixel art and sample the pixel centers
+    #[serde(skip)]
+    pixel_grid: Option<PixelGrid>,  // Detected grid, filled by prepare_pixel_art
+}
+
+/// Native pixel grid of an upscaled pixel art source, in source pixels
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+struct PixelGrid {
+    scale_x: f32,  // Source pixels per native pixel
+    scale_y: f32,
+    phase_x: f32,  // Position of a native pixel boundary, in [0, scale)
+    phase_y: f32,
+    native_width: u32,  // Native pixels, partial ones on the edges included
+    native_height: u32,
+}
+
+impl PixelGrid {
+    /// Center of the native pixel under a source coordinate, clamped to the image
+    fn snap(position: f32, scale: f32, phase: f32, size: u32) -> u32 {
+        let cell = ((position - phase) / scale).floor();
+        (phase + (cell + 0.5) * scale).clamp(0.0, (size - 1) as f32) as u32
     }
 
-    result
+    /// Native pixels holding at least one source pixel center, partial ones on the edges included
+    fn native_size(size: u32, scale: f32, phase: f32) -> u32 {
+        let first = ((0.5 - phase) / scale).floor();
+        let last = ((size as f32 - 0.5 - phase) / scale).floor();
+        (last - first) as u32 + 1
+    }
 }
 
-/// Combine two images based on mask: black (0) = use dithered, white (255) = use non-dithered
-fn combine_with_mask(dithered: &RgbaImage, non_dithered: &RgbaImage, mask: &[u8]) -> RgbaImage {
-    let (width, height) = dithered.dimensions();
-    let mut result = RgbaImage::new(width, height);
+/// Max scale factor, keeps the intermediate resized image reasonable
+const MAX_SOURCE_SCALE: f32 = 16.0;
 
-    for y in 0..height {
-        for x in 0..width {
-            let idx = (y * width + x) as usize;
-            let mask_value = mask.get(idx).copied().unwrap_or(255);
+impl SourceTransform {
+    fn validate(&self) -> Result<(), String> {
+        if !self.scale.is_finite() || self.scale < 0.0 || self.scale > MAX_SOURCE_SCALE {
+            return Err(format!("Facteur d'échelle invalide : {} (0 à {})", self.scale, MAX_SOURCE_SCALE));
+        }
+        Ok(())
+    }
 
-            // mask < 128 means use dithered (black areas), otherwise use non-dithered
-            let pixel = if mask_value < 128 {
-                *dithered.get_pixel(x, y)
-            } else {
-                *non_dithered.get_pixel(x, y)
-            };
+    /// Crop rectangle clamped to a source of the given size: (x, y, width, height)
+    fn crop_rect(&self, src_width: u32, src_height: u32) -> (u32, u32, u32, u32) {
+        if self.crop_width == 0 || self.crop_height == 0 {
+            return (0, 0, src_width, src_height);
+        }
+        let x = self.crop_x.min(src_width.saturating_sub(1));
+        let y = self.crop_y.min(src_height.saturating_sub(1));
+        (x, y, self.crop_width.min(src_width - x), self.crop_height.min(src_height - y))
+    }
+
+    /// Position and size of a (cropped) source in the target: (x, y, width, height).
+    /// The position may be negative or the size larger than the target when panning or zooming.
+    fn placement(
+        &self,
+        src_width: u32,
+        src_height: u32,
+        dst_width: u32,
+        dst_height: u32,
+        keep_ratio: bool,
+        pixel_aspect: f32,
+    ) -> (i64, i64, u32, u32) {
+        let (width, height) = if self.scale > 0.0 {
+            (
+                ((src_width as f32 * self.scale / pixel_aspect).round() as u32).max(1),
+                ((src_height as f32 * self.scale).round() as u32).max(1),
+            )
+        } else if keep_ratio {
+            fit_dimensions(src_width, src_height, dst_width, dst_height, pixel_aspect)
+        } else {
+            (dst_width, dst_height)
+        };
+        // Centered, then panned
+        let x = (dst_width as i64 - width as i64).div_euclid(2) + self.offset_x as i64;
+        let y = (dst_height as i64 - height as i64).div_euclid(2) + self.offset_y as i64;
+        (x, y, width, height)
+    }
+}
+
+/// Resize mask from source dimensions to target dimensions using nearest neighbor
+/// Applies the same crop, scale and placement as the image (see resize_to_target)
+#[allow(clippy::too_many_arguments)]
+fn resize_mask(
+    mask: &[u8],
+    src_width: u32,
+    src_height: u32,
+    dst_width: u32,
+    dst_height: u32,
+    keep_ratio: bool,
+    pixel_aspect: f32,
+    transform: &SourceTransform,
+) -> Vec<u8> {
+    // Start with white (no dithering) background
+    let mut result = vec![255u8; (dst_width * dst_height) as usize];
+
+    let (crop_x, crop_y, crop_width, crop_height) = transform.crop_rect(src_width, src_height);
+    let (offset_x, offset_y, scaled_width, scaled_height) =
+        transform.placement(crop_width, crop_height, dst_width, dst_height, keep_ratio, pixel_aspect);
+
+    // Map pixels from destination to source, considering offset and scaling
+    for y in 0..dst_height {
+        for x in 0..dst_width {
+            let local_x = x as i64 - offset_x;
+            let local_y = y as i64 - offset_y;
+            // Pixels outside the scaled area remain white (no dithering)
+            if local_x < 0 || local_y < 0 || local_x >= scaled_width as i64 || local_y >= scaled_height as i64 {
+                continue;
+            }
+            let src_x = crop_x + ((local_x as f32 * crop_width as f32 / scaled_width as f32) as u32).min(crop_width - 1);
+            let src_y = crop_y + ((local_y as f32 * crop_height as f32 / scaled_height as f32) as u32).min(crop_height - 1);
+            let src_idx = (src_y * src_width + src_x) as usize;
+            let dst_idx = (y * dst_width + x) as usize;
 
-            result.put_pixel(x, y, pixel);
+            if src_idx < mask.len() {
+                result[dst_idx] = mask[src_idx];
+            }
         }
     }
 
     result
 }
 
-#[tauri::command]
-fn run_conversion(
-    app: AppHandle,
-    input_path: String,
-    resize_method: String,
-    palette_count: u8,
-    dither_mode: String,
-    background_color: String,
-    keep_ratio: bool,
-    curve_lut: Vec<u8>,
+/// Load the source image and crop it, then pre-resize it if it is more than 2x the target size.
+/// Returns the image and whether it was pre-resized.
+fn load_source_image(
+    app: &AppHandle,
+    input_path: &str,
     target_width: u32,
     target_height: u32,
-    use_dither_mask: bool,
-    dither_mask: Vec<u8>,
-    mask_width: u32,
-    mask_height: u32,
-    palette_group_constraints: Vec<i32>,  // -1 = auto, 0-15 = forced group
-    seed: u64,  // Seed for deterministic palette clustering
-) -> Result<ConversionResult, String> {
-    // Emit: loading image
-    let _ = app.emit("conversion-progress", ProgressEvent {
-        percent: 5,
-        stage: "Chargement de l'image...".to_string(),
-    });
-
-    let mut image = image::open(&input_path).map_err(|e| e.to_string())?;
+    transform: &SourceTransform,
+    linear_light: bool,  // Pre-resize in linear light
+) -> Result<(DynamicImage, bool, Option<String>), String> {
+    let (mut image, encoding_warning) = open_source_image(input_path, transform)?;
     let mut was_pre_resized = false;
 
+    let (crop_x, crop_y, crop_width, crop_height) = transform.crop_rect(image.width(), image.height());
+    if (crop_width, crop_height) != (image.width(), image.height()) {
+        image = image.crop_imm(crop_x, crop_y, crop_width, crop_height);
+    }
+
     // Pre-resize if source is more than 2x the target size
     // This improves performance and quality for very large images
+    // (not with an explicit scale, which is relative to the source pixels, nor with pixel art
+    // whose edges must stay sharp for the grid detection)
     let max_width = target_width * 2;
     let max_height = target_height * 2;
-    if image.width() > max_width || image.height() > max_height {
+    if transform.scale == 0.0 && !transform.pixel_art && (image.width() > max_width || image.height() > max_height) {
         let _ = app.emit("conversion-progress", ProgressEvent {
             percent: 10,
             stage: "Pré-redimensionnement...".to_string(),
         });
 
         // Use Lanczos3 for high-quality pre-resize
-        image = image.resize(max_width, max_height, FilterType::Lanczos3);
+        image = if linear_light {
+            let linear = DynamicImage::ImageRgba32F(to_linear_light(&image));
+            from_linear_light(linear.resize(max_width, max_height, FilterType::Lanczos3).to_rgba32f())
+        } else {
+            image.resize(max_width, max_height, FilterType::Lanczos3)
+        };
         was_pre_resized = true;
     }
 
-    // Emit: resizing
-    let _ = app.emit("conversion-progress", ProgressEvent {
-        percent: 15,
-        stage: "Redimensionnement...".to_string(),
-    });
+    Ok((image, was_pre_resized, encoding_warning))
+}
+
+/// Source image read with its palette, one palette index per pixel (row-major).
+/// Transparent pixels are stored as index 0.
+struct IndexedImage {
+    width: u32,
+    height: u32,
+    indices: Vec<u8>,
+    palette: Vec<[u8; 3]>,
+}
+
+impl IndexedImage {
+    fn to_rgba(&self) -> RgbaImage {
+        RgbaImage::from_fn(self.width, self.height, |x, y| {
+            let index = self.indices[(y * self.width + x) as usize] as usize;
+            let [r, g, b] = self.palette.get(index).copied().unwrap_or([0, 0, 0]);
+            Rgba([r, g, b, 255])
+        })
+    }
+}
+
+fn file_extension(path: &str) -> String {
+    std::path::Path::new(path)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// Open a source image, including the formats the image crate does not decode (PCX, and
+/// Aseprite with the layer and frame of the transform). Also returns a warning when the color
+/// profile of the source could not be converted to sRGB.
+fn open_source_image(path: &str, transform: &SourceTransform) -> Result<(DynamicImage, Option<String>), String> {
+    match file_extension(path).as_str() {
+        "pcx" => {
+            let data = std::fs::read(path).map_err(|e| e.to_string())?;
+            let image = match read_pcx(&data)? {
+                PcxImage::Indexed(image) => image.to_rgba(),
+                PcxImage::Rgb(image) => image,
+            };
+            Ok((DynamicImage::ImageRgba8(image), None))
+        }
+        "ase" | "aseprite" => {
+            let data = std::fs::read(path).map_err(|e| e.to_string())?;
+            let image = read_aseprite(&data)?.composite(transform.layer, transform.frame)?;
+            Ok((DynamicImage::ImageRgba8(image), None))
+        }
+        _ => {
+            let image = image::open(path).map_err(|e| e.to_string())?;
+            let encoding = source_encoding(path);
+            let warning = matches!(encoding, SourceEncoding::Unsupported)
+                .then(|| "Profil ICC de la source non pris en charge : couleurs lues comme sRGB".to_string());
+            Ok((convert_to_srgb(image, &encoding), warning))
+        }
+    }
+}
+
+/// Color encoding declared by a source file
+enum SourceEncoding {
+    Srgb,
+    Gamma(f32),  // PNG gAMA without sRGB chunk: encoded = linear ^ gamma
+    Icc(Box<moxcms::TransformF32BitExecutor>),  // ICC profile to sRGB
+    Unsupported,  // ICC profile that cannot be converted: read as sRGB
+}
+
+/// Encoding of a source from its ICC profile or, for PNG, its sRGB / iCCP / gAMA chunks
+/// (in that order of precedence). Unreadable files count as sRGB.
+fn source_encoding(path: &str) -> SourceEncoding {
+    use image::ImageDecoder;
+
+    if file_extension(path) == "png" {
+        let Ok(data) = std::fs::read(path) else {
+            return SourceEncoding::Srgb;
+        };
+        let Ok(reader) = png::Decoder::new(std::io::Cursor::new(data)).read_info() else {
+            return SourceEncoding::Srgb;
+        };
+        let info = reader.info();
+        if info.srgb.is_some() {
+            return SourceEncoding::Srgb;
+        }
+        if let Some(icc) = &info.icc_profile {
+            return icc_encoding(icc);
+        }
+        // 1/2.2 is the usual stand-in for sRGB
+        return match info.gama_chunk.map(|gamma| gamma.into_value()) {
+            Some(gamma) if gamma > 0.0 && (gamma - 1.0 / 2.2).abs() > 0.01 => SourceEncoding::Gamma(gamma),
+            _ => SourceEncoding::Srgb,
+        };
+    }
+    let icc = image::ImageReader::open(path)
+        .ok()
+        .and_then(|reader| reader.with_guessed_format().ok())
+        .and_then(|reader| reader.into_decoder().ok())
+        .and_then(|mut decoder| decoder.icc_profile().ok().flatten());
+    match icc {
+        Some(icc) => icc_encoding(&icc),
+        None => SourceEncoding::Srgb,
+    }
+}
+
+/// Transform from an ICC profile to sRGB. Profiles that leave sRGB colors as they are
+/// (the sRGB profile itself) count as sRGB so the pixels stay untouched.
+fn icc_encoding(icc: &[u8]) -> SourceEncoding {
+    let Ok(profile) = moxcms::ColorProfile::new_from_slice(icc) else {
+        return SourceEncoding::Unsupported;
+    };
+    let Ok(transform) = profile.create_transform_f32(
+        moxcms::Layout::Rgba,
+        &moxcms::ColorProfile::new_srgb(),
+        moxcms::Layout::Rgba,
+        moxcms::TransformOptions::default(),
+    ) else {
+        return SourceEncoding::Unsupported;
+    };
+    let samples: Vec<f32> = (0..512)
+        .flat_map(|i| [(i & 7) as f32 / 7.0, ((i >> 3) & 7) as f32 / 7.0, (i >> 6) as f32 / 7.0, 1.0])
+        .collect();
+    let mut converted = vec![0.0; samples.len()];
+    if transform.transform(&samples, &mut converted).is_err() {
+        return SourceEncoding::Unsupported;
+    }
+    if samples.iter().zip(&converted).all(|(a, b)| (a - b).abs() < 0.5 / 255.0) {
+        SourceEncoding::Srgb
+    } else {
+        SourceEncoding::Icc(transform)
+    }
+}
+
+/// Convert a source to sRGB from its declared encoding (as float pixels, to keep the precision)
+fn convert_to_srgb(image: DynamicImage, encoding: &SourceEncoding) -> DynamicImage {
+    let mut rgba = match encoding {
+        SourceEncoding::Gamma(_) | SourceEncoding::Icc(_) => image.to_rgba32f(),
+        _ => return image,
+    };
+    match encoding {
+        SourceEncoding::Gamma(gamma) => {
+            for pixel in rgba.pixels_mut() {
+                for c in 0..3 {
+                    pixel.0[c] = linear_to_srgb(pixel.0[c].clamp(0.0, 1.0).powf(1.0 / gamma));
+                }
+            }
+        }
+        SourceEncoding::Icc(transform) => {
+            let source = rgba.clone();
+            if transform.transform(&source, &mut rgba).is_err() {
+                return image;
+            }
+            rgba.iter_mut().for_each(|value| *value = value.clamp(0.0, 1.0));
+        }
+        _ => {}
+    }
+    DynamicImage::ImageRgba32F(rgba)
+}
+
+/// sRGB transfer function (0.0-1.0): encoded value to linear light
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Linear light to sRGB encoded value (0.0-1.0)
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Float copy of an image with its colors in linear light (alpha unchanged)
+fn to_linear_light(image: &DynamicImage) -> Rgba32FImage {
+    let mut rgba = image.to_rgba32f();
+    for pixel in rgba.pixels_mut() {
+        for c in 0..3 {
+            pixel.0[c] = srgb_to_linear(pixel.0[c].clamp(0.0, 1.0));
+        }
+    }
+    rgba
+}
+
+/// Back from linear light to an sRGB float image
+fn from_linear_light(mut rgba: Rgba32FImage) -> DynamicImage {
+    for pixel in rgba.pixels_mut() {
+        for c in 0..3 {
+            pixel.0[c] = linear_to_srgb(pixel.0[c].clamp(0.0, 1.0));
+        }
+    }
+    DynamicImage::ImageRgba32F(rgba)
+}
+
+/// Source image as PNG (base64) for the formats the webview cannot display
+#[tauri::command]
+fn load_source_preview(input_path: String, source_transform: SourceTransform) -> Result<String, String> {
+    encode_png_base64(open_source_image(&input_path, &source_transform)?.0.to_rgba8())
+}
+
+/// Read the palette indices of an indexed PNG, GIF, BMP, PCX or Aseprite file (None for a true
+/// color image)
+fn read_indexed_image(path: &str, transform: &SourceTransform) -> Result<Option<IndexedImage>, String> {
+    let data = std::fs::read(path).map_err(|e| e.to_string())?;
+    match file_extension(path).as_str() {
+        "ase" | "aseprite" => read_aseprite(&data)?.composite_indices(transform.layer, transform.frame),
+        "png" => read_indexed_png(&data),
+        "gif" => read_indexed_gif(&data),
+        "bmp" => read_indexed_bmp(&data),
+        "pcx" => match read_pcx(&data)? {
+            PcxImage::Indexed(image) => Ok(Some(image)),
+            PcxImage::Rgb(_) => Ok(None),
+        },
+        _ => Ok(None),
+    }
+}
+
+/// Unpack rows of 1, 2, 4 or 8 bit indices (most significant bits = leftmost pixel)
+fn unpack_index_rows(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    stride: usize,  // Bytes per row, padding included
+    bits: u32,
+    bottom_up: bool,  // First row of the data is the bottom row of the image
+) -> Result<Vec<u8>, String> {
+    // Header sizes are checked against the data before anything is allocated
+    let truncated = || "Données de l'image indexée tronquées".to_string();
+    let data_size = stride.checked_mul(height as usize).ok_or_else(truncated)?;
+    let row_size = (width as usize).checked_mul(bits as usize).ok_or_else(truncated)?.div_ceil(8);
+    if data.len() < data_size || row_size > stride {
+        return Err(truncated());
+    }
+    let mask = ((1u16 << bits) - 1) as u8;
+    let mut indices = Vec::with_capacity(width as usize * height as usize);
+    for y in 0..height {
+        let row_index = if bottom_up { height - 1 - y } else { y } as usize;
+        let row = &data[row_index * stride..(row_index + 1) * stride];
+        for x in 0..width {
+            let bit = x * bits;
+            let shift = 8 - bits - bit % 8;
+            indices.push((row[(bit / 8) as usize] >> shift) & mask);
+        }
+    }
+    Ok(indices)
+}
+
+fn read_indexed_png(data: &[u8]) -> Result<Option<IndexedImage>, String> {
+    let mut decoder = png::Decoder::new(std::io::Cursor::new(data));
+    decoder.set_transformations(png::Transformations::IDENTITY);
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let info = reader.info();
+    if info.color_type != png::ColorType::Indexed {
+        return Ok(None);
+    }
+    let (width, height, bits) = (info.width, info.height, info.bit_depth as u32);
+    let palette: Vec<[u8; 3]> = info
+        .palette
+        .as_deref()
+        .unwrap_or(&[])
+        .chunks_exact(3)
+        .map(|rgb| [rgb[0], rgb[1], rgb[2]])
+        .collect();
+    // tRNS gives the alpha of the first palette entries
+    let transparent: Vec<bool> = info.trns.as_deref().unwrap_or(&[]).iter().map(|&alpha| alpha == 0).collect();
+
+    let size = reader.output_buffer_size().ok_or("Image PNG trop grande")?;
+    let mut buffer = vec![0; size];
+    let frame = reader.next_frame(&mut buffer).map_err(|e| e.to_string())?;
+    let mut indices = unpack_index_rows(&buffer, width, height, frame.line_size, bits, false)?;
+    for index in indices.iter_mut() {
+        if transparent.get(*index as usize).copied().unwrap_or(false) {
+            *index = 0;
+        }
+    }
+    Ok(Some(IndexedImage { width, height, indices, palette }))
+}
+
+/// First frame of a GIF, on the logical screen (uncovered areas take the background index)
+fn read_indexed_gif(data: &[u8]) -> Result<Option<IndexedImage>, String> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = options.read_info(std::io::Cursor::new(data)).map_err(|e| e.to_string())?;
+    let (width, height) = (decoder.width() as u32, decoder.height() as u32);
+    let global_palette = decoder.global_palette().map(|palette| palette.to_vec());
+    let background = decoder.bg_color().unwrap_or(0) as u8;
+    let frame = decoder
+        .read_next_frame()
+        .map_err(|e| e.to_string())?
+        .ok_or("Le GIF ne contient aucune image")?;
+
+    let palette: Vec<[u8; 3]> = frame
+        .palette
+        .clone()
+        .or(global_palette)
+        .unwrap_or_default()
+        .chunks_exact(3)
+        .map(|rgb| [rgb[0], rgb[1], rgb[2]])
+        .collect();
+    let fill = if frame.transparent.is_some() { 0 } else { background };
+    let mut indices = vec![fill; (width * height) as usize];
+    for y in 0..frame.height as u32 {
+        for x in 0..frame.width as u32 {
+            let (screen_x, screen_y) = (frame.left as u32 + x, frame.top as u32 + y);
+            if screen_x >= width || screen_y >= height {
+                continue;
+            }
+            let index = frame.buffer[(y * frame.width as u32 + x) as usize];
+            indices[(screen_y * width + screen_x) as usize] = if Some(index) == frame.transparent { 0 } else { index };
+        }
+    }
+    Ok(Some(IndexedImage { width, height, indices, palette }))
+}
+
+/// Uncompressed 1, 4 or 8 bit BMP (Windows or OS/2 header)
+fn read_indexed_bmp(data: &[u8]) -> Result<Option<IndexedImage>, String> {
+    let u16_at = |offset: usize| data.get(offset..offset + 2).map(|b| u16::from_le_bytes([b[0], b[1]]));
+    let u32_at = |offset: usize| data.get(offset..offset + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+    let truncated = || "Fichier BMP tronqué".to_string();
+    if data.get(0..2) != Some(b"BM".as_slice()) {
+        return Err("Fichier BMP invalide".to_string());
+    }
+    let pixel_offset = u32_at(10).ok_or_else(truncated)? as usize;
+    let header_size = u32_at(14).ok_or_else(truncated)? as usize;
+
+    // OS/2 headers use 16-bit sizes and 3-byte palette entries
+    let (width, height, bits, compression, colors_used, entry_size) = if header_size == 12 {
+        let width = u16_at(18).ok_or_else(truncated)? as i32;
+        let height = u16_at(20).ok_or_else(truncated)? as i32;
+        (width, height, u16_at(24).ok_or_else(truncated)? as u32, 0, 0, 3)
+    } else {
+        (
+            u32_at(18).ok_or_else(truncated)? as i32,
+            u32_at(22).ok_or_else(truncated)? as i32,
+            u16_at(28).ok_or_else(truncated)? as u32,
+            u32_at(30).ok_or_else(truncated)?,
+            u32_at(46).ok_or_else(truncated)? as usize,
+            4,
+        )
+    };
+    if bits > 8 {
+        return Ok(None);
+    }
+    if compression != 0 {
+        return Err("BMP compressé (RLE) non pris en charge par le mode palettes indexées".to_string());
+    }
+    if width <= 0 || height == 0 || ![1, 2, 4, 8].contains(&bits) {
+        return Err("Fichier BMP invalide".to_string());
+    }
+
+    let palette_len = if colors_used == 0 { 1 << bits } else { colors_used.min(256) };
+    let palette_start = 14 + header_size;
+    let palette: Vec<[u8; 3]> = (0..palette_len)
+        .map(|index| {
+            let entry = palette_start + index * entry_size;
+            data.get(entry..entry + 3).map(|bgr| [bgr[2], bgr[1], bgr[0]]).ok_or_else(truncated)
+        })
+        .collect::<Result<_, _>>()?;
+
+    // Rows are padded to 4 bytes and stored bottom-up unless the height is negative
+    let (width, height_abs) = (width as u32, height.unsigned_abs());
+    let stride = (width as usize).checked_mul(bits as usize).ok_or_else(truncated)?.div_ceil(32) * 4;
+    let pixels = data.get(pixel_offset..).ok_or_else(truncated)?;
+    if pixels.len() < stride.checked_mul(height_abs as usize).ok_or_else(truncated)? {
+        return Err(truncated());
+    }
+    let indices = unpack_index_rows(pixels, width, height_abs, stride, bits, height > 0)?;
+    Ok(Some(IndexedImage { width, height: height_abs, indices, palette }))
+}
+
+/// Decoded PCX: palette based (1 to 8 bits, EGA planes) or 24/32-bit color
+enum PcxImage {
+    Indexed(IndexedImage),
+    Rgb(RgbaImage),
+}
+
+fn read_pcx(data: &[u8]) -> Result<PcxImage, String> {
+    if data.len() < 128 || data[0] != 0x0A {
+        return Err("Fichier PCX invalide".to_string());
+    }
+    let u16_at = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]) as u32;
+    let bits = data[3] as u32;
+    let (x_min, y_min, x_max, y_max) = (u16_at(4), u16_at(6), u16_at(8), u16_at(10));
+    let planes = data[65] as usize;
+    let bytes_per_line = u16_at(66) as usize;
+    if x_max < x_min || y_max < y_min || planes == 0 || bits == 0 {
+        return Err("Fichier PCX invalide".to_string());
+    }
+    let (width, height) = (x_max - x_min + 1, y_max - y_min + 1);
+    if bytes_per_line * 8 < (width * bits) as usize {
+        return Err("Fichier PCX invalide".to_string());
+    }
+
+    // Scanlines hold each plane in turn, run-length encoded (two top bits set = repeat count)
+    let scanline = planes * bytes_per_line;
+    let total = scanline * height as usize;
+    // A run of 2 bytes gives at most 63 bytes: a smaller file cannot hold the image
+    let packed = data.len() - 128;
+    let max_size = if data[2] == 1 { packed.saturating_mul(63).div_ceil(2) } else { packed };
+    if total > max_size {
+        return Err("Fichier PCX tronqué".to_string());
+    }
+    let mut raw = Vec::with_capacity(total);
+    let mut pos = 128;
+    while raw.len() < total && pos < data.len() {
+        let byte = data[pos];
+        pos += 1;
+        if data[2] == 1 && byte & 0xC0 == 0xC0 {
+            let value = *data.get(pos).ok_or("Fichier PCX tronqué")?;
+            pos += 1;
+            raw.resize(raw.len() + (byte & 0x3F) as usize, value);
+        } else {
+            raw.push(byte);
+        }
+    }
+    if raw.len() < total {
+        return Err("Fichier PCX tronqué".to_string());
+    }
+
+    let header_palette = || data[16..64].chunks_exact(3).map(|rgb| [rgb[0], rgb[1], rgb[2]]).collect::<Vec<_>>();
+    match (bits, planes) {
+        // 256 colors: the palette follows a 0x0C marker at the end of the file
+        (8, 1) => {
+            let marker = data.len().checked_sub(769).filter(|&offset| offset >= 128 && data[offset] == 0x0C);
+            let palette_start = marker.ok_or("Palette 256 couleurs du PCX absente")? + 1;
+            let palette = data[palette_start..].chunks_exact(3).map(|rgb| [rgb[0], rgb[1], rgb[2]]).collect();
+            let indices = unpack_index_rows(&raw, width, height, scanline, 8, false)?;
+            Ok(PcxImage::Indexed(IndexedImage { width, height, indices, palette }))
+        }
+        (1 | 2 | 4, 1) => {
+            let indices = unpack_index_rows(&raw, width, height, scanline, bits, false)?;
+            Ok(PcxImage::Indexed(IndexedImage { width, height, indices, palette: header_palette() }))
+        }
+        // EGA: one bit per plane, plane N gives bit N of the index
+        (1, 2..=4) => {
+            let mut indices = Vec::with_capacity((width * height) as usize);
+            for y in 0..height as usize {
+                for x in 0..width as usize {
+                    let index = (0..planes).fold(0u8, |index, plane| {
+                        let byte = raw[y * scanline + plane * bytes_per_line + x / 8];
+                        index | (((byte >> (7 - x % 8)) & 1) << plane)
+                    });
+                    indices.push(index);
+                }
+            }
+            Ok(PcxImage::Indexed(IndexedImage { width, height, indices, palette: header_palette() }))
+        }
+        (8, 3 | 4) => Ok(PcxImage::Rgb(RgbaImage::from_fn(width, height, |x, y| {
+            let row = y as usize * scanline + x as usize;
+            let alpha = if planes == 4 { raw[row + 3 * bytes_per_line] } else { 255 };
+            Rgba([raw[row], raw[row + bytes_per_line], raw[row + 2 * bytes_per_line], alpha])
+        }))),
+        _ => Err(format!("Format PCX non pris en charge ({} bits, {} plans)", bits, planes)),
+    }
+}
+
+/// Aseprite document: canvas, color mode, palette, layer stack and the cels of every frame
+struct AsepriteFile {
+    width: u32,
+    height: u32,
+    color_depth: u16,  // Bits per pixel: 32 = RGBA, 16 = grayscale with alpha, 8 = indexed
+    transparent_index: u8,  // Indexed mode: transparent in every layer but the background one
+    palette: Vec<[u8; 4]>,
+    layers: Vec<AsepriteLayer>,
+    frames: Vec<Vec<AsepriteCel>>,
+}
+
+struct AsepriteLayer {
+    name: String,
+    visible: bool,  // Own visibility flag (its groups may still hide it)
+    background: bool,
+    reference: bool,
+    group: bool,
+    tilemap: bool,
+    parent: Option<usize>,  // Enclosing group
+    opacity: u8,
+}
+
+#[derive(Clone)]
+struct AsepriteCel {
+    layer: usize,
+    x: i32,
+    y: i32,
+    z_index: i16,
+    opacity: u8,
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,  // Row-major, color_depth / 8 bytes per pixel
+}
+
+/// Layer and frame selection of an Aseprite source
+#[derive(Serialize)]
+struct AsepriteInfo {
+    width: u32,
+    height: u32,
+    frame_count: usize,
+    indexed: bool,
+    layers: Vec<AsepriteLayerInfo>,
+}
+
+#[derive(Serialize)]
+struct AsepriteLayerInfo {
+    name: String,
+    level: usize,  // Nesting depth in groups
+    visible: bool,
+    group: bool,
+    palette_group: Option<u8>,  // Palette group from a "pal<N>_" name
+}
+
+/// Largest canvas side accepted, in pixels (the canvas is allocated whole when compositing)
+const ASEPRITE_MAX_CANVAS_SIDE: u32 = 8192;
+/// Largest cel accepted, in pixels: cels may go past the canvas, not beyond this
+const ASEPRITE_MAX_CEL_PIXELS: usize = 8192 * 8192;
+
+fn read_aseprite(data: &[u8]) -> Result<AsepriteFile, String> {
+    use std::io::Read;
+
+    let truncated = || "Fichier Aseprite tronqué".to_string();
+    let u16_at = |offset: usize| data.get(offset..offset + 2).map(|b| u16::from_le_bytes([b[0], b[1]])).ok_or_else(truncated);
+    let u32_at = |offset: usize| data.get(offset..offset + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).ok_or_else(truncated);
+    if u16_at(4)? != 0xA5E0 {
+        return Err("Fichier Aseprite invalide".to_string());
+    }
+    let frame_count = u16_at(6)? as usize;
+    let (width, height) = (u16_at(8)? as u32, u16_at(10)? as u32);
+    if width > ASEPRITE_MAX_CANVAS_SIDE || height > ASEPRITE_MAX_CANVAS_SIDE {
+        return Err(format!("Image Aseprite trop grande ({}×{}, {} pixels au plus par côté)", width, height, ASEPRITE_MAX_CANVAS_SIDE));
+    }
+    let color_depth = u16_at(12)?;
+    if ![8, 16, 32].contains(&color_depth) {
+        return Err(format!("Profondeur de couleur Aseprite inconnue ({} bits)", color_depth));
+    }
+    // Header flag 1: the layer opacity is valid (older files leave it unset)
+    let layer_opacity_valid = u32_at(14)? & 1 != 0;
+    let transparent_index = *data.get(28).ok_or_else(truncated)?;
+
+    let mut layers: Vec<AsepriteLayer> = Vec::new();
+    let mut frames: Vec<Vec<AsepriteCel>> = Vec::with_capacity(frame_count);
+    let mut links: Vec<(usize, usize, u16)> = Vec::new();  // (frame, cel, linked frame)
+    let mut palette: Vec<[u8; 4]> = Vec::new();
+    let mut old_palette: Vec<[u8; 4]> = Vec::new();
+    let mut level_parents: Vec<usize> = Vec::new();  // Last group seen at each nesting level
+
+    let mut frame_start = 128;
+    for _ in 0..frame_count {
+        let frame_size = u32_at(frame_start)? as usize;
+        if u16_at(frame_start + 4)? != 0xF1FA || frame_size < 16 {
+            return Err("Image Aseprite invalide".to_string());
+        }
+        let old_chunks = u16_at(frame_start + 6)? as usize;
+        let new_chunks = u32_at(frame_start + 12)? as usize;
+        let chunk_count = if new_chunks == 0 { old_chunks } else { new_chunks };
+        let mut cels = Vec::new();
+
+        let mut chunk_start = frame_start + 16;
+        for _ in 0..chunk_count {
+            let chunk_size = u32_at(chunk_start)? as usize;
+            let chunk_type = u16_at(chunk_start + 4)?;
+            let chunk = data.get(chunk_start + 6..chunk_start + chunk_size.max(6)).ok_or_else(truncated)?;
+            let c16 = |offset: usize| chunk.get(offset..offset + 2).map(|b| u16::from_le_bytes([b[0], b[1]])).ok_or_else(truncated);
+            let c32 = |offset: usize| chunk.get(offset..offset + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).ok_or_else(truncated);
+            match chunk_type {
+                // Old palette: packets of (entries to skip, count, RGB colors)
+                0x0004 => {
+                    let mut offset = 2;
+                    let mut index = 0;
+                    for _ in 0..c16(0)? {
+                        index += *chunk.get(offset).ok_or_else(truncated)? as usize;
+                        let count = match *chunk.get(offset + 1).ok_or_else(truncated)? {
+                            0 => 256,
+                            count => count as usize,
+                        };
+                        offset += 2;
+                        for _ in 0..count {
+                            let rgb = chunk.get(offset..offset + 3).ok_or_else(truncated)?;
+                            if old_palette.len() <= index {
+                                old_palette.resize(index + 1, [0, 0, 0, 255]);
+                            }
+                            old_palette[index] = [rgb[0], rgb[1], rgb[2], 255];
+                            index += 1;
+                            offset += 3;
+                        }
+                    }
+                }
+                0x2004 => {
+                    let flags = c16(0)?;
+                    let layer_type = c16(2)?;
+                    let level = c16(4)? as usize;
+                    let name_length = c16(16)? as usize;
+                    let name = chunk.get(18..18 + name_length).ok_or_else(truncated)?;
+                    level_parents.truncate(level);
+                    let parent = if level > 0 { level_parents.get(level - 1).copied() } else { None };
+                    if layer_type == 1 {
+                        level_parents.push(layers.len());
+                    }
+                    layers.push(AsepriteLayer {
+                        name: String::from_utf8_lossy(name).to_string(),
+                        visible: flags & 1 != 0,
+                        background: flags & 8 != 0,
+                        reference: flags & 64 != 0,
+                        group: layer_type == 1,
+                        tilemap: layer_type == 2,
+                        parent,
+                        opacity: if layer_opacity_valid { chunk[12] } else { 255 },
+                    });
+                }
+                0x2005 => {
+                    let layer = c16(0)? as usize;
+                    let (x, y) = (c16(2)? as i16 as i32, c16(4)? as i16 as i32);
+                    let opacity = *chunk.get(6).ok_or_else(truncated)?;
+                    let cel_type = c16(7)?;
+                    let z_index = c16(9)? as i16;
+                    let mut cel = AsepriteCel { layer, x, y, z_index, opacity, width: 0, height: 0, pixels: Vec::new() };
+                    match cel_type {
+                        0 | 2 => {
+                            cel.width = c16(16)? as u32;
+                            cel.height = c16(18)? as u32;
+                            if cel.width as usize * cel.height as usize > ASEPRITE_MAX_CEL_PIXELS {
+                                return Err(format!("Cel Aseprite trop grande ({}×{})", cel.width, cel.height));
+                            }
+                            let size = (cel.width * cel.height) as usize * (color_depth / 8) as usize;
+                            let pixels = chunk.get(20..).ok_or_else(truncated)?;
+                            cel.pixels = if cel_type == 0 {
+                                pixels.get(..size).ok_or_else(truncated)?.to_vec()
+                            } else {
+                                // The declared size is only trusted up to the canvas size
+                                let canvas_size = (width * height) as usize * (color_depth / 8) as usize;
+                                let mut inflated = Vec::with_capacity(size.min(canvas_size));
+                                flate2::read::ZlibDecoder::new(pixels)
+                                    .take(size as u64)
+                                    .read_to_end(&mut inflated)
+                                    .map_err(|e| e.to_string())?;
+                                if inflated.len() < size {
+                                    return Err(truncated());
+                                }
+                                inflated
+                            };
+                            cels.push(cel);
+                        }
+                        1 => {
+                            links.push((frames.len(), cels.len(), c16(16)?));
+                            cels.push(cel);
+                        }
+                        // Tilemap cels are not supported, their layer stays empty
+                        _ => {}
+                    }
+                }
+                0x2019 => {
+                    let (size, first, last) = (c32(0)? as usize, c32(4)? as usize, c32(8)? as usize);
+                    palette.resize(size.max(palette.len()).min(256), [0, 0, 0, 255]);
+                    let mut offset = 20;
+                    for index in first..=last {
+                        let flags = c16(offset)?;
+                        let rgba = chunk.get(offset + 2..offset + 6).ok_or_else(truncated)?;
+                        if index < palette.len() {
+                            palette[index] = [rgba[0], rgba[1], rgba[2], rgba[3]];
+                        }
+                        offset += 6;
+                        // Entry with a name
+                        if flags & 1 != 0 {
+                            offset += 2 + c16(offset)? as usize;
+                        }
+                    }
+                }
+                _ => {}
+            }
+            chunk_start += chunk_size.max(6);
+        }
+        frames.push(cels);
+        frame_start += frame_size;
+    }
+
+    // Linked cels reuse the pixels of the same layer in another frame
+    for (frame, cel, linked_frame) in links {
+        let layer = frames[frame][cel].layer;
+        let source = frames
+            .get(linked_frame as usize)
+            .and_then(|cels| cels.iter().find(|other| other.layer == layer && !other.pixels.is_empty()))
+            .cloned();
+        if let Some(source) = source {
+            frames[frame][cel] = AsepriteCel { z_index: frames[frame][cel].z_index, ..source };
+        }
+    }
+    if palette.is_empty() {
+        palette = old_palette;
+    }
+
+    Ok(AsepriteFile { width, height, color_depth, transparent_index, palette, layers, frames })
+}
+
+/// Palette group named by a layer: "pal3_sky" or "pal12" give groups 3 and 12
+fn layer_palette_group(name: &str) -> Option<u8> {
+    let rest = name.strip_prefix("pal").or_else(|| name.strip_prefix("PAL"))?;
+    let digits: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+    let after = &rest[digits.len()..];
+    if digits.is_empty() || !(after.is_empty() || after.starts_with('_')) {
+        return None;
+    }
+    digits.parse::<u8>().ok().filter(|&group| group < 16)
+}
+
+impl AsepriteFile {
+    fn info(&self) -> AsepriteInfo {
+        let level = |mut index: usize| {
+            let mut level = 0;
+            while let Some(parent) = self.layers[index].parent {
+                level += 1;
+                index = parent;
+            }
+            level
+        };
+        AsepriteInfo {
+            width: self.width,
+            height: self.height,
+            frame_count: self.frames.len(),
+            indexed: self.color_depth == 8,
+            layers: self
+                .layers
+                .iter()
+                .enumerate()
+                .map(|(index, layer)| AsepriteLayerInfo {
+                    name: layer.name.clone(),
+                    level: level(index),
+                    visible: layer.visible,
+                    group: layer.group,
+                    palette_group: layer_palette_group(&layer.name),
+                })
+                .collect(),
+        }
+    }
+
+    /// Whether a layer is drawn for a selection: with no selection, the layer and all its groups
+    /// must be visible (reference layers never are); a selected layer is drawn even when hidden,
+    /// and a selected group draws its visible content
+    fn is_drawn(&self, index: usize, selection: Option<u32>) -> bool {
+        let layer = &self.layers[index];
+        if layer.group || layer.tilemap {
+            return false;
+        }
+        let mut current = Some(index);
+        while let Some(position) = current {
+            if Some(position as u32) == selection {
+                return true;
+            }
+            let layer = &self.layers[position];
+            if !layer.visible || layer.reference {
+                return false;
+            }
+            current = layer.parent;
+        }
+        selection.is_none()
+    }
+
+    /// Cels of a frame drawn for a selection, bottom to top
+    fn drawn_cels(&self, selection: Option<u32>, frame: u32) -> Result<Vec<&AsepriteCel>, String> {
+        if let Some(layer) = selection {
+            let layer = self.layers.get(layer as usize).ok_or("Calque Aseprite introuvable")?;
+            if layer.tilemap {
+                return Err(format!("Le calque de tuiles « {} » n'est pas pris en charge", layer.name));
+            }
+        }
+        let cels = self
+            .frames
+            .get(frame as usize)
+            .ok_or_else(|| format!("Le fichier Aseprite n'a que {} images", self.frames.len()))?;
+        let mut drawn: Vec<&AsepriteCel> = cels
+            .iter()
+            .filter(|cel| cel.layer < self.layers.len() && !cel.pixels.is_empty() && self.is_drawn(cel.layer, selection))
+            .collect();
+        // Draw order: layer index plus z-index, the z-index breaking ties
+        drawn.sort_by_key(|cel| (cel.layer as i32 + cel.z_index as i32, cel.z_index));
+        Ok(drawn)
+    }
+
+    /// Color of one cel pixel (transparent index of the non-background layers = transparent)
+    fn cel_color(&self, cel: &AsepriteCel, offset: usize) -> [u8; 4] {
+        match self.color_depth {
+            32 => {
+                let p = &cel.pixels[offset * 4..offset * 4 + 4];
+                [p[0], p[1], p[2], p[3]]
+            }
+            16 => {
+                let p = &cel.pixels[offset * 2..offset * 2 + 2];
+                [p[0], p[0], p[0], p[1]]
+            }
+            _ => {
+                let index = cel.pixels[offset];
+                if index == self.transparent_index && !self.layers[cel.layer].background {
+                    [0, 0, 0, 0]
+                } else {
+                    self.palette.get(index as usize).copied().unwrap_or([0, 0, 0, 255])
+                }
+            }
+        }
+    }
+
+    /// Composite the drawn layers of a frame (normal blending with cel and layer opacity)
+    fn composite(&self, selection: Option<u32>, frame: u32) -> Result<RgbaImage, String> {
+        let mut canvas = RgbaImage::new(self.width, self.height);
+        for cel in self.drawn_cels(selection, frame)? {
+            let opacity = cel.opacity as u32 * self.layers[cel.layer].opacity as u32 / 255;
+            for cy in 0..cel.height {
+                for cx in 0..cel.width {
+                    let (x, y) = (cel.x + cx as i32, cel.y + cy as i32);
+                    if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+                        continue;
+                    }
+                    let src = self.cel_color(cel, (cy * cel.width + cx) as usize);
+                    let src_alpha = src[3] as u32 * opacity / 255;
+                    if src_alpha == 0 {
+                        continue;
+                    }
+                    let dst = canvas.get_pixel_mut(x as u32, y as u32);
+                    let dst_alpha = dst.0[3] as u32 * (255 - src_alpha) / 255;
+                    let out_alpha = src_alpha + dst_alpha;
+                    for (channel, &value) in dst.0.iter_mut().zip(src.iter()).take(3) {
+                        *channel = ((value as u32 * src_alpha + *channel as u32 * dst_alpha) / out_alpha) as u8;
+                    }
+                    dst.0[3] = out_alpha as u8;
+                }
+            }
+        }
+        Ok(canvas)
+    }
+
+    /// Palette indices of the drawn layers of an indexed frame: the topmost opaque index wins,
+    /// transparent pixels are stored as index 0 (None for a non-indexed file)
+    fn composite_indices(&self, selection: Option<u32>, frame: u32) -> Result<Option<IndexedImage>, String> {
+        if self.color_depth != 8 {
+            return Ok(None);
+        }
+        let mut indices = vec![0u8; (self.width * self.height) as usize];
+        for cel in self.drawn_cels(selection, frame)? {
+            let background = self.layers[cel.layer].background;
+            for cy in 0..cel.height {
+                for cx in 0..cel.width {
+                    let (x, y) = (cel.x + cx as i32, cel.y + cy as i32);
+                    if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+                        continue;
+                    }
+                    let index = cel.pixels[(cy * cel.width + cx) as usize];
+                    if index != self.transparent_index || background {
+                        indices[(y as u32 * self.width + x as u32) as usize] = index;
+                    }
+                }
+            }
+        }
+        Ok(Some(IndexedImage {
+            width: self.width,
+            height: self.height,
+            indices,
+            palette: self.palette.iter().map(|&[r, g, b, _]| [r, g, b]).collect(),
+        }))
+    }
+}
+
+/// Layers and frames of an Aseprite source, for the layer and frame selection
+#[tauri::command]
+fn read_aseprite_info(input_path: String) -> Result<AsepriteInfo, String> {
+    let data = std::fs::read(&input_path).map_err(|e| e.to_string())?;
+    Ok(read_aseprite(&data)?.info())
+}
+
+/// Palette group of every virtual tile of an Aseprite source (row-major, -1 = none), from its
+/// visible layers named "pal<N>_...": the layer with the most opaque pixels in the tile wins
+#[tauri::command]
+fn read_aseprite_layer_groups(input_path: String, frame: u32, grid_width: u32, grid_height: u32) -> Result<Vec<i32>, String> {
+    let data = std::fs::read(&input_path).map_err(|e| e.to_string())?;
+    let file = read_aseprite(&data)?;
+    let (width, height) = (file.width, file.height);
+    if width == 0 || height == 0 {
+        return Err("Image Aseprite vide".to_string());
+    }
+    let (grid_width, grid_height) = (grid_width.clamp(1, width), grid_height.clamp(1, height));
+
+    let mut coverage = vec![[0u32; 16]; (grid_width * grid_height) as usize];
+    for (index, layer) in file.layers.iter().enumerate() {
+        let Some(group) = layer_palette_group(&layer.name) else {
+            continue;
+        };
+        if !file.is_drawn(index, None) {
+            continue;
+        }
+        let pixels = file.composite(Some(index as u32), frame)?;
+        for (x, y, pixel) in pixels.enumerate_pixels() {
+            if pixel.0[3] > 0 {
+                let tile = (y * grid_height / height) * grid_width + x * grid_width / width;
+                coverage[tile as usize][group as usize] += 1;
+            }
+        }
+    }
+    Ok(coverage
+        .iter()
+        .map(|counts| {
+            // Highest count, the lowest group on ties
+            match (0..16).rev().max_by_key(|&group| counts[group]) {
+                Some(group) if counts[group] > 0 => group as i32,
+                _ => -1,
+            }
+        })
+        .collect())
+}
+
+/// Palette indices of an indexed source placed at the target size like the color image
+/// (index in the red channel, uncovered areas = index 0), with nearest sampling only
+fn place_indexed_source(
+    source: &IndexedImage,
+    width: u32,
+    height: u32,
+    keep_ratio: bool,
+    pixel_aspect: f32,
+    transform: &SourceTransform,
+) -> Result<RgbaImage, String> {
+    let index_map = RgbaImage::from_fn(source.width, source.height, |x, y| {
+        Rgba([source.indices[(y * source.width + x) as usize], 0, 0, 255])
+    });
+    let (crop_x, crop_y, crop_width, crop_height) = transform.crop_rect(source.width, source.height);
+    let cropped = DynamicImage::ImageRgba8(index_map).crop_imm(crop_x, crop_y, crop_width, crop_height);
+    let placed = resize_to_target(cropped, width, height, "nearest", keep_ratio, "#000000", pixel_aspect, transform, false)?;
+    Ok(placed.to_rgba8())
+}
+
+/// Tile palettes of an indexed source laid out in blocks of 16 colors: block N is PCE palette N
+/// and the index within the block is the color index, so no color is matched. Color 0 of every
+/// palette is the color of index 0. Returns the palettes, the preview and the color index of
+/// every pixel (row-major).
+fn indexed_tile_palettes(index_map: &RgbaImage, palette: &[[u8; 3]]) -> Result<(TilePaletteResult, RgbaImage, Vec<u8>), String> {
+    let (width, height) = index_map.dimensions();
+    let tiles_x = width / 8;
+    let total_tiles = (tiles_x * (height / 8)) as usize;
+
+    let to_hex = |rgb: &[u8; 3]| {
+        let [r, g, b] = rgb.map(|value| quantize_channel_with_levels(value, 8));
+        format!("#{:02X}{:02X}{:02X}", r, g, b)
+    };
+    let color0 = palette.first().map(to_hex).unwrap_or_else(|| "#000000".to_string());
+    let palettes: Vec<Vec<String>> = (0..16)
+        .map(|block| {
+            (0..16)
+                .map(|entry| match palette.get(block * 16 + entry) {
+                    Some(rgb) if entry > 0 => to_hex(rgb),
+                    _ => color0.clone(),
+                })
+                .collect()
+        })
+        .collect();
+
+    // Index 0 of any block is the shared color 0, the other indices of a tile must share one block
+    let mut tile_palette_map = vec![0; total_tiles];
+    let mut empty_tiles = vec![true; total_tiles];
+    let mut used_entries = vec![[false; 16]; 16];
+    for tile_idx in 0..total_tiles {
+        let tile_x = (tile_idx % tiles_x as usize) as u32;
+        let tile_y = (tile_idx / tiles_x as usize) as u32;
+        let mut block = None;
+        for y in 0..8 {
+            for x in 0..8 {
+                let index = index_map.get_pixel(tile_x * 8 + x, tile_y * 8 + y).0[0] as usize;
+                if index.is_multiple_of(16) {
+                    continue;
+                }
+                match block {
+                    Some(first) if first != index / 16 => {
+                        return Err(format!(
+                            "La tuile {}, {} mélange les blocs de palette {} et {}",
+                            tile_x,
+                            tile_y,
+                            first,
+                            index / 16
+                        ));
+                    }
+                    _ => block = Some(index / 16),
+                }
+                used_entries[index / 16][index % 16] = true;
+            }
+        }
+        if let Some(block) = block {
+            tile_palette_map[tile_idx] = block;
+            empty_tiles[tile_idx] = false;
+        }
+    }
+
+    let palette_colors: Vec<Vec<String>> = palettes
+        .iter()
+        .zip(used_entries.iter())
+        .map(|(colors, used)| {
+            let mut used_colors = vec![color0.clone()];
+            used_colors.extend((1..16).filter(|&entry| used[entry]).map(|entry| colors[entry].clone()));
+            used_colors
+        })
+        .collect();
+
+    let pixel_indices: Vec<u8> = index_map.pixels().map(|pixel| pixel.0[0] % 16).collect();
+    let preview = RgbaImage::from_fn(width, height, |x, y| {
+        let index = index_map.get_pixel(x, y).0[0] as usize;
+        parse_hex_color(&palettes[index / 16][index % 16]).unwrap_or(Rgba([0, 0, 0, 255]))
+    });
+    Ok((
+        TilePaletteResult {
+            palettes,
+            tile_palette_map,
+            palette_colors,
+            empty_tiles,
+        },
+        preview,
+        pixel_indices,
+    ))
+}
+
+/// Apply the tile palettes with dithering, scaled by the mask if any (black = full, white = none)
+fn apply_palettes_with_mask(
+    image: &RgbaImage,
+    palette_result: &TilePaletteResult,
+    dither: &DitherOptions,
+    mask: Option<&[u8]>,
+    residuals: Option<&[[f32; 3]]>,
+) -> Result<RgbaImage, String> {
+    let strength_map = mask.map(|mask| {
+        mask.iter().map(|&value| (255 - value) as f32 / 255.0).collect::<Vec<f32>>()
+    });
+    apply_tile_palettes_with_dither(image, palette_result, dither, strength_map.as_deref(), residuals)
+}
+
+/// Output size and placement of the source, shared by the conversion commands
+#[derive(Deserialize, Clone)]
+struct TargetSettings {
+    width: u32,
+    height: u32,
+    pixel_aspect: f32,  // Displayed width / height of an output pixel (1.0 = square, video mode presets)
+    resize_method: String,
+    keep_ratio: bool,
+    background_color: String,
+    curve_lut: Vec<u8>,
+    grid_align: String,  // Grid alignment search: "none", "tiles" (fewest unique tiles) or "colors" (least color overflow)
+    grid_align_step: u32,  // Step between tried offsets in pixels: 1 (64 offsets), 2 (16) or 4 (4)
+    linear_light: bool,  // Resize and diffuse the dithering error in linear light
+}
+
+/// BG palette building settings
+#[derive(Deserialize, Clone)]
+struct PaletteSettings {
+    count: u8,  // Palettes to use (1-16)
+    group_constraints: Vec<i32>,  // -1 = auto, 0-15 = forced group
+    seed: u64,  // Seed for deterministic palette clustering
+    band_rows: u32,  // Build palettes per band of N tile rows (0 = one set for the whole image)
+    max_band_changes: usize,  // Max palette entries reloaded between two bands
+}
+
+/// Dither mask painted over the source (black = full dithering, white = none)
+#[derive(Deserialize, Clone)]
+struct DitherMask {
+    values: Vec<u8>,
+    width: u32,
+    height: u32,
+}
+
+/// Passes that lower the unique tile count after dithering
+#[derive(Deserialize, Clone)]
+struct TileReductionSettings {
+    mode: String,  // "none", "threshold" or "max_tiles"
+    threshold: f32,  // Max RMS error per pixel for "threshold"
+    max_unique_tiles: usize,  // Tile budget for "max_tiles"
+    pattern_reuse_tolerance: f32,  // Extra RMS error accepted to reuse an existing tile pattern (0 = off)
+}
+
+/// Modes that go past one layer of BG tiles, and the modes that keep the source colors
+#[derive(Deserialize, Clone)]
+struct ConversionModes {
+    flicker: bool,  // Produce two alternating frames whose average approximates the source
+    raster_bands: String,  // Raster split bands, one "start_line,scroll_x,scroll_y[,bat_x,bat_y]" per line
+    sgx_mode: String,  // SuperGrafx layers: "none", "image" (second image), "alpha" or "color" split
+    sgx_layer_path: String,  // Background layer image for "image"
+    sgx_split_colors: String,  // Background colors for "color", comma separated hex
+    sprite_overlay: bool,  // Add 16x16 sprites with extra colors over the worst background cells
+    max_overlay_sprites: usize,  // Sprite budget for the overlay (at most 64)
+    lossless: bool,  // Art already follows the PCE rules: validate it and keep it pixel-identical
+    indexed_palettes: bool,  // Indexed source: palette block N is PCE palette N, indices are kept as they are
+}
+
+/// Settings panel of the BG conversion (see getConversionSettings in main.js)
+#[derive(Deserialize, Clone)]
+struct ConversionSettings {
+    target: TargetSettings,
+    palettes: PaletteSettings,
+    dither: DitherOptions,
+    dither_mask: Option<DitherMask>,
+    reduction: TileReductionSettings,
+    modes: ConversionModes,
+}
+
+/// Source placed on the target, curved and split into layers, ready for the palette stage
+struct PreparedSource {
+    palette_group_constraints: Vec<i32>,  // Shifted with the grid alignment
+    was_pre_resized: bool,
+    grid_offset: Option<(i32, i32)>,
+    pixel_grid: Option<PixelGrid>,
+    warnings: Vec<String>,
+    curved: RgbaImage,  // Main layer: whole image, SuperGrafx VDC1 layer or flicker frame A
+    residuals: Option<Vec<[f32; 3]>>,  // 16-bit sources: precision below the 8-bit values
+    quantized_for_palette: RgbaImage,  // Undithered RGB333 colors, both SuperGrafx layers stacked
+    indexed_result: Option<TilePaletteResult>,  // Indexed palettes: palettes given by the source
+    pixel_indices: Vec<u8>,  // Indexed palettes: color index of every pixel
+    mask: Option<Vec<u8>>,  // Dither mask resized to the target
+    sgx: Option<(RgbaImage, Vec<bool>)>,  // SuperGrafx VDC2 layer and foreground coverage
+    flicker_frame: Option<RgbaImage>,  // Flicker frame B
+    reference: RgbaImage,  // What the layers should show together, for the error metrics
+}
+
+/// BG layer after the dithering, pattern reuse and tile reduction stages
+struct TileLayer {
+    palette_result: TilePaletteResult,
+    preview: RgbaImage,
+    unique_tiles: Vec<[u8; 32]>,
+    tile_to_unique: Vec<usize>,
+    reused_tile_count: usize,
+    merged_tile_count: usize,
+}
+
+/// Layers of a conversion with a given palette count
+struct ConvertedLayers {
+    main: TileLayer,
+    palette_band_rows: u32,  // Band height actually used (0 = one set for the whole image)
+    band_palette_changes: Vec<usize>,
+    flicker: Option<TileLayer>,  // Flicker frame B, with its own palettes
+    sgx: Option<TileLayer>,  // SuperGrafx VDC2 layer, with the palettes shared with VDC1
+}
+
+fn emit_progress(app: Option<&AppHandle>, percent: u8, stage: &str) {
+    if let Some(app) = app {
+        let _ = app.emit("conversion-progress", ProgressEvent {
+            percent,
+            stage: stage.to_string(),
+        });
+    }
+}
+
+#[tauri::command]
+fn run_conversion(
+    app: AppHandle,
+    input_path: String,
+    source_transform: SourceTransform,  // Crop, scale and pan of the source
+    settings: ConversionSettings,
+) -> Result<ConversionResult, String> {
+    // Check band declarations before the conversion work
+    let bands = parse_raster_bands(&settings.modes.raster_bands, settings.target.height)?;
+    let prepared = prepare_source(&app, &input_path, &source_transform, &settings)?;
+    let layers = convert_layers(Some(&app), &prepared, &settings, settings.palettes.count)?;
+    let PreparedSource { was_pre_resized, grid_offset, pixel_grid, mut warnings, curved, pixel_indices, .. } = prepared;
+    let ConvertedLayers { main, palette_band_rows, band_palette_changes, flicker, sgx } = layers;
+
+    let modes = &settings.modes;
+    let reduction = &settings.reduction;
+    if (modes.lossless || modes.indexed_palettes) && (reduction.pattern_reuse_tolerance > 0.0 || reduction.mode != "none") {
+        warnings.push(format!(
+            "{} : réutilisation des motifs et réduction des tuiles ignorées",
+            if modes.lossless { "Mode sans perte" } else { "Palettes indexées" }
+        ));
+    }
+
+    // Flicker mode: frame B was converted with the same settings
+    let flicker_result = match flicker {
+        Some(frame_b) => {
+            let shared_unique_tile_count = shared_unique_tile_count(&main.unique_tiles, &frame_b.unique_tiles);
+            let blended = blend_frames(&main.preview, &frame_b.preview);
+
+            Some(FlickerFrameResult {
+                preview_base64: encode_png_base64(frame_b.preview)?,
+                palettes: frame_b.palette_result.palettes,
+                tile_palette_map: frame_b.palette_result.tile_palette_map,
+                empty_tiles: frame_b.palette_result.empty_tiles,
+                unique_tile_count: frame_b.unique_tiles.len(),
+                tile_to_unique: frame_b.tile_to_unique,
+                blended_preview_base64: encode_png_base64(blended)?,
+                shared_unique_tile_count,
+            })
+        }
+        None => None,
+    };
+
+    // SuperGrafx mode: the VDC2 layer uses the shared palettes
+    let sgx_result = match sgx {
+        Some(background) => {
+            let composite = composite_layers(&main.preview, &background.preview, &main.palette_result);
+
+            Some(SgxLayerResult {
+                preview_base64: encode_png_base64(background.preview)?,
+                tile_palette_map: background.palette_result.tile_palette_map,
+                empty_tiles: background.palette_result.empty_tiles,
+                unique_tile_count: background.unique_tiles.len(),
+                tile_to_unique: background.tile_to_unique,
+                composite_preview_base64: encode_png_base64(composite)?,
+            })
+        }
+        None => None,
+    };
+
+    // Sprite overlay: extra colors for the cells the background tiles render worst
+    let sprite_overlay_result = if modes.sprite_overlay {
+        let _ = app.emit("conversion-progress", ProgressEvent {
+            percent: 87,
+            stage: "Sprites de couleur...".to_string(),
+        });
+        let color0 = main
+            .palette_result
+            .palettes
+            .first()
+            .and_then(|palette| palette.first())
+            .cloned()
+            .unwrap_or_else(|| settings.target.background_color.clone());
+        let (sprites, sprite_palettes) = build_sprite_overlay(&curved, &main.preview, modes.max_overlay_sprites, &color0);
+        let composite = composite_sprites(&main.preview, &sprites, &sprite_palettes);
+        Some(SpriteOverlayResult {
+            sprites,
+            palettes: sprite_palettes,
+            composite_preview_base64: encode_png_base64(composite)?,
+        })
+    } else {
+        None
+    };
+
+    let tiles_x = main.preview.width() / 8;
+    let total_tiles = (tiles_x * (main.preview.height() / 8)) as usize;
+    let raster_band_usage = raster_band_usage(&bands, &main.palette_result, &main.tile_to_unique, tiles_x);
+
+    // Emit: encoding
+    let _ = app.emit("conversion-progress", ProgressEvent {
+        percent: 90,
+        stage: "Encodage PNG...".to_string(),
+    });
+
+    let mut output = Vec::new();
+    DynamicImage::ImageRgba8(main.preview)
+        .write_to(&mut std::io::Cursor::new(&mut output), image::ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+
+    // Emit: done
+    let _ = app.emit("conversion-progress", ProgressEvent {
+        percent: 100,
+        stage: "Terminé!".to_string(),
+    });
+
+    Ok(ConversionResult {
+        preview_base64: base64::engine::general_purpose::STANDARD.encode(output),
+        palettes: main.palette_result.palettes,
+        tile_palette_map: main.palette_result.tile_palette_map,
+        empty_tiles: main.palette_result.empty_tiles,
+        tile_count: total_tiles,
+        unique_tile_count: main.unique_tiles.len(),
+        tile_to_unique: main.tile_to_unique,
+        was_pre_resized,
+        grid_offset,
+        pixel_grid,
+        warnings,
+        pixel_indices,
+        merged_tile_count: main.merged_tile_count,
+        reused_tile_count: main.reused_tile_count,
+        flicker: flicker_result,
+        palette_band_rows,
+        band_palette_changes,
+        raster_bands: raster_band_usage,
+        sgx: sgx_result,
+        sprite_overlay: sprite_overlay_result,
+    })
+}
+
+/// Load the source, place it on the target, curve it and split it into the layers of the
+/// SuperGrafx and flicker modes (shared by run_conversion and run_palette_sweep)
+fn prepare_source(
+    app: &AppHandle,
+    input_path: &str,
+    source_transform: &SourceTransform,
+    settings: &ConversionSettings,
+) -> Result<PreparedSource, String> {
+    let target = &settings.target;
+    let modes = &settings.modes;
+    let (target_width, target_height) = (target.width, target.height);
+    let (keep_ratio, pixel_aspect, linear_light) = (target.keep_ratio, target.pixel_aspect, target.linear_light);
+    let background_color = &target.background_color;
+    source_transform.validate()?;
+    // Both modes keep the source colors as they are
+    let exact_colors = modes.lossless || modes.indexed_palettes;
+    if exact_colors && (modes.flicker || modes.sgx_mode != "none" || modes.sprite_overlay) {
+        return Err("Les modes sans perte et palettes indexées ne sont pas compatibles avec le flicker, le mode SuperGrafx ni les sprites de couleur".to_string());
+    }
+    if modes.lossless && modes.indexed_palettes {
+        return Err("Choisir le mode sans perte ou le mode palettes indexées, pas les deux".to_string());
+    }
+    // Any filtering would add colors
+    let resize_method = if exact_colors { "nearest" } else { target.resize_method.as_str() };
+    if modes.sgx_mode != "none" && modes.flicker {
+        return Err("Le mode SuperGrafx n'est pas compatible avec le flicker".to_string());
+    }
+    if modes.sprite_overlay && (modes.flicker || modes.sgx_mode != "none") {
+        return Err("Les sprites de couleur ne sont pas compatibles avec le flicker ni le mode SuperGrafx".to_string());
+    }
+
+    // Emit: loading image
+    let _ = app.emit("conversion-progress", ProgressEvent {
+        percent: 5,
+        stage: "Chargement de l'image...".to_string(),
+    });
+
+    let (image, was_pre_resized, encoding_warning) =
+        load_source_image(app, input_path, target_width, target_height, source_transform, linear_light)?;
+    let (source_transform, mut warnings) =
+        prepare_pixel_art(&image, target_width, target_height, keep_ratio, pixel_aspect, source_transform);
+    warnings.extend(encoding_warning);
+    let pixel_grid = source_transform.pixel_grid;
+
+    // Shift the 8x8 grid over the art before anything is resized or built
+    if target.grid_align != "none" {
+        let _ = app.emit("conversion-progress", ProgressEvent {
+            percent: 12,
+            stage: "Recherche de l'alignement de la grille...".to_string(),
+        });
+    }
+    let (source_transform, palette_group_constraints, grid_offset) = align_grid(
+        &image,
+        target_width,
+        target_height,
+        resize_method,
+        keep_ratio,
+        background_color,
+        pixel_aspect,
+        source_transform,
+        settings.palettes.group_constraints.clone(),
+        &target.curve_lut,
+        &target.grid_align,
+        target.grid_align_step,
+        linear_light,
+    )?;
+
+    // SuperGrafx: the foreground (VDC1) covers the opaque pixels of the source
+    let sgx_alpha_coverage = if modes.sgx_mode == "image" || modes.sgx_mode == "alpha" {
+        Some(alpha_coverage(&image, target_width, target_height, resize_method, keep_ratio, pixel_aspect, &source_transform)?)
+    } else {
+        None
+    };
+    // The alpha split keeps the colors behind transparent pixels for the background layer
+    let image = if modes.sgx_mode == "alpha" {
+        let mut opaque = image.to_rgba8();
+        opaque.pixels_mut().for_each(|pixel| pixel.0[3] = 255);
+        DynamicImage::ImageRgba8(opaque)
+    } else {
+        image
+    };
+
+    // Emit: resizing
+    let _ = app.emit("conversion-progress", ProgressEvent {
+        percent: 15,
+        stage: "Redimensionnement...".to_string(),
+    });
+
+    let resized = resize_to_target(
+        image,
+        target_width,
+        target_height,
+        resize_method,
+        keep_ratio,
+        background_color,
+        pixel_aspect,
+        &source_transform,
+        linear_light,
+    )?;
+
+    // Indexed palettes: the palette indices of the source give the palettes and the colors
+    let (indexed_result, indexed_preview, pixel_indices) = if modes.indexed_palettes {
+        let source = read_indexed_image(input_path, &source_transform)?
+            .ok_or("L'image source n'a pas de palette (PNG, GIF, BMP, PCX ou Aseprite indexé attendu)")?;
+        let index_map = place_indexed_source(&source, target_width, target_height, keep_ratio, pixel_aspect, &source_transform)?;
+        let (palette_result, preview, pixel_indices) = indexed_tile_palettes(&index_map, &source.palette)?;
+        (Some(palette_result), Some(preview), pixel_indices)
+    } else {
+        (None, None, Vec::new())
+    };
+
+    // Emit: applying curve
+    let _ = app.emit("conversion-progress", ProgressEvent {
+        percent: 25,
+        stage: "Application de la courbe...".to_string(),
+    });
+
+    // Apply curve LUT to adjust color levels before quantization (the lossless path keeps the
+    // colors as they are and stops on the first art that breaks the rules)
+    let curved = if let Some(preview) = indexed_preview {
+        preview
+    } else if modes.lossless {
+        let source = resized.to_rgba8();
+        let violations = validate_pce_tiles(&source, background_color);
+        if !violations.is_empty() {
+            return Err(format!(
+                "{} tuiles ne respectent pas les contraintes PCE (première : tuile {}, {} : {})",
+                violations.len(),
+                violations[0].tile_x,
+                violations[0].tile_y,
+                violations[0].messages.join(", ")
+            ));
+        }
+        source
+    } else {
+        apply_curve_lut(&resized.to_rgba8(), &target.curve_lut)
+    };
+    // 16-bit sources: the precision below the 8-bit values goes on to the RGB333 quantization
+    // (not with SuperGrafx layers and flicker frames, which change the pixels)
+    let residuals = if exact_colors || modes.sgx_mode != "none" || modes.flicker {
+        None
+    } else {
+        precision_residuals(&resized, &target.curve_lut)
+    };
+
+    // SuperGrafx mode: the VDC1 layer follows the normal pipeline, the VDC2 layer is converted at the end
+    let (curved, sgx, reference) = if modes.sgx_mode != "none" {
+        let background = if modes.sgx_mode == "image" {
+            let (layer, _, _) = load_source_image(app, &modes.sgx_layer_path, target_width, target_height, &source_transform, linear_light)?;
+            let layer = resize_to_target(layer, target_width, target_height, resize_method, keep_ratio, background_color, pixel_aspect, &source_transform, linear_light)?;
+            Some(apply_curve_lut(&layer.to_rgba8(), &target.curve_lut))
+        } else {
+            None
+        };
+        let coverage = match sgx_alpha_coverage {
+            Some(coverage) => coverage,
+            None => color_coverage(&curved, &modes.sgx_split_colors)?,
+        };
+        let (foreground, background) = split_sgx_layers(&curved, background, &coverage, background_color);
+        let reference = RgbaImage::from_fn(target_width, target_height, |x, y| {
+            let covered = coverage.get((y * target_width + x) as usize).copied().unwrap_or(false);
+            *if covered { &foreground } else { &background }.get_pixel(x, y)
+        });
+        (foreground, Some((background, coverage)), reference)
+    } else {
+        let reference = curved.clone();
+        (curved, None, reference)
+    };
+
+    // Flicker mode: the first frame follows the normal pipeline, the second one is converted at the end
+    let (curved, flicker_frame) = if modes.flicker {
+        let (frame_a, frame_b) = split_flicker_frames(&curved);
+        (frame_a, Some(frame_b))
+    } else {
+        (curved, None)
+    };
+
+    // Emit: quantization
+    let _ = app.emit("conversion-progress", ProgressEvent {
+        percent: 35,
+        stage: "Quantification RGB333...".to_string(),
+    });
+
+    // First pass: quantize to RGB333 WITHOUT dithering to build palettes
+    let palette_count = settings.palettes.count;
+    let mut quantized_for_palette = quantize_rgb333(DynamicImage::ImageRgba8(curved.clone()), palette_count, "none", background_color)?;
+    if let Some(residuals) = &residuals {
+        requantize_with_residuals(&mut quantized_for_palette, &curved, residuals);
+    }
+
+    // Both SuperGrafx layers share the VCE's BG palettes: build them from the layers stacked
+    let quantized_for_palette = match &sgx {
+        Some((background, _)) => {
+            let quantized_background = quantize_rgb333(
+                DynamicImage::ImageRgba8(background.clone()),
+                palette_count,
+                "none",
+                background_color,
+            )?;
+            stack_layers(&quantized_for_palette, &quantized_background)
+        }
+        None => quantized_for_palette,
+    };
+
+    // Resize mask to target dimensions (using same keep_ratio logic as image)
+    let mask = settings
+        .dither_mask
+        .as_ref()
+        .filter(|mask| !mask.values.is_empty())
+        .map(|mask| resize_mask(&mask.values, mask.width, mask.height, target_width, target_height, keep_ratio, pixel_aspect, &source_transform));
+
+    Ok(PreparedSource {
+        palette_group_constraints,
+        was_pre_resized,
+        grid_offset,
+        pixel_grid,
+        warnings,
+        curved,
+        residuals,
+        quantized_for_palette,
+        indexed_result,
+        pixel_indices,
+        mask,
+        sgx,
+        flicker_frame,
+        reference,
+    })
+}
+
+/// Build the palettes of a prepared source and convert its layers to tiles (shared by
+/// run_conversion and run_palette_sweep, which passes no app to stay silent)
+fn convert_layers(
+    app: Option<&AppHandle>,
+    prepared: &PreparedSource,
+    settings: &ConversionSettings,
+    palette_count: u8,
+) -> Result<ConvertedLayers, String> {
+    let target = &settings.target;
+    let modes = &settings.modes;
+    let palettes = &settings.palettes;
+    let background_color = &target.background_color;
+    let exact_colors = modes.lossless || modes.indexed_palettes;
+
+    // Emit: palette building
+    emit_progress(app, 50, "Construction des palettes...");
+
+    // Bands only make sense when the image is taller than one band (and not with stacked layers)
+    let palette_band_rows = if palettes.band_rows < target.height / 8 && prepared.sgx.is_none() && !exact_colors {
+        palettes.band_rows
+    } else {
+        0
+    };
+    let (palette_result, band_palette_changes) = if let Some(palette_result) = &prepared.indexed_result {
+        (palette_result.clone(), Vec::new())
+    } else if modes.lossless {
+        let palette_result = exact_tile_palettes(
+            &prepared.quantized_for_palette,
+            palette_count as usize,
+            background_color,
+            &prepared.palette_group_constraints,
+        )?;
+        (palette_result, Vec::new())
+    } else {
+        build_frame_palettes(
+            &prepared.quantized_for_palette,
+            palette_count as usize,
+            background_color,
+            &prepared.palette_group_constraints,
+            palettes.seed,
+            palette_band_rows,
+            palettes.max_band_changes,
+        )?
+    };
+    let (palette_result, sgx_palette_result) = if prepared.sgx.is_some() {
+        let (foreground, background) = split_palette_result(palette_result, ((target.width / 8) * (target.height / 8)) as usize);
+        (foreground, Some(background))
+    } else {
+        (palette_result, None)
+    };
+
+    // Emit: applying palettes with dithering
+    emit_progress(app, 70, "Application des palettes...");
+
+    // Lossless and indexed palettes: every tile color is in its palette, the art is the result
+    let exact = exact_colors.then_some((&prepared.quantized_for_palette, prepared.pixel_indices.as_slice()));
+    let main = convert_tile_layer(
+        app,
+        &prepared.curved,
+        palette_result,
+        settings,
+        prepared.mask.as_deref(),
+        prepared.residuals.as_deref(),
+        prepared.sgx.as_ref().map(|(_, coverage)| coverage.as_slice()),
+        exact,
+    )?;
+
+    // Flicker mode: convert the second frame with the same settings
+    let flicker = match &prepared.flicker_frame {
+        Some(frame_b) => {
+            emit_progress(app, 85, "Conversion de l'image B (flicker)...");
+
+            let quantized_b = quantize_rgb333(DynamicImage::ImageRgba8(frame_b.clone()), palette_count, "none", background_color)?;
+            let (palette_result_b, _) = build_frame_palettes(
+                &quantized_b,
+                palette_count as usize,
+                background_color,
+                &prepared.palette_group_constraints,
+                palettes.seed,
+                palette_band_rows,
+                palettes.max_band_changes,
+            )?;
+            Some(convert_tile_layer(None, frame_b, palette_result_b, settings, prepared.mask.as_deref(), None, None, None)?)
+        }
+        None => None,
+    };
+
+    // SuperGrafx mode: convert the VDC2 layer with the shared palettes
+    let sgx = match (&prepared.sgx, sgx_palette_result) {
+        (Some((background, _)), Some(palette_result_bg)) => {
+            emit_progress(app, 85, "Conversion du plan VDC2 (SuperGrafx)...");
+
+            Some(convert_tile_layer(None, background, palette_result_bg, settings, prepared.mask.as_deref(), None, None, None)?)
+        }
+        _ => None,
+    };
+
+    Ok(ConvertedLayers {
+        main,
+        palette_band_rows,
+        band_palette_changes,
+        flicker,
+        sgx,
+    })
+}
+
+/// Dither a layer with its palettes, then dedupe (and optionally reduce) its tiles.
+/// coverage: SuperGrafx VDC1 pixels that are not transparent, kept as color 0 through the passes.
+/// exact: exact color modes, the art as it is and its pixel indices (no dithering nor passes).
+#[allow(clippy::too_many_arguments)]
+fn convert_tile_layer(
+    app: Option<&AppHandle>,
+    source: &RgbaImage,
+    palette_result: TilePaletteResult,
+    settings: &ConversionSettings,
+    mask: Option<&[u8]>,
+    residuals: Option<&[[f32; 3]]>,
+    coverage: Option<&[bool]>,
+    exact: Option<(&RgbaImage, &[u8])>,
+) -> Result<TileLayer, String> {
+    let reduction = &settings.reduction;
+    let dither = DitherOptions { linear_light: settings.target.linear_light, ..settings.dither.clone() };
+    let pixel_indices = exact.map(|(_, indices)| indices).unwrap_or(&[]);
+    let mut preview = match exact {
+        Some((art, _)) => art.clone(),
+        None => apply_palettes_with_mask(source, &palette_result, &dither, mask, residuals)?,
+    };
+
+    // Optional dedup-friendly pass: replace dithered tiles by already used patterns
+    let mut reused_tile_count = 0;
+    if reduction.pattern_reuse_tolerance > 0.0 && exact.is_none() {
+        emit_progress(app, 75, "Réutilisation des motifs...");
+        reused_tile_count =
+            reuse_tile_patterns(source, &mut preview, &palette_result, reduction.pattern_reuse_tolerance, coverage);
+    }
+
+    let (mut unique_tiles, mut tile_to_unique) = build_unique_tiles(
+        &preview,
+        &palette_result.palettes,
+        &palette_result.tile_palette_map,
+        &palette_result.empty_tiles,
+        pixel_indices,
+    );
+
+    // Optional lossy pass: merge near-identical tiles to fit the VRAM budget
+    let mut merged_tile_count = 0;
+    if reduction.mode != "none" && exact.is_none() {
+        emit_progress(app, 80, "Réduction des tuiles...");
+        merged_tile_count = reduce_unique_tiles(
+            app,
+            source,
+            &mut preview,
+            &palette_result,
+            &mut unique_tiles,
+            &mut tile_to_unique,
+            &reduction.mode,
+            reduction.threshold,
+            reduction.max_unique_tiles,
+            coverage,
+        );
+    }
+
+    // Put back color 0 where dithering changed it, after the passes so they cannot repaint it
+    if let Some(coverage) = coverage {
+        keep_transparent_pixels(coverage, &mut preview, &palette_result, &settings.target.background_color);
+        (unique_tiles, tile_to_unique) = build_unique_tiles(
+            &preview,
+            &palette_result.palettes,
+            &palette_result.tile_palette_map,
+            &palette_result.empty_tiles,
+            pixel_indices,
+        );
+    }
+
+    Ok(TileLayer {
+        palette_result,
+        preview,
+        unique_tiles,
+        tile_to_unique,
+        reused_tile_count,
+        merged_tile_count,
+    })
+}
+
+/// Unique tiles of two layers sharing the VRAM (the flicker frames)
+fn shared_unique_tile_count(tiles_a: &[[u8; 32]], tiles_b: &[[u8; 32]]) -> usize {
+    tiles_a
+        .iter()
+        .chain(tiles_b.iter())
+        .collect::<std::collections::HashSet<_>>()
+        .len()
+}
+
+/// Split an image into two RGB333 frames whose average approximates it: each channel is
+/// rounded down in one frame and up in the other when it falls between two levels,
+/// alternating in a checkerboard so each frame keeps an even brightness
+fn split_flicker_frames(image: &RgbaImage) -> (RgbaImage, RgbaImage) {
+    let (width, height) = image.dimensions();
+    let mut frame_a = RgbaImage::new(width, height);
+    let mut frame_b = RgbaImage::new(width, height);
+    let to_byte = |level: f32| (level * RGB333_STEP).round().clamp(0.0, 255.0) as u8;
+
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let mut a = pixel.0;
+        let mut b = pixel.0;
+        let checker = (x + y) % 2 == 0;
+        for c in 0..3 {
+            let level = pixel.0[c] as f32 / RGB333_STEP;
+            let low = level.floor();
+            let frac = level - low;
+            let (level_a, level_b) = if frac < 0.25 {
+                (low, low)
+            } else if frac > 0.75 {
+                (low + 1.0, low + 1.0)
+            } else if checker {
+                (low + 1.0, low)
+            } else {
+                (low, low + 1.0)
+            };
+            a[c] = to_byte(level_a);
+            b[c] = to_byte(level_b);
+        }
+        frame_a.put_pixel(x, y, Rgba(a));
+        frame_b.put_pixel(x, y, Rgba(b));
+    }
+
+    (frame_a, frame_b)
+}
+
+/// Pixel average of two frames
+fn blend_frames(frame_a: &RgbaImage, frame_b: &RgbaImage) -> RgbaImage {
+    let (width, height) = frame_a.dimensions();
+    RgbaImage::from_fn(width, height, |x, y| {
+        let a = frame_a.get_pixel(x, y).0;
+        let b = frame_b.get_pixel(x, y).0;
+        Rgba([
+            (a[0] as u16 + b[0] as u16).div_ceil(2) as u8,
+            (a[1] as u16 + b[1] as u16).div_ceil(2) as u8,
+            (a[2] as u16 + b[2] as u16).div_ceil(2) as u8,
+            a[3].max(b[3]),
+        ])
+    })
+}
+
+fn encode_png_base64(image: RgbaImage) -> Result<String, String> {
+    let mut output = Vec::new();
+    DynamicImage::ImageRgba8(image)
+        .write_to(&mut std::io::Cursor::new(&mut output), image::ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+    Ok(base64::engine::general_purpose::STANDARD.encode(output))
+}
+
+/// SuperGrafx VPC registers: priority ($0008), window 1 and window 2 widths ($000A, $000C).
+/// $3333 enables both VDCs in the four window regions with VDC1 in front, so windows are unused.
+const SGX_VPC_REGISTERS: [u16; 3] = [0x3333, 0x0000, 0x0000];
+
+/// Max RGB distance for a pixel to match a background color of the SuperGrafx color split
+const SGX_SPLIT_COLOR_TOLERANCE: f32 = 24.0;
+
+/// Foreground coverage (alpha >= 128) of the source, resized like the image itself
+fn alpha_coverage(
+    image: &DynamicImage,
+    width: u32,
+    height: u32,
+    method: &str,
+    keep_ratio: bool,
+    pixel_aspect: f32,
+    transform: &SourceTransform,
+) -> Result<Vec<bool>, String> {
+    let rgba = image.to_rgba8();
+    let alpha = RgbaImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let a = rgba.get_pixel(x, y).0[3];
+        Rgba([a, a, a, 255])
+    });
+    // Letterbox borders are background
+    let resized = resize_to_target(DynamicImage::ImageRgba8(alpha), width, height, method, keep_ratio, "#000000", pixel_aspect, transform, false)?;
+    Ok(resized.to_rgba8().pixels().map(|pixel| pixel.0[0] >= 128).collect())
+}
+
+/// Foreground coverage of a color split: pixels far from every listed background color
+fn color_coverage(image: &RgbaImage, split_colors: &str) -> Result<Vec<bool>, String> {
+    let colors = split_colors
+        .split(',')
+        .map(|c| c.trim())
+        .filter(|c| !c.is_empty())
+        .map(|c| parse_hex_color(c).ok_or(format!("Couleur de séparation invalide : {}", c)))
+        .collect::<Result<Vec<_>, _>>()?;
+    if colors.is_empty() {
+        return Err("Aucune couleur de séparation pour le plan VDC2".to_string());
+    }
+    Ok(image
+        .pixels()
+        .map(|pixel| {
+            colors.iter().all(|color| {
+                let distance: f32 = (0..3)
+                    .map(|c| (pixel.0[c] as f32 - color.0[c] as f32).powi(2))
+                    .sum();
+                distance.sqrt() > SGX_SPLIT_COLOR_TOLERANCE
+            })
+        })
+        .collect())
+}
+
+/// Split an image into the SuperGrafx VDC1 (foreground) and VDC2 (background) layers.
+/// Pixels outside the foreground take the background color (color 0, transparent on VDC1).
+/// Without a separate background layer, it gets the uncovered pixels, the rest being hidden.
+fn split_sgx_layers(
+    image: &RgbaImage,
+    background: Option<RgbaImage>,
+    coverage: &[bool],
+    background_color: &str,
+) -> (RgbaImage, RgbaImage) {
+    let bg = parse_hex_color(background_color).unwrap_or(Rgba([0, 0, 0, 255]));
+    let (width, height) = image.dimensions();
+    let covered = |x: u32, y: u32| coverage.get((y * width + x) as usize).copied().unwrap_or(false);
+
+    let foreground = RgbaImage::from_fn(width, height, |x, y| if covered(x, y) { *image.get_pixel(x, y) } else { bg });
+    let background = background.unwrap_or_else(|| {
+        RgbaImage::from_fn(width, height, |x, y| if covered(x, y) { bg } else { *image.get_pixel(x, y) })
+    });
+    (foreground, background)
+}
+
+/// Stack two layers vertically so their palettes are built together
+fn stack_layers(top: &RgbaImage, bottom: &RgbaImage) -> RgbaImage {
+    let mut stacked = RgbaImage::new(top.width(), top.height() + bottom.height());
+    image::imageops::replace(&mut stacked, top, 0, 0);
+    image::imageops::replace(&mut stacked, bottom, 0, top.height() as i64);
+    stacked
+}
+
+/// Split palettes built on stacked layers: the first `tile_count` tiles belong to the top layer
+fn split_palette_result(result: TilePaletteResult, tile_count: usize) -> (TilePaletteResult, TilePaletteResult) {
+    let split = tile_count.min(result.tile_palette_map.len());
+    let top = TilePaletteResult {
+        palettes: result.palettes.clone(),
+        tile_palette_map: result.tile_palette_map[..split].to_vec(),
+        palette_colors: result.palette_colors.clone(),
+        empty_tiles: result.empty_tiles[..split].to_vec(),
+    };
+    let bottom = TilePaletteResult {
+        palettes: result.palettes,
+        tile_palette_map: result.tile_palette_map[split..].to_vec(),
+        palette_colors: result.palette_colors,
+        empty_tiles: result.empty_tiles[split..].to_vec(),
+    };
+    (top, bottom)
+}
+
+/// Put back color 0 on the pixels outside the foreground coverage, which dithering may have
+/// changed, so the VDC2 layer shows through
+fn keep_transparent_pixels(
+    coverage: &[bool],
+    preview: &mut RgbaImage,
+    palette_result: &TilePaletteResult,
+    background_color: &str,
+) {
+    let bg = parse_hex_color(background_color).unwrap_or(Rgba([0, 0, 0, 255]));
+    let color0 = palette_result
+        .palettes
+        .first()
+        .and_then(|palette| palette.first())
+        .and_then(|color| parse_hex_color(color))
+        .unwrap_or(bg);
+    // Opaque foreground pixels of the background color stay as dithered
+    for (pixel, &covered) in preview.pixels_mut().zip(coverage) {
+        if !covered {
+            *pixel = color0;
+        }
+    }
+}
+
+/// Hardware sprite limits: sprites in the SATB and 16-pixel wide sprites on one scanline
+const SPRITE_LIMIT: usize = 64;
+const SPRITES_PER_LINE: usize = 16;
+/// Min RGB distance between the source and the background for a pixel to get sprite detail
+const OVERLAY_MIN_PIXEL_ERROR: f32 = 24.0;
+
+/// 16x16 sprite of the color boost overlay: indices in its sprite palette (0 = transparent)
+#[derive(Serialize, Deserialize, Clone)]
+struct OverlaySprite {
+    x: u32,
+    y: u32,
+    palette: usize,  // Sprite palette 0-15 (VCE $100 + palette * 16)
+    pixels: Vec<u8>,  // 256 indices, row-major
+}
+
+/// Sprites added over the background where its tiles lack colors
+#[derive(Serialize)]
+struct SpriteOverlayResult {
+    sprites: Vec<OverlaySprite>,
+    palettes: Vec<Vec<String>>,  // Sprite palettes, index 0 (transparent) holds color0
+    composite_preview_base64: String,  // Background with the sprites drawn over it
+}
+
+/// Put extra colors into 16x16 sprites over the background cells with the highest error.
+/// Cells are taken by decreasing error within the sprite count and per-scanline limits, their
+/// colors packed into up to 16 sprite palettes of 15 colors. A sprite pixel is opaque only where
+/// it is closer to the source than the background. Returns the sprites and their palettes.
+fn build_sprite_overlay(
+    source: &RgbaImage,
+    background: &RgbaImage,
+    max_sprites: usize,
+    color0: &str,
+) -> (Vec<OverlaySprite>, Vec<Vec<String>>) {
+    use std::collections::BTreeMap;
+
+    let (width, height) = background.dimensions();
+    let map = Rgb333Map { levels: 8 };
+    let distance = |a: &Rgba<u8>, b: &Rgba<u8>| -> f32 {
+        (0..3).map(|c| (a.0[c] as f32 - b.0[c] as f32).powi(2)).sum::<f32>().sqrt()
+    };
+    let quantized = |pixel: &Rgba<u8>| {
+        let mut color = *pixel;
+        map.map_color(&mut color);
+        color
+    };
+
+    // Background error and wanted colors per 16x16 cell
+    let mut cells = Vec::new();
+    for cell_y in (0..height).step_by(16) {
+        for cell_x in (0..width).step_by(16) {
+            let mut error = 0.0;
+            // Ordered map: the colors reach pack_sprite_palettes in the same order on every run
+            let mut colors: BTreeMap<[u8; 3], f32> = BTreeMap::new();
+            for y in cell_y..(cell_y + 16).min(height) {
+                for x in cell_x..(cell_x + 16).min(width) {
+                    let wanted = source.get_pixel(x, y);
+                    let pixel_error = distance(wanted, background.get_pixel(x, y));
+                    if pixel_error > OVERLAY_MIN_PIXEL_ERROR {
+                        error += pixel_error;
+                        let color = quantized(wanted);
+                        *colors.entry([color.0[0], color.0[1], color.0[2]]).or_insert(0.0) += pixel_error;
+                    }
+                }
+            }
+            if error > 0.0 {
+                cells.push((cell_x, cell_y, error, colors));
+            }
+        }
+    }
+    cells.sort_by(|a, b| b.2.total_cmp(&a.2));
+
+    // Take the worst cells within the sprite and scanline limits
+    let mut line_counts = vec![0usize; height as usize];
+    let mut selected = Vec::new();
+    for cell in cells {
+        if selected.len() >= max_sprites.min(SPRITE_LIMIT) {
+            break;
+        }
+        let lines = cell.1 as usize..(cell.1 as usize + 16).min(height as usize);
+        if line_counts[lines.clone()].iter().any(|&count| count >= SPRITES_PER_LINE) {
+            continue;
+        }
+        line_counts[lines].iter_mut().for_each(|count| *count += 1);
+        selected.push(cell);
+    }
+
+    // Pack the cell colors into sprite palettes (15 colors + transparent)
+    let color_sets: Vec<Vec<([u8; 3], f32)>> = selected
+        .iter()
+        .map(|(_, _, _, colors)| colors.iter().map(|(c, w)| (*c, *w)).collect())
+        .collect();
+    let (palettes, cell_palettes) = pack_sprite_palettes(&color_sets, 16);
+
+    // Sprite pixels: nearest palette color where it beats the background
+    let mut sprites = Vec::new();
+    for ((cell_x, cell_y, _, _), palette_index) in selected.iter().zip(cell_palettes) {
+        let palette = &palettes[palette_index];
+        let mut pixels = vec![0u8; 256];
+        for y in *cell_y..(*cell_y + 16).min(height) {
+            for x in *cell_x..(*cell_x + 16).min(width) {
+                let wanted = source.get_pixel(x, y);
+                let background_error = distance(wanted, background.get_pixel(x, y));
+                if background_error <= OVERLAY_MIN_PIXEL_ERROR {
+                    continue;
+                }
+                let nearest = palette
+                    .iter()
+                    .enumerate()
+                    .map(|(index, c)| (index, distance(wanted, &Rgba([c[0], c[1], c[2], 255]))))
+                    .min_by(|a, b| a.1.total_cmp(&b.1));
+                if let Some((index, error)) = nearest {
+                    if error < background_error {
+                        pixels[((y - cell_y) * 16 + (x - cell_x)) as usize] = index as u8 + 1;
+                    }
+                }
+            }
+        }
+        if pixels.iter().any(|&index| index > 0) {
+            sprites.push(OverlaySprite { x: *cell_x, y: *cell_y, palette: palette_index, pixels });
+        }
+    }
+
+    (sprites, sprite_palette_strings(&palettes, color0))
+}
+
+/// Pack weighted color sets (one per sprite) into at most `max_palettes` sprite palettes of
+/// 15 colors: each set keeps its 15 heaviest colors and joins the palette it adds the fewest
+/// colors to. When every palette is full, it takes the one already holding most of its colors.
+/// Returns the palettes and the palette of each set.
+fn pack_sprite_palettes(color_sets: &[Vec<([u8; 3], f32)>], max_palettes: usize) -> (Vec<Vec<[u8; 3]>>, Vec<usize>) {
+    let max_palettes = max_palettes.clamp(1, 16);
+    let mut palettes: Vec<Vec<[u8; 3]>> = Vec::new();
+    let mut set_palettes = Vec::with_capacity(color_sets.len());
+    for colors in color_sets {
+        let mut ranked = colors.clone();
+        // Equal weights are ranked by color so the palettes do not depend on the input order
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        ranked.truncate(15);
+
+        let missing = |palette: &Vec<[u8; 3]>| ranked.iter().filter(|(c, _)| !palette.contains(c)).count();
+        let fitting = palettes
+            .iter()
+            .enumerate()
+            .filter(|(_, palette)| palette.len() + missing(palette) <= 15)
+            .min_by_key(|(_, palette)| missing(palette))
+            .map(|(index, _)| index);
+        let index = match fitting {
+            Some(index) => index,
+            None if palettes.len() < max_palettes => {
+                palettes.push(Vec::new());
+                palettes.len() - 1
+            }
+            None => {
+                let covered = |palette: &Vec<[u8; 3]>| -> f32 {
+                    ranked.iter().filter(|(c, _)| palette.contains(c)).map(|(_, w)| w).sum()
+                };
+                (0..palettes.len()).max_by(|&a, &b| covered(&palettes[a]).total_cmp(&covered(&palettes[b]))).unwrap_or(0)
+            }
+        };
+        for (color, _) in &ranked {
+            if palettes[index].len() < 15 && !palettes[index].contains(color) {
+                palettes[index].push(*color);
+            }
+        }
+        set_palettes.push(index);
+    }
+    (palettes, set_palettes)
+}
+
+/// Sprite palettes as hex strings: color0 (transparent) first, padded to 16 entries with color0
+fn sprite_palette_strings(palettes: &[Vec<[u8; 3]>], color0: &str) -> Vec<Vec<String>> {
+    palettes
+        .iter()
+        .map(|colors| {
+            let mut palette = vec![color0.to_string()];
+            palette.extend(colors.iter().map(|c| format!("#{:02X}{:02X}{:02X}", c[0], c[1], c[2])));
+            palette.resize(16, color0.to_string());
+            palette
+        })
+        .collect()
+}
+
+/// Draw the overlay sprites over the background (index 0 is transparent)
+fn composite_sprites(background: &RgbaImage, sprites: &[OverlaySprite], palettes: &[Vec<String>]) -> RgbaImage {
+    let mut output = background.clone();
+    let (width, height) = output.dimensions();
+    for sprite in sprites {
+        let palette: Vec<Option<Rgba<u8>>> = palettes
+            .get(sprite.palette)
+            .map(|p| p.iter().map(|c| parse_hex_color(c)).collect())
+            .unwrap_or_default();
+        for (offset, &index) in sprite.pixels.iter().enumerate() {
+            let (x, y) = (sprite.x + offset as u32 % 16, sprite.y + offset as u32 / 16);
+            if index == 0 || x >= width || y >= height {
+                continue;
+            }
+            if let Some(Some(color)) = palette.get(index as usize) {
+                output.put_pixel(x, y, *color);
+            }
+        }
+    }
+    output
+}
+
+/// VDC1 layer over the VDC2 layer: color 0 pixels of the foreground are transparent
+fn composite_layers(foreground: &RgbaImage, background: &RgbaImage, palette_result: &TilePaletteResult) -> RgbaImage {
+    let color0 = palette_result
+        .palettes
+        .first()
+        .and_then(|palette| palette.first())
+        .and_then(|color| parse_hex_color(color));
+    RgbaImage::from_fn(foreground.width(), foreground.height(), |x, y| {
+        let pixel = *foreground.get_pixel(x, y);
+        if Some(pixel) == color0 {
+            *background.get_pixel(x, y)
+        } else {
+            pixel
+        }
+    })
+}
+
+/// Sprite of a sprite conversion: one sprite palette for the whole cell
+#[derive(Serialize, Deserialize, Clone)]
+struct SpriteCell {
+    x: u32,
+    y: u32,
+    width: u32,   // 16 or 32
+    height: u32,  // 16, 32 or 64
+    palette: usize,  // Sprite palette 0-15 (VCE $100 + palette * 16)
+    pixels: Vec<u8>,  // width * height indices, row-major, 0 = transparent
+}
+
+#[derive(Serialize)]
+struct SpriteConversionResult {
+    preview_base64: String,
+    palettes: Vec<Vec<String>>,  // Sprite palettes, index 0 (transparent) holds color0
+    sprites: Vec<SpriteCell>,  // Cells with at least one opaque pixel
+    cell_count: usize,  // Cells in the grid, empty ones included
+    warnings: Vec<String>,
+}
+
+/// Convert an image to sprites: the image is cut into cells of cell_width x cell_height,
+/// each cell gets one of the sprite palettes. Transparent pixels (alpha < 128) and the
+/// background color become color 0.
+#[tauri::command]
+fn run_sprite_conversion(
+    app: AppHandle,
+    input_path: String,
+    source_transform: SourceTransform,  // Crop, scale and pan of the source
+    settings: ConversionSettings,  // Only the target, the palette count (1-16 sprite palettes) and the dither mode are used
+    cell_width: u32,   // 16 or 32
+    cell_height: u32,  // 16, 32 or 64
+) -> Result<SpriteConversionResult, String> {
+    let ConversionSettings { target, palettes, dither, .. } = settings;
+    let TargetSettings {
+        width: target_width,
+        height: target_height,
+        pixel_aspect,
+        resize_method,
+        keep_ratio,
+        background_color,
+        curve_lut,
+        linear_light,  // Resize and diffuse the dithering error in linear light
+        ..
+    } = target;
+    let (palette_count, dither_mode) = (palettes.count, dither.mode);
+    if !matches!(cell_width, 16 | 32) || !matches!(cell_height, 16 | 32 | 64) {
+        return Err(format!("Taille de sprite invalide : {}x{}", cell_width, cell_height));
+    }
+    source_transform.validate()?;
+
+    let _ = app.emit("conversion-progress", ProgressEvent {
+        percent: 5,
+        stage: "Chargement de l'image...".to_string(),
+    });
+    let (image, _, encoding_warning) =
+        load_source_image(&app, &input_path, target_width, target_height, &source_transform, linear_light)?;
+    let (source_transform, mut warnings) =
+        prepare_pixel_art(&image, target_width, target_height, keep_ratio, pixel_aspect, &source_transform);
+    warnings.extend(encoding_warning);
+
+    let _ = app.emit("conversion-progress", ProgressEvent {
+        percent: 20,
+        stage: "Redimensionnement...".to_string(),
+    });
+    let resized = resize_to_target(image, target_width, target_height, &resize_method, keep_ratio, &background_color, pixel_aspect, &source_transform, linear_light)?;
+    let mut curved = apply_curve_lut(&resized.to_rgba8(), &curve_lut);
+
+    // Half transparent pixels are transparent, quantization turns them into the background color
+    for pixel in curved.pixels_mut() {
+        if pixel.0[3] < 128 {
+            pixel.0[3] = 0;
+        }
+    }
+
+    let _ = app.emit("conversion-progress", ProgressEvent {
+        percent: 35,
+        stage: "Quantification RGB333...".to_string(),
+    });
+    let bg = quantized_background(&background_color);
+    let (quantized, transparent) = sprite_transparency(&curved, &background_color, &bg)?;
+
+    let _ = app.emit("conversion-progress", ProgressEvent {
+        percent: 50,
+        stage: "Construction des palettes de sprites...".to_string(),
+    });
+    let (cells, color_sets, cell_count) = sprite_cell_colors(&quantized, &transparent, cell_width, cell_height);
+    let (packed, cell_palettes) = pack_sprite_palettes(&color_sets, palette_count as usize);
+    let color0 = format!("#{:02X}{:02X}{:02X}", bg.0[0], bg.0[1], bg.0[2]);
+    let palettes = sprite_palette_strings(&packed, &color0);
+
+    let _ = app.emit("conversion-progress", ProgressEvent {
+        percent: 70,
+        stage: "Application des palettes...".to_string(),
+    });
+    let (preview, sprites) = encode_sprite_cells(
+        &curved,
+        &bg,
+        &transparent,
+        &cells,
+        &cell_palettes,
+        &palettes,
+        &dither_mode,
+        cell_width,
+        cell_height,
+        linear_light,
+    )?;
+
+    let _ = app.emit("conversion-progress", ProgressEvent {
+        percent: 100,
+        stage: "Terminé!".to_string(),
+    });
+
+    Ok(SpriteConversionResult {
+        preview_base64: encode_png_base64(preview)?,
+        palettes,
+        sprites,
+        cell_count,
+        warnings,
+    })
+}
+
+/// Quantize a sprite image and flag its transparent pixels: alpha 0 (after thresholding)
+/// or the background color
+fn sprite_transparency(image: &RgbaImage, background_color: &str, bg: &Rgba<u8>) -> Result<(RgbaImage, Vec<bool>), String> {
+    let quantized = quantize_rgb333(DynamicImage::ImageRgba8(image.clone()), 16, "none", background_color)?;
+    let transparent = quantized.pixels().map(|pixel| pixel.0[..3] == bg.0[..3]).collect();
+    Ok((quantized, transparent))
+}
+
+/// Wanted colors of a sprite cell and their weight
+type CellColors = Vec<([u8; 3], f32)>;
+
+/// Cut a quantized image into sprite cells and collect the wanted colors of each non-empty cell.
+/// Returns the non-empty cell positions, their color sets and the cell count, empty ones included.
+fn sprite_cell_colors(
+    quantized: &RgbaImage,
+    transparent: &[bool],
+    cell_width: u32,
+    cell_height: u32,
+) -> (Vec<(u32, u32)>, Vec<CellColors>, usize) {
+    use std::collections::HashMap;
+
+    let (width, height) = quantized.dimensions();
+    let mut cells: Vec<(u32, u32)> = Vec::new();
+    let mut color_sets: Vec<CellColors> = Vec::new();
+    let mut cell_count = 0;
+    for cell_y in (0..height).step_by(cell_height as usize) {
+        for cell_x in (0..width).step_by(cell_width as usize) {
+            cell_count += 1;
+            let mut counts: HashMap<[u8; 3], usize> = HashMap::new();
+            for y in cell_y..(cell_y + cell_height).min(height) {
+                for x in cell_x..(cell_x + cell_width).min(width) {
+                    if !transparent[(y * width + x) as usize] {
+                        let pixel = quantized.get_pixel(x, y);
+                        *counts.entry([pixel.0[0], pixel.0[1], pixel.0[2]]).or_insert(0) += 1;
+                    }
+                }
+            }
+            if !counts.is_empty() {
+                cells.push((cell_x, cell_y));
+                color_sets.push(spread_cell_colors(&counts, 15));
+            }
+        }
+    }
+    (cells, color_sets, cell_count)
+}
+
+/// Dither an image with the palette of each sprite cell and encode the cells as sprites.
+/// Returns the preview (transparent pixels in the background color) and the sprites.
+#[allow(clippy::too_many_arguments)]
+fn encode_sprite_cells(
+    image: &RgbaImage,
+    bg: &Rgba<u8>,
+    transparent: &[bool],
+    cells: &[(u32, u32)],
+    cell_palettes: &[usize],
+    palettes: &[Vec<String>],
+    dither_mode: &str,
+    cell_width: u32,
+    cell_height: u32,
+    linear_light: bool,
+) -> Result<(RgbaImage, Vec<SpriteCell>), String> {
+    let (width, height) = image.dimensions();
+
+    // Every 8x8 tile of a cell uses the cell palette, so the background dithering applies as is
+    let tiles_x = width / 8;
+    let tiles_y = height / 8;
+    let mut tile_palette_map = vec![0usize; (tiles_x * tiles_y) as usize];
+    for ((cell_x, cell_y), palette) in cells.iter().zip(cell_palettes) {
+        for tile_y in cell_y / 8..((cell_y + cell_height) / 8).min(tiles_y) {
+            for tile_x in cell_x / 8..((cell_x + cell_width) / 8).min(tiles_x) {
+                tile_palette_map[(tile_y * tiles_x + tile_x) as usize] = *palette;
+            }
+        }
+    }
+    let empty_tiles: Vec<bool> = (0..tiles_x * tiles_y)
+        .map(|tile| {
+            let (tile_x, tile_y) = (tile % tiles_x, tile / tiles_x);
+            (0..64).all(|i| transparent[((tile_y * 8 + i / 8) * width + tile_x * 8 + i % 8) as usize])
+        })
+        .collect();
+    let palette_result = TilePaletteResult {
+        palettes: palettes.to_vec(),
+        tile_palette_map,
+        // Opaque pixels never use color 0, it is the transparent color of sprites
+        palette_colors: palettes.iter().map(|palette| palette[1..].to_vec()).collect(),
+        empty_tiles,
+    };
+
+    // Transparent pixels neither take nor spread dithering error
+    let strength_map: Vec<f32> = transparent.iter().map(|&t| if t { 0.0 } else { 1.0 }).collect();
+    let dither = DitherOptions { mode: dither_mode.to_string(), linear_light, ..DitherOptions::none() };
+    let mut preview = apply_tile_palettes_with_dither(&quantized_source(image, bg), &palette_result, &dither, Some(&strength_map), None)?;
+    for (index, pixel) in preview.pixels_mut().enumerate() {
+        if transparent[index] {
+            *pixel = *bg;
+        }
+    }
+
+    let mut sprites = Vec::with_capacity(cells.len());
+    for (&(cell_x, cell_y), &palette_index) in cells.iter().zip(cell_palettes) {
+        let palette: Vec<Option<Rgba<u8>>> = palettes[palette_index].iter().map(|c| parse_hex_color(c)).collect();
+        let mut pixels = vec![0u8; (cell_width * cell_height) as usize];
+        for y in cell_y..(cell_y + cell_height).min(height) {
+            for x in cell_x..(cell_x + cell_width).min(width) {
+                if transparent[(y * width + x) as usize] {
+                    continue;
+                }
+                let pixel = preview.get_pixel(x, y);
+                let index = palette.iter().skip(1).position(|c| *c == Some(*pixel)).map(|i| i + 1).unwrap_or(0);
+                pixels[((y - cell_y) * cell_width + (x - cell_x)) as usize] = index as u8;
+            }
+        }
+        sprites.push(SpriteCell {
+            x: cell_x,
+            y: cell_y,
+            width: cell_width,
+            height: cell_height,
+            palette: palette_index,
+            pixels,
+        });
+    }
+    Ok((preview, sprites))
+}
+
+/// Background color as quantized by quantize_rgb333
+fn quantized_background(background_color: &str) -> Rgba<u8> {
+    let mut bg = parse_hex_color(background_color).unwrap_or(Rgba([0, 0, 0, 255]));
+    Rgb333Map { levels: 8 }.map_color(&mut bg);
+    bg
+}
+
+/// Source for dithering with transparent pixels set to the (quantized) background color
+fn quantized_source(image: &RgbaImage, bg: &Rgba<u8>) -> RgbaImage {
+    let mut source = image.clone();
+    for pixel in source.pixels_mut() {
+        if pixel.0[3] == 0 {
+            *pixel = *bg;
+        } else {
+            pixel.0[3] = 255;
+        }
+    }
+    source
+}
+
+/// Pick up to `max_colors` colors of a cell, favoring frequent colors far from those already
+/// picked so small details keep their color. Weights are pixel counts.
+fn spread_cell_colors(counts: &std::collections::HashMap<[u8; 3], usize>, max_colors: usize) -> Vec<([u8; 3], f32)> {
+    let mut candidates: Vec<([u8; 3], usize)> = counts.iter().map(|(c, n)| (*c, *n)).collect();
+    // Deterministic order for ties
+    candidates.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    let mut picked: Vec<([u8; 3], f32)> = Vec::new();
+    while picked.len() < max_colors && !candidates.is_empty() {
+        let score = |(color, count): &([u8; 3], usize)| -> f32 {
+            let nearest = picked
+                .iter()
+                .map(|(p, _)| (0..3).map(|c| (color[c] as f32 - p[c] as f32).powi(2)).sum::<f32>())
+                .fold(f32::MAX, f32::min);
+            // Square root of the count so a small distinct detail beats shades of a large area
+            (*count as f32).sqrt() * if picked.is_empty() { 1.0 } else { nearest.sqrt() }
+        };
+        let best = (0..candidates.len())
+            .max_by(|&a, &b| score(&candidates[a]).total_cmp(&score(&candidates[b])).then(b.cmp(&a)))
+            .unwrap_or(0);
+        let (color, count) = candidates.remove(best);
+        picked.push((color, count as f32));
+    }
+    picked
+}
+
+/// Frames of a sprite animation
+#[derive(Deserialize)]
+struct AnimationSource {
+    path: String,  // Sprite sheet, GIF or folder of images
+    kind: String,  // "sheet", "folder" or "gif"
+    frame_width: u32,  // Sheet grid (sheet only)
+    frame_height: u32,
+    frame_count: u32,  // Frames to keep, 0 = all
+}
+
+/// Sprite of a metasprite (animation frame), positioned from the frame top-left corner
+#[derive(Serialize, Deserialize, Clone)]
+struct MetaspriteEntry {
+    x: i32,
+    y: i32,
+    pattern: usize,  // Index in the unique patterns
+    palette: usize,
+    h_flip: bool,
+    v_flip: bool,
+}
+
+#[derive(Serialize)]
+struct SpriteAnimationResult {
+    preview_base64: String,  // Converted frames side by side, up to 8 per row
+    palettes: Vec<Vec<String>>,  // Sprite palettes shared by all frames
+    patterns: Vec<SpriteCell>,  // Unique sprite patterns (position and palette of their first use)
+    frames: Vec<Vec<MetaspriteEntry>>,  // Metasprite table of each frame
+    frame_width: u32,
+    frame_height: u32,
+    sprite_count: usize,  // Non-empty cells over all frames, before dedup
+    flipped_count: usize,  // Cells matching a pattern only once flipped
+}
+
+const ANIMATION_PREVIEW_COLUMNS: u32 = 8;
+
+/// Mirror the indices of a sprite horizontally and/or vertically
+fn flip_sprite_pixels(pixels: &[u8], width: u32, height: u32, h_flip: bool, v_flip: bool) -> Vec<u8> {
+    let mut flipped = vec![0u8; pixels.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let source_x = if h_flip { width - 1 - x } else { x };
+            let source_y = if v_flip { height - 1 - y } else { y };
+            flipped[(y * width + x) as usize] = pixels[(source_y * width + source_x) as usize];
+        }
+    }
+    flipped
+}
+
+/// Index of a sprite in the unique patterns and the flips that turn the pattern into the sprite.
+/// Tries the pattern as is, then mirrored; adds the sprite as a new pattern when none matches.
+fn dedup_sprite_pattern(
+    patterns: &mut Vec<SpriteCell>,
+    pattern_index: &mut std::collections::HashMap<Vec<u8>, usize>,
+    sprite: &SpriteCell,
+) -> (usize, bool, bool) {
+    let matched = [(false, false), (true, false), (false, true), (true, true)].into_iter().find_map(|(h_flip, v_flip)| {
+        let variant = flip_sprite_pixels(&sprite.pixels, sprite.width, sprite.height, h_flip, v_flip);
+        pattern_index.get(&variant).map(|&index| (index, h_flip, v_flip))
+    });
+    matched.unwrap_or_else(|| {
+        pattern_index.insert(sprite.pixels.clone(), patterns.len());
+        patterns.push(sprite.clone());
+        (patterns.len() - 1, false, false)
+    })
+}
+
+/// Load the frames of an animation: cells of a sprite sheet read row by row, the images of a
+/// folder in file name order, or the frames of an animated GIF.
+/// Frames of different sizes are placed at the top-left corner of the largest one.
+fn load_animation_frames(
+    source_path: &str,
+    source_kind: &str,
+    frame_width: u32,
+    frame_height: u32,
+    frame_count: u32,
+) -> Result<Vec<RgbaImage>, String> {
+    use image::AnimationDecoder;
+
+    let mut frames: Vec<RgbaImage> = match source_kind {
+        "sheet" => {
+            if frame_width == 0 || frame_height == 0 {
+                return Err("Taille des images de la planche invalide".to_string());
+            }
+            let sheet = open_source_image(source_path, &SourceTransform::default())?.0.to_rgba8();
+            let columns = sheet.width() / frame_width;
+            let rows = sheet.height() / frame_height;
+            (0..rows)
+                .flat_map(|row| (0..columns).map(move |column| (column, row)))
+                .map(|(column, row)| {
+                    image::imageops::crop_imm(&sheet, column * frame_width, row * frame_height, frame_width, frame_height).to_image()
+                })
+                .collect()
+        }
+        "folder" => {
+            let mut paths: Vec<std::path::PathBuf> = std::fs::read_dir(source_path)
+                .map_err(|e| format!("Lecture du dossier impossible : {}", e))?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| {
+                    path.extension()
+                        .and_then(|ext| ext.to_str())
+                        .map(|ext| matches!(ext.to_ascii_lowercase().as_str(), "png" | "jpg" | "jpeg" | "gif" | "webp" | "bmp" | "pcx" | "tga" | "tif" | "tiff" | "qoi"))
+                        .unwrap_or(false)
+                })
+                .collect();
+            paths.sort();
+            paths
+                .iter()
+                .map(|path| open_source_image(&path.to_string_lossy(), &SourceTransform::default()).map(|(image, _)| image.to_rgba8()).map_err(|e| format!("{} : {}", path.display(), e)))
+                .collect::<Result<_, _>>()?
+        }
+        "gif" => {
+            let file = std::fs::File::open(source_path).map_err(|e| e.to_string())?;
+            let decoder = image::codecs::gif::GifDecoder::new(std::io::BufReader::new(file)).map_err(|e| e.to_string())?;
+            decoder
+                .into_frames()
+                .collect_frames()
+                .map_err(|e| e.to_string())?
+                .into_iter()
+                .map(|frame| frame.into_buffer())
+                .collect()
+        }
+        _ => return Err(format!("Source d'animation inconnue : {}", source_kind)),
+    };
+
+    if frame_count > 0 {
+        frames.truncate(frame_count as usize);
+    }
+    if frames.is_empty() {
+        return Err("Aucune image trouvée pour l'animation".to_string());
+    }
+
+    let width = frames.iter().map(|frame| frame.width()).max().unwrap_or(0);
+    let height = frames.iter().map(|frame| frame.height()).max().unwrap_or(0);
+    Ok(frames
+        .into_iter()
+        .map(|frame| {
+            if frame.dimensions() == (width, height) {
+                return frame;
+            }
+            let mut canvas = RgbaImage::new(width, height);
+            image::imageops::overlay(&mut canvas, &frame, 0, 0);
+            canvas
+        })
+        .collect())
+}
+
+/// Convert an animation to sprites: every frame is cut into cells of cell_width x cell_height
+/// with sprite palettes shared by all frames. Identical cells, flipped ones included, share
+/// one pattern and each frame gets a metasprite table. Frames are used at their own size.
+#[tauri::command]
+fn run_sprite_animation(
+    app: AppHandle,
+    source: AnimationSource,
+    settings: ConversionSettings,  // Only the background color, curve, palette count and dither mode are used
+    cell_width: u32,   // 16 or 32
+    cell_height: u32,  // 16, 32 or 64
+) -> Result<SpriteAnimationResult, String> {
+    use std::collections::HashMap;
 
-    let resized = resize_to_target(
-        image,
-        target_width,
-        target_height,
-        &resize_method,
-        keep_ratio,
-        &background_color,
-    )?;
+    let AnimationSource { path: source_path, kind: source_kind, frame_width, frame_height, frame_count } = source;
+    let ConversionSettings { target, palettes, dither, .. } = settings;
+    let TargetSettings {
+        background_color,
+        curve_lut,
+        linear_light,  // Diffuse the dithering error in linear light
+        ..
+    } = target;
+    let (palette_count, dither_mode) = (palettes.count, dither.mode);
+
+    if !matches!(cell_width, 16 | 32) || !matches!(cell_height, 16 | 32 | 64) {
+        return Err(format!("Taille de sprite invalide : {}x{}", cell_width, cell_height));
+    }
 
-    // Emit: applying curve
     let _ = app.emit("conversion-progress", ProgressEvent {
-        percent: 25,
-        stage: "Application de la courbe...".to_string(),
+        percent: 5,
+        stage: "Chargement des images...".to_string(),
     });
+    let frames = load_animation_frames(&source_path, &source_kind, frame_width, frame_height, frame_count)?;
+    let (source_width, source_height) = frames[0].dimensions();
 
-    // Apply curve LUT to adjust color levels before quantization
-    let curved = apply_curve_lut(&resized.to_rgba8(), &curve_lut);
-    let curved_image = DynamicImage::ImageRgba8(curved);
+    // Frames are padded with transparent pixels to whole sprite cells
+    let width = source_width.div_ceil(cell_width) * cell_width;
+    let height = source_height.div_ceil(cell_height) * cell_height;
+    let bg = quantized_background(&background_color);
 
-    // Emit: quantization
     let _ = app.emit("conversion-progress", ProgressEvent {
-        percent: 35,
+        percent: 20,
         stage: "Quantification RGB333...".to_string(),
     });
+    let mut prepared = Vec::with_capacity(frames.len());
+    let mut all_color_sets: Vec<CellColors> = Vec::new();
+    for frame in &frames {
+        let mut canvas = RgbaImage::new(width, height);
+        image::imageops::overlay(&mut canvas, frame, 0, 0);
+        let mut curved = apply_curve_lut(&canvas, &curve_lut);
+        for pixel in curved.pixels_mut() {
+            if pixel.0[3] < 128 {
+                pixel.0[3] = 0;
+            }
+        }
+        let (quantized, transparent) = sprite_transparency(&curved, &background_color, &bg)?;
+        let (cells, color_sets, _) = sprite_cell_colors(&quantized, &transparent, cell_width, cell_height);
+        all_color_sets.extend(color_sets);
+        prepared.push((curved, transparent, cells));
+    }
 
-    // First pass: quantize to RGB333 WITHOUT dithering to build palettes
-    let quantized_for_palette = quantize_rgb333(curved_image.clone(), palette_count, "none", &background_color)?;
-
-    // Emit: palette building
     let _ = app.emit("conversion-progress", ProgressEvent {
-        percent: 50,
-        stage: "Construction des palettes...".to_string(),
+        percent: 40,
+        stage: "Construction des palettes de sprites...".to_string(),
     });
+    let (packed, all_cell_palettes) = pack_sprite_palettes(&all_color_sets, palette_count as usize);
+    let color0 = format!("#{:02X}{:02X}{:02X}", bg.0[0], bg.0[1], bg.0[2]);
+    let palettes = sprite_palette_strings(&packed, &color0);
 
-    let palette_result = build_palettes_for_tiles(
-        &quantized_for_palette,
-        palette_count as usize,
-        &background_color,
-        &palette_group_constraints,
-        seed,
-    )?;
-
-    // Emit: applying palettes with dithering
     let _ = app.emit("conversion-progress", ProgressEvent {
-        percent: 70,
+        percent: 60,
         stage: "Application des palettes...".to_string(),
     });
-
-    // Second pass: apply dithering with the actual tile palettes (using curved image)
-    let preview = if use_dither_mask && !dither_mask.is_empty() && dither_mode != "none" {
-        // Generate both dithered and non-dithered versions
-        let dithered = apply_tile_palettes_with_dither(
-            &curved_image.to_rgba8(),
-            &palette_result,
+    let columns = (frames.len() as u32).min(ANIMATION_PREVIEW_COLUMNS);
+    let rows = (frames.len() as u32).div_ceil(columns);
+    let mut preview = RgbaImage::from_pixel(columns * width, rows * height, bg);
+    let mut patterns: Vec<SpriteCell> = Vec::new();
+    let mut pattern_index: HashMap<Vec<u8>, usize> = HashMap::new();
+    let mut metasprites = Vec::with_capacity(frames.len());
+    let mut sprite_count = 0;
+    let mut flipped_count = 0;
+    let mut palette_offset = 0;
+    for (frame_index, (curved, transparent, cells)) in prepared.iter().enumerate() {
+        let cell_palettes = &all_cell_palettes[palette_offset..palette_offset + cells.len()];
+        palette_offset += cells.len();
+        let (frame_preview, sprites) = encode_sprite_cells(
+            curved,
+            &bg,
+            transparent,
+            cells,
+            cell_palettes,
+            &palettes,
             &dither_mode,
+            cell_width,
+            cell_height,
+            linear_light,
         )?;
-        let non_dithered = apply_tile_palettes_with_dither(
-            &curved_image.to_rgba8(),
-            &palette_result,
-            "none",
-        )?;
-
-        // Resize mask to target dimensions (using same keep_ratio logic as image)
-        let resized_mask = resize_mask(&dither_mask, mask_width, mask_height, target_width, target_height, keep_ratio);
-
-        // Combine based on mask (black = dithered, white = non-dithered)
-        combine_with_mask(&dithered, &non_dithered, &resized_mask)
-    } else {
-        apply_tile_palettes_with_dither(
-            &curved_image.to_rgba8(),
-            &palette_result,
-            &dither_mode,
-        )?
-    };
+        let (column, row) = (frame_index as u32 % columns, frame_index as u32 / columns);
+        image::imageops::replace(&mut preview, &frame_preview, (column * width) as i64, (row * height) as i64);
+
+        // Dedup against the stored patterns as is, then mirrored (the SATB flip bits undo the mirror)
+        let mut entries = Vec::with_capacity(sprites.len());
+        for sprite in sprites {
+            sprite_count += 1;
+            let (pattern, h_flip, v_flip) = dedup_sprite_pattern(&mut patterns, &mut pattern_index, &sprite);
+            if h_flip || v_flip {
+                flipped_count += 1;
+            }
+            entries.push(MetaspriteEntry {
+                x: sprite.x as i32,
+                y: sprite.y as i32,
+                pattern,
+                palette: sprite.palette,
+                h_flip,
+                v_flip,
+            });
+        }
+        metasprites.push(entries);
+    }
 
-    // Emit: encoding
     let _ = app.emit("conversion-progress", ProgressEvent {
-        percent: 90,
-        stage: "Encodage PNG...".to_string(),
+        percent: 100,
+        stage: "Terminé!".to_string(),
     });
 
-    let mut output = Vec::new();
-    DynamicImage::ImageRgba8(preview.clone())
-        .write_to(&mut std::io::Cursor::new(&mut output), image::ImageFormat::Png)
-        .map_err(|e| e.to_string())?;
-
-    // Calculate unique tiles for stats
-    let (width, height) = preview.dimensions();
-    let tiles_x = width / 8;
-    let tiles_y = height / 8;
-    let total_tiles = (tiles_x * tiles_y) as usize;
+    Ok(SpriteAnimationResult {
+        preview_base64: encode_png_base64(preview)?,
+        palettes,
+        patterns,
+        frames: metasprites,
+        frame_width: width,
+        frame_height: height,
+        sprite_count,
+        flipped_count,
+    })
+}
 
-    // Empty tile is always first (32 bytes of zeros = all pixels are color index 0)
-    let empty_tile: [u8; 32] = [0u8; 32];
-    let mut unique_tiles: Vec<[u8; 32]> = vec![empty_tile];
-    let mut tile_to_unique: Vec<usize> = Vec::with_capacity(total_tiles);
+#[derive(Serialize)]
+struct PaletteSweepEntry {
+    palette_count: u8,
+    preview_base64: String,
+    unique_tile_count: usize,
+    used_palette_count: usize,
+    mse: f64,
+    psnr: f64,
+}
 
-    for tile_idx in 0..total_tiles {
-        // Empty tiles all point to the first tile (index 0)
-        if palette_result.empty_tiles.get(tile_idx).copied().unwrap_or(false) {
-            tile_to_unique.push(0);
-            continue;
-        }
+#[derive(Serialize)]
+struct ArtValidationResult {
+    violations: Vec<TileViolation>,
+    tile_count: usize,
+    color_count: usize,  // Distinct colors besides color0
+    palettes: Vec<Vec<String>>,  // Exact palettes in use when the art passes (empty otherwise)
+    cover_error: Option<String>,  // Why no exact palette cover was found
+    grid_offset: Option<(i32, i32)>,  // Offset (dx, dy) chosen by the grid alignment search, added to the pan
+}
 
-        let tile_x = (tile_idx % tiles_x as usize) as u32;
-        let tile_y = (tile_idx / tiles_x as usize) as u32;
-        let palette_idx = palette_result.tile_palette_map.get(tile_idx).copied().unwrap_or(0);
-        let palette = palette_result.palettes.get(palette_idx).cloned().unwrap_or_default();
-        let tile_data = encode_tile_planar(&preview, tile_x, tile_y, &palette);
+/// Check that the source, once placed at the target size like the lossless conversion does,
+/// follows the PCE rules, and look for the exact palettes
+#[tauri::command]
+fn validate_pce_art(
+    app: AppHandle,
+    input_path: String,
+    source_transform: SourceTransform,  // Crop, scale and pan of the source
+    settings: ConversionSettings,  // Only the target and palette settings are used
+) -> Result<ArtValidationResult, String> {
+    let ConversionSettings { target, palettes, .. } = settings;
+    let TargetSettings {
+        width: target_width,
+        height: target_height,
+        pixel_aspect,
+        keep_ratio,
+        background_color,
+        grid_align,
+        grid_align_step,
+        linear_light,  // Pre-resize in linear light, like the conversion
+        ..
+    } = target;
+    let PaletteSettings { count: palette_count, group_constraints: palette_group_constraints, .. } = palettes;
+    source_transform.validate()?;
+    let (image, _, _) = load_source_image(&app, &input_path, target_width, target_height, &source_transform, linear_light)?;
+    let (source_transform, _) =
+        prepare_pixel_art(&image, target_width, target_height, keep_ratio, pixel_aspect, &source_transform);
+    // Same grid shift as the lossless conversion
+    let (source_transform, palette_group_constraints, grid_offset) = align_grid(
+        &image,
+        target_width,
+        target_height,
+        "nearest",
+        keep_ratio,
+        &background_color,
+        pixel_aspect,
+        source_transform,
+        palette_group_constraints,
+        &[],
+        &grid_align,
+        grid_align_step,
+        linear_light,
+    )?;
+    let resized = resize_to_target(image, target_width, target_height, "nearest", keep_ratio, &background_color, pixel_aspect, &source_transform, linear_light)?
+        .to_rgba8();
 
-        // Check for duplicate
-        let existing_idx = unique_tiles.iter().position(|t| *t == tile_data);
-        match existing_idx {
-            Some(idx) => tile_to_unique.push(idx),
-            None => {
-                tile_to_unique.push(unique_tiles.len());
-                unique_tiles.push(tile_data);
+    let violations = validate_pce_tiles(&resized, &background_color);
+    let quantized = quantize_rgb333(DynamicImage::ImageRgba8(resized.clone()), palette_count, "none", &background_color)?;
+    let color0 = quantized_background(&background_color);
+    let color_count = quantized
+        .pixels()
+        .filter(|pixel| **pixel != color0)
+        .map(|pixel| pixel.0)
+        .collect::<std::collections::HashSet<_>>()
+        .len();
+
+    let (palettes, cover_error) = if violations.is_empty() {
+        match exact_tile_palettes(&quantized, palette_count as usize, &background_color, &palette_group_constraints) {
+            Ok(result) => {
+                let used = result.palette_colors.iter().filter(|colors| colors.len() > 1).count();
+                (result.palettes.into_iter().take(used).collect(), None)
             }
+            Err(error) => (Vec::new(), Some(error)),
         }
+    } else {
+        (Vec::new(), None)
+    };
+
+    Ok(ArtValidationResult {
+        violations,
+        tile_count: ((target_width / 8) * (target_height / 8)) as usize,
+        color_count,
+        palettes,
+        cover_error,
+        grid_offset,
+    })
+}
+
+/// Convert at several palette counts in one call, for side-by-side comparison.
+/// The source is prepared once like run_conversion does; palette building, dithering and
+/// the tile passes run per palette count, with the same modes as the conversion.
+#[tauri::command]
+fn run_palette_sweep(
+    app: AppHandle,
+    input_path: String,
+    source_transform: SourceTransform,  // Crop, scale and pan of the source
+    settings: ConversionSettings,  // The palette count is replaced by each of palette_counts
+    palette_counts: Vec<u8>,
+    preview_width: u32,  // Width of the returned previews in pixels
+) -> Result<Vec<PaletteSweepEntry>, String> {
+    let prepared = prepare_source(&app, &input_path, &source_transform, &settings)?;
+    let (target_width, target_height) = (settings.target.width, settings.target.height);
+
+    // Previews keep the aspect ratio of the target
+    let thumb_width = preview_width.clamp(8, target_width.max(8));
+    let thumb_height = ((target_height as u64 * thumb_width as u64) / target_width.max(1) as u64).max(1) as u32;
+
+    let mut counts: Vec<u8> = palette_counts.iter().map(|&c| c.clamp(1, 16)).collect();
+    counts.sort_unstable();
+    counts.dedup();
+
+    let mut entries = Vec::with_capacity(counts.len());
+    for (step, &palette_count) in counts.iter().enumerate() {
+        let _ = app.emit("conversion-progress", ProgressEvent {
+            percent: (50 + step * 45 / counts.len().max(1)) as u8,
+            stage: format!("Conversion avec {} palettes...", palette_count),
+        });
+
+        let layers = convert_layers(None, &prepared, &settings, palette_count)?;
+        // Previews and errors show what the layers display together; the tiles of flicker
+        // frame B share the VRAM of frame A, the VDC2 layer has its own
+        let (shown, unique_tile_count) = match (&layers.flicker, &layers.sgx) {
+            (Some(frame_b), _) => (
+                blend_frames(&layers.main.preview, &frame_b.preview),
+                shared_unique_tile_count(&layers.main.unique_tiles, &frame_b.unique_tiles),
+            ),
+            (_, Some(background)) => (
+                composite_layers(&layers.main.preview, &background.preview, &layers.main.palette_result),
+                layers.main.unique_tiles.len(),
+            ),
+            _ => (layers.main.preview.clone(), layers.main.unique_tiles.len()),
+        };
+
+        let (mse, psnr) = image_error_metrics(&prepared.reference, &shown);
+        let palette_result = &layers.main.palette_result;
+        let used_palette_count = palette_result
+            .tile_palette_map
+            .iter()
+            .zip(palette_result.empty_tiles.iter())
+            .filter(|(_, &empty)| !empty)
+            .map(|(&palette_idx, _)| palette_idx)
+            .collect::<std::collections::HashSet<_>>()
+            .len();
+
+        let thumbnail = image::imageops::resize(&shown, thumb_width, thumb_height, FilterType::Triangle);
+        let mut output = Vec::new();
+        DynamicImage::ImageRgba8(thumbnail)
+            .write_to(&mut std::io::Cursor::new(&mut output), image::ImageFormat::Png)
+            .map_err(|e| e.to_string())?;
+
+        entries.push(PaletteSweepEntry {
+            palette_count,
+            preview_base64: base64::engine::general_purpose::STANDARD.encode(output),
+            unique_tile_count,
+            used_palette_count,
+            mse,
+            psnr,
+        });
     }
 
-    // Emit: done
     let _ = app.emit("conversion-progress", ProgressEvent {
         percent: 100,
         stage: "Terminé!".to_string(),
     });
 
-    Ok(ConversionResult {
-        preview_base64: base64::engine::general_purpose::STANDARD.encode(output),
-        palettes: palette_result.palettes,
-        tile_palette_map: palette_result.tile_palette_map,
-        empty_tiles: palette_result.empty_tiles,
-        tile_count: total_tiles,
-        unique_tile_count: unique_tiles.len(),
-        tile_to_unique,
-        was_pre_resized,
-    })
+    Ok(entries)
+}
+
+/// Mean squared error (per RGB channel) and PSNR in dB between two images of the same size
+fn image_error_metrics(reference: &RgbaImage, result: &RgbaImage) -> (f64, f64) {
+    let mut sum = 0f64;
+    let mut count = 0usize;
+    for (a, b) in reference.pixels().zip(result.pixels()) {
+        for c in 0..3 {
+            let d = a.0[c] as f64 - b.0[c] as f64;
+            sum += d * d;
+        }
+        count += 3;
+    }
+    let mse = if count > 0 { sum / count as f64 } else { 0.0 };
+    let psnr = if mse > 0.0 {
+        10.0 * (255.0 * 255.0 / mse).log10()
+    } else {
+        f64::INFINITY
+    };
+    (mse, psnr)
 }
 
+/// Resize the (already cropped) source to the target size. Without an explicit scale,
+/// keep_ratio fits and centers the source (square pixels) so it keeps its shape once displayed
+/// with the target pixel aspect ratio. Uncovered areas get the background color.
+#[allow(clippy::too_many_arguments)]
 fn resize_to_target(
     image: DynamicImage,
     width: u32,
@@ -323,24 +3160,420 @@ fn resize_to_target(
     method: &str,
     keep_ratio: bool,
     background_color: &str,
+    pixel_aspect: f32,  // Displayed width / height of a target pixel (1.0 = square)
+    transform: &SourceTransform,
+    linear_light: bool,  // Filter in linear light instead of on the sRGB values
 ) -> Result<DynamicImage, String> {
-    let filter = match method {
+    let placed = resize_visible(&image, width, height, method, keep_ratio, pixel_aspect, transform, linear_light, 0);
+    let bg = parse_hex_color(background_color).unwrap_or(Rgba([0, 0, 0, 255]));
+    let Some((offset_x, offset_y, resized)) = placed else {
+        // The source lies entirely outside the target
+        return Ok(DynamicImage::ImageRgba8(RgbaImage::from_pixel(width, height, bg)));
+    };
+    if (offset_x, offset_y, resized.width(), resized.height()) == (0, 0, width, height) {
+        return Ok(resized);
+    }
+
+    // High bit depth sources keep their precision on a float canvas
+    if is_high_bit_depth(&resized) {
+        let mut canvas = Rgba32FImage::from_pixel(width, height, Rgba(bg.0.map(|v| v as f32 / 255.0)));
+        image::imageops::overlay(&mut canvas, &resized.to_rgba32f(), offset_x, offset_y);
+        return Ok(DynamicImage::ImageRgba32F(canvas));
+    }
+    let mut canvas = RgbaImage::from_pixel(width, height, bg);
+    image::imageops::overlay(&mut canvas, &resized.to_rgba8(), offset_x, offset_y);
+    Ok(DynamicImage::ImageRgba8(canvas))
+}
+
+/// More than 8 bits per channel (16-bit PNG or TIFF, float images)
+fn is_high_bit_depth(image: &DynamicImage) -> bool {
+    let color = image.color();
+    color.bits_per_pixel() > 8 * color.channel_count() as u16
+}
+
+/// Resize the part of the placed source that can land in the target, `margin` target pixels
+/// around it included: (x, y, image) with the position of that part in the target, None when
+/// nothing is visible. A zoomed source is cropped before resizing (with a few source pixels
+/// more for the filter), so the resize never allocates the whole zoomed image.
+#[allow(clippy::too_many_arguments)]
+fn resize_visible(
+    image: &DynamicImage,
+    width: u32,
+    height: u32,
+    method: &str,
+    keep_ratio: bool,
+    pixel_aspect: f32,
+    transform: &SourceTransform,
+    linear_light: bool,
+    margin: u32,
+) -> Option<(i64, i64, DynamicImage)> {
+    let (x, y, scaled_width, scaled_height) =
+        transform.placement(image.width(), image.height(), width, height, keep_ratio, pixel_aspect);
+    let (src_x, src_width, x, out_width) = visible_span(x, scaled_width, width, margin, image.width())?;
+    let (src_y, src_height, y, out_height) = visible_span(y, scaled_height, height, margin, image.height())?;
+    if (src_width, src_height) == (image.width(), image.height()) {
+        return Some((x, y, resize_source(image, out_width, out_height, method, transform, linear_light)));
+    }
+    // The pixel grid phase is relative to the cropped source
+    let transform = SourceTransform {
+        pixel_grid: transform.pixel_grid.map(|grid| PixelGrid {
+            phase_x: (grid.phase_x - src_x as f32).rem_euclid(grid.scale_x),
+            phase_y: (grid.phase_y - src_y as f32).rem_euclid(grid.scale_y),
+            ..grid
+        }),
+        ..*transform
+    };
+    let cropped = image.crop_imm(src_x, src_y, src_width, src_height);
+    Some((x, y, resize_source(&cropped, out_width, out_height, method, &transform, linear_light)))
+}
+
+/// Visible span along one axis of a source of `src_size` pixels placed at `position` with
+/// `scaled_size` pixels in a target of `size` pixels (plus `margin` on both sides):
+/// (first source pixel, source pixels, position in the target, resized pixels)
+fn visible_span(position: i64, scaled_size: u32, size: u32, margin: u32, src_size: u32) -> Option<(u32, u32, i64, u32)> {
+    let low = -(margin as i64);
+    let high = size as i64 + margin as i64;
+    if position >= low && position + scaled_size as i64 <= high {
+        return Some((0, src_size, position, scaled_size));
+    }
+    let start = (low - position).max(0);
+    let end = (high - position).min(scaled_size as i64);
+    if end <= start {
+        return None;
+    }
+    // Lanczos3 reads up to 3 source pixels around each sample
+    let ratio = scaled_size as f64 / src_size as f64;
+    let src_start = ((start as f64 / ratio).floor() as i64 - 3).max(0);
+    let src_end = ((end as f64 / ratio).ceil() as i64 + 3).min(src_size as i64);
+    let placed_start = (src_start as f64 * ratio).round() as i64;
+    let placed_end = (src_end as f64 * ratio).round() as i64;
+    Some((src_start as u32, (src_end - src_start) as u32, position + placed_start, (placed_end - placed_start).max(1) as u32))
+}
+
+fn resize_filter(method: &str) -> FilterType {
+    match method {
         "nearest" => FilterType::Nearest,
         "catmullrom" => FilterType::CatmullRom,
         _ => FilterType::Lanczos3,
+    }
+}
+
+/// Resize the (already cropped) source to its placed size. With a detected pixel grid,
+/// every target pixel takes the color at the center of its native pixel instead of filtering.
+/// In linear light the filter averages light intensities (the result stays a float image).
+fn resize_source(image: &DynamicImage, width: u32, height: u32, method: &str, transform: &SourceTransform, linear_light: bool) -> DynamicImage {
+    let Some(grid) = transform.pixel_grid else {
+        if linear_light && method != "nearest" {
+            let linear = image::imageops::resize(&to_linear_light(image), width, height, resize_filter(method));
+            return from_linear_light(linear);
+        }
+        return image.resize_exact(width, height, resize_filter(method));
+    };
+    let (src_width, src_height) = (image.width(), image.height());
+    let columns: Vec<u32> = (0..width)
+        .map(|x| PixelGrid::snap((x as f32 + 0.5) * src_width as f32 / width as f32, grid.scale_x, grid.phase_x, src_width))
+        .collect();
+    let rows: Vec<u32> = (0..height)
+        .map(|y| PixelGrid::snap((y as f32 + 0.5) * src_height as f32 / height as f32, grid.scale_y, grid.phase_y, src_height))
+        .collect();
+    if is_high_bit_depth(image) {
+        DynamicImage::ImageRgba32F(sample_grid(&image.to_rgba32f(), &columns, &rows))
+    } else {
+        DynamicImage::ImageRgba8(sample_grid(&image.to_rgba8(), &columns, &rows))
+    }
+}
+
+/// Image made of the source pixels at the given columns and rows
+fn sample_grid<P: Pixel>(image: &ImageBuffer<P, Vec<P::Subpixel>>, columns: &[u32], rows: &[u32]) -> ImageBuffer<P, Vec<P::Subpixel>> {
+    ImageBuffer::from_fn(columns.len() as u32, rows.len() as u32, |x, y| *image.get_pixel(columns[x as usize], rows[y as usize]))
+}
+
+/// Native pixel period and phase along one axis from the count of color changes before each
+/// position (edges[x] = changes between x - 1 and x). Tries integer and near-integer periods
+/// from 2 to 16 and keeps the largest one that explains nearly all the edges.
+fn detect_axis_period(edges: &[u32]) -> Option<(f32, f32)> {
+    let total: u32 = edges.iter().sum();
+    if total < 16 {
+        return None;
+    }
+    let max_period = (edges.len() / 4).min(16) as u32;
+    for n in (2..=max_period).rev() {
+        let mut best: Option<(u32, f32, f32)> = None;
+        for step in -10..=10 {
+            let period = n as f32 + step as f32 * 0.005;
+            let mut phase = 0.0;
+            while phase < period {
+                // An edge is on the grid when it is the first position after a boundary
+                let score: u32 = edges
+                    .iter()
+                    .enumerate()
+                    .filter(|(x, _)| (*x as f32 - phase).rem_euclid(period) < 1.0)
+                    .map(|(_, &count)| count)
+                    .sum();
+                let is_better = match best {
+                    Some((best_score, _, _)) => score > best_score,
+                    None => true,
+                };
+                if is_better {
+                    best = Some((score, period, phase));
+                }
+                phase += 0.25;
+            }
+        }
+        if let Some((score, period, phase)) = best {
+            if score as f32 >= total as f32 * 0.95 {
+                return Some(refine_axis_period(edges, period, phase));
+            }
+        }
+    }
+    None
+}
+
+/// Precise period and phase around a coarse match. A pixel belongs to the native pixel under
+/// its center, so the boundary before an edge at x lies in (x - 0.5, x + 0.5]: keep the period
+/// leaving the widest range of phases meeting that for every edge, and the middle of that range.
+fn refine_axis_period(edges: &[u32], period: f32, phase: f32) -> (f32, f32) {
+    // Edges on the coarse grid, with the index of their boundary
+    let points: Vec<(f32, f32)> = edges
+        .iter()
+        .enumerate()
+        .filter(|(x, &count)| count > 0 && (*x as f32 - phase).rem_euclid(period) < 1.0)
+        .map(|(x, _)| (((x as f32 - phase) / period).floor(), x as f32))
+        .collect();
+
+    let mut best = (period, phase, f32::NEG_INFINITY);
+    for step in -100..=100 {
+        let candidate = period + step as f32 * 0.0005;
+        let low = points.iter().map(|&(k, x)| x - 0.5 - k * candidate).fold(f32::NEG_INFINITY, f32::max);
+        let high = points.iter().map(|&(k, x)| x + 0.5 - k * candidate).fold(f32::INFINITY, f32::min);
+        if high - low > best.2 {
+            best = (candidate, (low + high) / 2.0, high - low);
+        }
+    }
+    (best.0, best.1.rem_euclid(best.0))
+}
+
+/// Detect the native pixel grid of pixel art upscaled by an integer or near-integer factor
+fn detect_pixel_grid(image: &DynamicImage) -> Option<PixelGrid> {
+    let rgba = image.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    if width < 8 || height < 8 {
+        return None;
+    }
+    // Small differences (compression noise) are not edges
+    let differs = |a: &Rgba<u8>, b: &Rgba<u8>| {
+        a.0.iter().zip(b.0.iter()).map(|(&p, &q)| (p as i32 - q as i32).abs()).sum::<i32>() > 24
+    };
+    let mut column_edges = vec![0u32; width as usize];
+    let mut row_edges = vec![0u32; height as usize];
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = rgba.get_pixel(x, y);
+            if x > 0 && differs(pixel, rgba.get_pixel(x - 1, y)) {
+                column_edges[x as usize] += 1;
+            }
+            if y > 0 && differs(pixel, rgba.get_pixel(x, y - 1)) {
+                row_edges[y as usize] += 1;
+            }
+        }
+    }
+
+    let (scale_x, phase_x) = detect_axis_period(&column_edges)?;
+    let (scale_y, phase_y) = detect_axis_period(&row_edges)?;
+    Some(PixelGrid {
+        scale_x,
+        scale_y,
+        phase_x,
+        phase_y,
+        native_width: PixelGrid::native_size(width, scale_x, phase_x),
+        native_height: PixelGrid::native_size(height, scale_y, phase_y),
+    })
+}
+
+/// Detect the pixel grid when the pixel art option is on. Returns the transform carrying the
+/// grid, and warnings when the art cannot map 1:1 to the target.
+fn prepare_pixel_art(
+    image: &DynamicImage,
+    width: u32,
+    height: u32,
+    keep_ratio: bool,
+    pixel_aspect: f32,
+    transform: &SourceTransform,
+) -> (SourceTransform, Vec<String>) {
+    let mut warnings = Vec::new();
+    if !transform.pixel_art {
+        return (*transform, warnings);
+    }
+    let Some(grid) = detect_pixel_grid(image) else {
+        warnings.push("Pixel art : aucun agrandissement entier détecté, redimensionnement normal".to_string());
+        return (*transform, warnings);
     };
 
-    if !keep_ratio {
-        return Ok(image.resize_exact(width, height, filter));
+    let (_, _, scaled_width, scaled_height) =
+        transform.placement(image.width(), image.height(), width, height, keep_ratio, pixel_aspect);
+    let ratio_x = scaled_width as f32 / grid.native_width as f32;
+    let ratio_y = scaled_height as f32 / grid.native_height as f32;
+    if ratio_x < 0.98 || ratio_y < 0.98 {
+        warnings.push(format!(
+            "Pixel art de {}×{} pixels réduit à {}×{} : des pixels sont perdus (échelle 1:1 = {:.3})",
+            grid.native_width, grid.native_height, scaled_width, scaled_height, 1.0 / grid.scale_y
+        ));
+    } else if (ratio_x - ratio_x.round()).abs() > 0.02 || (ratio_y - ratio_y.round()).abs() > 0.02 {
+        warnings.push(format!(
+            "Pixel art agrandi de {:.2}×{:.2} : les pixels n'ont pas tous la même taille (échelle 1:1 = {:.3})",
+            ratio_x, ratio_y, 1.0 / grid.scale_y
+        ));
+    }
+    (SourceTransform { pixel_grid: Some(grid), ..*transform }, warnings)
+}
+
+/// Try shifting the placed source by 0-7 pixels on each axis (every `step` pixels) and return
+/// the (dx, dy) whose 8x8 grid gives the best RGB333 tiles: fewest unique tiles for "tiles",
+/// fewest colors over 16 per tile for "colors" (the other count breaks ties, then the smallest shift)
+#[allow(clippy::too_many_arguments)]
+fn search_grid_offset(
+    image: &DynamicImage,
+    width: u32,
+    height: u32,
+    method: &str,
+    keep_ratio: bool,
+    background_color: &str,
+    pixel_aspect: f32,
+    transform: &SourceTransform,
+    curve_lut: &[u8],
+    criterion: &str,
+    step: u32,
+    linear_light: bool,
+) -> Result<(i32, i32), String> {
+    if !matches!(criterion, "tiles" | "colors") {
+        return Err(format!("Critère d'alignement inconnu : {}", criterion));
+    }
+    if !matches!(step, 1 | 2 | 4) {
+        return Err(format!("Pas d'alignement invalide : {} (1, 2 ou 4)", step));
     }
 
-    let resized = image.resize(width, height, filter);
+    // The tried offsets move the source by up to 7 pixels
+    let Some((x, y, resized)) = resize_visible(image, width, height, method, keep_ratio, pixel_aspect, transform, linear_light, 8) else {
+        return Ok((0, 0));
+    };
+    let resized = apply_curve_lut(&resized.to_rgba8(), curve_lut);
     let bg = parse_hex_color(background_color).unwrap_or(Rgba([0, 0, 0, 255]));
-    let mut canvas = RgbaImage::from_pixel(width, height, bg);
-    let offset_x = (width - resized.width()) / 2;
-    let offset_y = (height - resized.height()) / 2;
-    image::imageops::overlay(&mut canvas, &resized.to_rgba8(), offset_x.into(), offset_y.into());
-    Ok(DynamicImage::ImageRgba8(canvas))
+
+    let mut best: Option<((usize, usize), (i32, i32))> = None;
+    for dy in (0..8).step_by(step as usize) {
+        for dx in (0..8).step_by(step as usize) {
+            let mut canvas = RgbaImage::from_pixel(width, height, bg);
+            image::imageops::overlay(&mut canvas, &resized, x + dx as i64, y + dy as i64);
+            let (unique_tiles, color_overflow) = grid_alignment_score(&canvas, bg);
+            let score = if criterion == "colors" {
+                (color_overflow, unique_tiles)
+            } else {
+                (unique_tiles, color_overflow)
+            };
+            let is_better = match best {
+                Some((best_score, _)) => score < best_score,
+                None => true,
+            };
+            if is_better {
+                best = Some((score, (dx, dy)));
+            }
+        }
+    }
+    Ok(best.map(|(_, offset)| offset).unwrap_or((0, 0)))
+}
+
+/// Source transform after the grid alignment, tile constraints following the shift and chosen offset
+type GridAlignment = (SourceTransform, Vec<i32>, Option<(i32, i32)>);
+
+/// Run the grid alignment search unless `grid_align` is "none" and add the chosen offset to the pan
+#[allow(clippy::too_many_arguments)]
+fn align_grid(
+    image: &DynamicImage,
+    width: u32,
+    height: u32,
+    method: &str,
+    keep_ratio: bool,
+    background_color: &str,
+    pixel_aspect: f32,
+    transform: SourceTransform,
+    constraints: Vec<i32>,
+    curve_lut: &[u8],
+    grid_align: &str,
+    step: u32,
+    linear_light: bool,
+) -> Result<GridAlignment, String> {
+    if grid_align == "none" {
+        return Ok((transform, constraints, None));
+    }
+    let (dx, dy) = search_grid_offset(
+        image,
+        width,
+        height,
+        method,
+        keep_ratio,
+        background_color,
+        pixel_aspect,
+        &transform,
+        curve_lut,
+        grid_align,
+        step,
+        linear_light,
+    )?;
+    Ok((
+        SourceTransform {
+            offset_x: transform.offset_x + dx,
+            offset_y: transform.offset_y + dy,
+            ..transform
+        },
+        shift_tile_constraints(&constraints, width / 8, height / 8, dx, dy),
+        Some((dx, dy)),
+    ))
+}
+
+/// Follow a grid shift with per-tile constraints computed for the unshifted placement:
+/// each tile takes the constraint found under its center before the shift (-1 = none)
+fn shift_tile_constraints(constraints: &[i32], tiles_x: u32, tiles_y: u32, dx: i32, dy: i32) -> Vec<i32> {
+    if constraints.len() != (tiles_x * tiles_y) as usize {
+        return constraints.to_vec();
+    }
+    let mut shifted = Vec::with_capacity(constraints.len());
+    for tile_y in 0..tiles_y as i32 {
+        for tile_x in 0..tiles_x as i32 {
+            let src_x = (tile_x * 8 + 4 - dx).div_euclid(8);
+            let src_y = (tile_y * 8 + 4 - dy).div_euclid(8);
+            let inside = src_x >= 0 && src_y >= 0 && src_x < tiles_x as i32 && src_y < tiles_y as i32;
+            shifted.push(if inside { constraints[(src_y * tiles_x as i32 + src_x) as usize] } else { -1 });
+        }
+    }
+    shifted
+}
+
+/// Unique 8x8 tiles and total colors beyond 16 per tile of an image reduced to RGB333
+fn grid_alignment_score(image: &RgbaImage, background: Rgba<u8>) -> (usize, usize) {
+    use std::collections::HashSet;
+
+    let rgb333 = |pixel: &Rgba<u8>| {
+        let pixel = if pixel.0[3] == 0 { &background } else { pixel };
+        ((pixel.0[0] as u16 >> 5) << 6) | ((pixel.0[1] as u16 >> 5) << 3) | (pixel.0[2] as u16 >> 5)
+    };
+    let mut tiles = HashSet::new();
+    let mut color_overflow = 0;
+    for tile_y in 0..image.height() / 8 {
+        for tile_x in 0..image.width() / 8 {
+            let mut tile = Vec::with_capacity(64);
+            for y in 0..8 {
+                for x in 0..8 {
+                    tile.push(rgb333(image.get_pixel(tile_x * 8 + x, tile_y * 8 + y)));
+                }
+            }
+            let mut colors = tile.clone();
+            colors.sort_unstable();
+            colors.dedup();
+            color_overflow += colors.len().saturating_sub(16);
+            tiles.insert(tile);
+        }
+    }
+    (tiles.len(), color_overflow)
 }
 
 fn quantize_rgb333(
@@ -385,6 +3618,47 @@ fn quantize_channel_with_levels(value: u8, levels: u8) -> u8 {
     ((level / max) * 255.0).round() as u8
 }
 
+/// Precision of a high bit depth image below its 8-bit values once the curve is applied:
+/// the curve taken between its LUT entries on the exact value, minus the curved 8-bit value.
+/// None for an 8-bit image.
+fn precision_residuals(image: &DynamicImage, curve_lut: &[u8]) -> Option<Vec<[f32; 3]>> {
+    if !is_high_bit_depth(image) {
+        return None;
+    }
+    let curve = |value: f32| -> f32 {
+        if curve_lut.len() != 256 {
+            return value;
+        }
+        let low = value.floor().clamp(0.0, 255.0) as usize;
+        let high = (low + 1).min(255);
+        let t = value - low as f32;
+        curve_lut[low] as f32 * (1.0 - t) + curve_lut[high] as f32 * t
+    };
+    let rounded = apply_curve_lut(&image.to_rgba8(), curve_lut);
+    let exact = image.to_rgba32f();
+    Some(
+        exact
+            .pixels()
+            .zip(rounded.pixels())
+            .map(|(exact, rounded)| [0, 1, 2].map(|c| curve(exact.0[c].clamp(0.0, 1.0) * 255.0) - rounded.0[c] as f32))
+            .collect(),
+    )
+}
+
+/// Quantize the opaque pixels again from their exact values (8-bit values plus residuals)
+fn requantize_with_residuals(quantized: &mut RgbaImage, source: &RgbaImage, residuals: &[[f32; 3]]) {
+    let max = levels_from_palette(0).saturating_sub(1) as f32;
+    for ((pixel, original), residual) in quantized.pixels_mut().zip(source.pixels()).zip(residuals) {
+        if original.0[3] == 0 {
+            continue;
+        }
+        for ((channel, &exact), error) in pixel.0.iter_mut().zip(original.0.iter()).zip(residual) {
+            let value = (exact as f32 + error).clamp(0.0, 255.0);
+            *channel = (((value / 255.0) * max).round() / max * 255.0).round() as u8;
+        }
+    }
+}
+
 fn apply_curve_lut(image: &RgbaImage, lut: &[u8]) -> RgbaImage {
     let mut output = image.clone();
 
@@ -433,6 +3707,7 @@ impl ColorMap for Rgb333Map {
     }
 }
 
+#[derive(Clone)]
 struct TilePaletteResult {
     palettes: Vec<Vec<String>>,
     tile_palette_map: Vec<usize>,
@@ -589,86 +3864,840 @@ fn build_palettes_for_tiles(
                     tile_palette_map[tile_index] = palette_index;
                 }
             }
-        }
+        }
+
+        // Rebuild palettes from assigned non-empty tiles only
+        clusters = rebuild_clusters_with_frequency_filtered(
+            &tile_infos,
+            &tile_palette_map,
+            &empty_tiles,
+            palette_slots,
+            &global_color0,
+            seed,
+        );
+
+        // Log iteration state
+        log_content.push_str(&format!("--- Iteration {} ---\n", iteration + 1));
+
+        // Count tiles per palette
+        let mut palette_tile_counts = vec![0usize; palette_slots];
+        for &p in tile_palette_map.iter() {
+            if p < palette_slots {
+                palette_tile_counts[p] += 1;
+            }
+        }
+
+        for (i, cluster) in clusters.iter().enumerate() {
+            if palette_tile_counts[i] > 0 || (cluster.len() > 1 || (cluster.len() == 1 && cluster[0] != global_color0)) {
+                log_content.push_str(&format!("  Palette {} ({} tiles): {} colors\n", i, palette_tile_counts[i], cluster.len()));
+                // Log first 8 colors of each palette
+                let preview: Vec<&str> = cluster.iter().take(8).map(|s| s.as_str()).collect();
+                log_content.push_str(&format!("    Colors: {:?}\n", preview));
+            }
+        }
+        log_content.push_str("\n");
+    }
+
+    // Write log file
+    let _ = std::fs::write(&log_path, &log_content);
+    eprintln!("Clustering log written to: {:?}", log_path);
+
+    let mut palette_colors = Vec::new();
+    let mut palettes = Vec::new();
+    for cluster in clusters.iter_mut() {
+        // Remove color0 before sorting to ensure it stays at position 0
+        cluster.retain(|c| c != &global_color0);
+        cluster.sort();
+        cluster.dedup();
+        // Truncate to 15 colors (leaving room for color0 at position 0)
+        if cluster.len() > 15 {
+            cluster.truncate(15);
+        }
+        // Always insert color0 at position 0
+        cluster.insert(0, global_color0.clone());
+
+        palette_colors.push(cluster.clone());
+        let mut padded = cluster.clone();
+        while padded.len() < 16 {
+            padded.push(global_color0.clone());
+        }
+        palettes.push(padded);
+    }
+
+    while palettes.len() < 16 {
+        palettes.push(vec![global_color0.clone(); 16]);
+        palette_colors.push(vec![global_color0.clone()]);
+    }
+
+    // Compact palettes: move unused palettes to the end
+    // Always compact - palettes with 0 tiles should be at the end regardless of constraints
+    let (palettes, palette_colors, tile_palette_map) = compact_palettes(
+        palettes,
+        palette_colors,
+        tile_palette_map,
+        &global_color0,
+    );
+
+    Ok(TilePaletteResult {
+        palettes,
+        tile_palette_map,
+        palette_colors,
+        empty_tiles,
+    })
+}
+
+/// PCE rules broken by one tile of the lossless path
+#[derive(Serialize)]
+struct TileViolation {
+    tile_x: u32,
+    tile_y: u32,
+    messages: Vec<String>,
+}
+
+/// Check the PCE rules exactly on every tile: opaque or fully transparent pixels, RGB333
+/// colors, and at most 15 colors besides color0 (transparent pixels count as color0)
+fn validate_pce_tiles(image: &RgbaImage, background_color: &str) -> Vec<TileViolation> {
+    use std::collections::HashSet;
+
+    let color0 = quantized_background(background_color);
+    let mut violations = Vec::new();
+    for tile_y in 0..image.height() / 8 {
+        for tile_x in 0..image.width() / 8 {
+            let mut partial_alpha = 0;
+            let mut off_grid = 0;
+            let mut first_off_grid = None;
+            let mut colors = HashSet::new();
+            for y in 0..8 {
+                for x in 0..8 {
+                    let pixel = image.get_pixel(tile_x * 8 + x, tile_y * 8 + y);
+                    match pixel.0[3] {
+                        0 => continue,
+                        255 => {}
+                        _ => partial_alpha += 1,
+                    }
+                    let mut quantized = *pixel;
+                    Rgb333Map { levels: 8 }.map_color(&mut quantized);
+                    if quantized.0[..3] != pixel.0[..3] {
+                        off_grid += 1;
+                        first_off_grid.get_or_insert([pixel.0[0], pixel.0[1], pixel.0[2]]);
+                    }
+                    if quantized != color0 {
+                        colors.insert([quantized.0[0], quantized.0[1], quantized.0[2]]);
+                    }
+                }
+            }
+
+            let mut messages = Vec::new();
+            if partial_alpha > 0 {
+                messages.push(format!("{} pixels semi-transparents", partial_alpha));
+            }
+            if let Some([r, g, b]) = first_off_grid {
+                messages.push(format!("{} pixels hors RGB333 (ex. #{:02X}{:02X}{:02X})", off_grid, r, g, b));
+            }
+            if colors.len() > 15 {
+                messages.push(format!("{} couleurs en plus de la couleur 0 (15 au plus)", colors.len()));
+            }
+            if !messages.is_empty() {
+                violations.push(TileViolation { tile_x, tile_y, messages });
+            }
+        }
+    }
+    violations
+}
+
+/// Max search steps of the exact palette cover before giving up
+const EXACT_COVER_MAX_STEPS: usize = 2_000_000;
+
+/// Palettes holding every color of every tile, for art that passed validate_pce_tiles.
+/// Forced palette groups keep their palette; other tiles go where the search puts them.
+fn exact_tile_palettes(
+    image: &RgbaImage,
+    palette_count: usize,
+    background_color: &str,
+    constraints: &[i32],  // -1 = auto, 0-15 = forced group
+) -> Result<TilePaletteResult, String> {
+    use std::collections::HashMap;
+
+    let palette_slots = palette_count.clamp(1, 16);
+    let color0 = quantized_background(background_color);
+    let color0_hex = format!("#{:02X}{:02X}{:02X}", color0.0[0], color0.0[1], color0.0[2]);
+
+    // Colors as bits of a 512-bit set (one bit per RGB333 color)
+    let color_bit = |color: &str| {
+        let rgb = parse_hex_color(color).unwrap_or(color0);
+        let level = |value: u8| (value as f32 / RGB333_STEP).round() as usize;
+        (level(rgb.0[0]) << 6) | (level(rgb.0[1]) << 3) | level(rgb.0[2])
+    };
+    let tile_infos = extract_tile_colors_with_frequency(image);
+    let tile_sets: Vec<ColorSet> = tile_infos
+        .iter()
+        .map(|info| {
+            let mut set = ColorSet::default();
+            for color in info.colors.iter().filter(|c| **c != color0_hex) {
+                set.insert(color_bit(color));
+            }
+            set
+        })
+        .collect();
+    let mut names: HashMap<usize, String> = HashMap::new();
+    for info in &tile_infos {
+        for color in &info.colors {
+            names.insert(color_bit(color), color.clone());
+        }
+    }
+
+    // Forced groups start their palette with the colors of their tiles
+    let mut palettes = vec![ColorSet::default(); palette_slots];
+    let mut forced = vec![None; tile_sets.len()];
+    for (index, set) in tile_sets.iter().enumerate() {
+        let group = constraints.get(index).copied().unwrap_or(-1);
+        if group >= 0 && !set.is_empty() {
+            let group = group as usize;
+            if group >= palette_slots {
+                return Err(format!("Groupe de palettes {} au-delà des {} palettes", group, palette_slots));
+            }
+            palettes[group] = palettes[group].union(set);
+            forced[index] = Some(group);
+        }
+    }
+    if let Some(group) = palettes.iter().position(|palette| palette.len() > 15) {
+        return Err(format!("Le groupe de palettes {} demande plus de 15 couleurs", group));
+    }
+
+    // Distinct free color sets, largest first; sets included in another one follow it
+    let mut free_sets: Vec<ColorSet> = tile_sets
+        .iter()
+        .zip(forced.iter())
+        .filter(|(set, group)| !set.is_empty() && group.is_none())
+        .map(|(set, _)| *set)
+        .collect();
+    free_sets.sort_by(|a, b| b.len().cmp(&a.len()).then(b.bits.cmp(&a.bits)));
+    free_sets.dedup();
+    let maximal: Vec<ColorSet> = free_sets
+        .iter()
+        .enumerate()
+        .filter(|(index, set)| !free_sets[..*index].iter().any(|other| set.is_subset(other)))
+        .map(|(_, set)| *set)
+        .collect();
+
+    let mut steps = 0;
+    if !cover_color_sets(&maximal, &mut palettes, &mut steps) {
+        return Err(if steps >= EXACT_COVER_MAX_STEPS {
+            "Recherche des palettes exactes abandonnée : trop de combinaisons".to_string()
+        } else {
+            format!("Aucune répartition exacte des couleurs en {} palettes", palette_slots)
+        });
+    }
+
+    let empty_tiles: Vec<bool> = tile_sets.iter().map(|set| set.is_empty()).collect();
+    let tile_palette_map: Vec<usize> = tile_sets
+        .iter()
+        .zip(forced.iter())
+        .map(|(set, group)| {
+            group.unwrap_or_else(|| palettes.iter().position(|palette| set.is_subset(palette)).unwrap_or(0))
+        })
+        .collect();
+    let mut palette_colors = Vec::new();
+    let mut padded_palettes = Vec::new();
+    for palette in &palettes {
+        let mut colors: Vec<String> = palette.iter().filter_map(|bit| names.get(&bit).cloned()).collect();
+        colors.sort();
+        colors.insert(0, color0_hex.clone());
+        let mut padded = colors.clone();
+        padded.resize(16, color0_hex.clone());
+        palette_colors.push(colors);
+        padded_palettes.push(padded);
+    }
+    while padded_palettes.len() < 16 {
+        padded_palettes.push(vec![color0_hex.clone(); 16]);
+        palette_colors.push(vec![color0_hex.clone()]);
+    }
+
+    let (palettes, palette_colors, tile_palette_map) =
+        compact_palettes(padded_palettes, palette_colors, tile_palette_map, &color0_hex);
+    Ok(TilePaletteResult {
+        palettes,
+        tile_palette_map,
+        palette_colors,
+        empty_tiles,
+    })
+}
+
+/// Set of RGB333 colors, one bit per color
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+struct ColorSet {
+    bits: [u64; 8],
+}
+
+impl ColorSet {
+    fn insert(&mut self, color: usize) {
+        self.bits[color / 64] |= 1 << (color % 64);
+    }
+
+    fn union(&self, other: &ColorSet) -> ColorSet {
+        let mut bits = self.bits;
+        bits.iter_mut().zip(other.bits.iter()).for_each(|(a, b)| *a |= b);
+        ColorSet { bits }
+    }
+
+    fn len(&self) -> usize {
+        self.bits.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bits.iter().all(|&word| word == 0)
+    }
+
+    fn is_subset(&self, other: &ColorSet) -> bool {
+        self.bits.iter().zip(other.bits.iter()).all(|(a, b)| a & !b == 0)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..512).filter(move |&color| self.bits[color / 64] & (1 << (color % 64)) != 0)
+    }
+}
+
+/// Depth-first search putting every color set in a palette of at most 15 colors. Palettes that
+/// grow the least are tried first; only one of the empty palettes is tried for each set.
+fn cover_color_sets(sets: &[ColorSet], palettes: &mut [ColorSet], steps: &mut usize) -> bool {
+    let Some((set, rest)) = sets.split_first() else {
+        return true;
+    };
+    *steps += 1;
+    if *steps >= EXACT_COVER_MAX_STEPS {
+        return false;
+    }
+
+    let mut candidates: Vec<(usize, usize)> = Vec::new();
+    let mut tried_empty = false;
+    for (index, palette) in palettes.iter().enumerate() {
+        if palette.is_empty() {
+            if tried_empty {
+                continue;
+            }
+            tried_empty = true;
+        }
+        let merged = palette.union(set).len();
+        if merged <= 15 {
+            candidates.push((merged - palette.len(), index));
+        }
+    }
+    candidates.sort();
+
+    for (growth, index) in candidates {
+        let saved = palettes[index];
+        palettes[index] = saved.union(set);
+        if cover_color_sets(rest, palettes, steps) {
+            return true;
+        }
+        palettes[index] = saved;
+        // A palette already holding the set is the best possible choice
+        if growth == 0 || *steps >= EXACT_COVER_MAX_STEPS {
+            break;
+        }
+    }
+    false
+}
+
+/// Palettes for the whole image, or per band when band_rows > 0 (see build_band_palettes)
+fn build_frame_palettes(
+    image: &RgbaImage,
+    palette_count: usize,
+    background_color: &str,
+    constraints: &[i32],
+    seed: u64,
+    band_rows: u32,
+    max_changes: usize,
+) -> Result<(TilePaletteResult, Vec<usize>), String> {
+    if band_rows > 0 {
+        build_band_palettes(image, palette_count, background_color, constraints, seed, band_rows, max_changes)
+    } else {
+        Ok((build_palettes_for_tiles(image, palette_count, background_color, constraints, seed)?, Vec::new()))
+    }
+}
+
+/// Build palettes per horizontal band of `band_rows` tile rows, for raster palette changes.
+/// Each band's palettes are aligned on the previous band (slot matching, colors kept at the
+/// same index) so that at most `max_changes` palette entries change between two bands; the
+/// least used extra changes are dropped and the band's tiles reassigned.
+/// The result uses "virtual" palette indices: band * 16 + palette slot.
+/// Returns the palettes and the number of palette entries changed at each band.
+fn build_band_palettes(
+    image: &RgbaImage,
+    palette_count: usize,
+    background_color: &str,
+    constraints: &[i32],
+    seed: u64,
+    band_rows: u32,
+    max_changes: usize,
+) -> Result<(TilePaletteResult, Vec<usize>), String> {
+    use std::collections::{HashMap, HashSet};
+
+    let (width, height) = image.dimensions();
+    let tiles_x = width / 8;
+    let tiles_y = height / 8;
+    let band_rows = band_rows.max(1);
+    let color0 = parse_hex_color(background_color)
+        .map(|color| format!("#{:02X}{:02X}{:02X}", color.0[0], color.0[1], color.0[2]))
+        .unwrap_or_else(|| "#000000".to_string());
+
+    let mut result = TilePaletteResult {
+        palettes: Vec::new(),
+        tile_palette_map: Vec::new(),
+        palette_colors: Vec::new(),
+        empty_tiles: Vec::new(),
+    };
+    let mut band_changes = Vec::new();
+
+    // Start from the palettes of the whole image: each band then adapts up to max_changes
+    // entries of the previous band's palettes to its own colors
+    let mut previous = build_palettes_for_tiles(image, palette_count, background_color, constraints, seed)?.palettes;
+
+    let mut first_row = 0;
+    while first_row < tiles_y {
+        let rows = band_rows.min(tiles_y - first_row);
+        let band_index = band_changes.len();
+        let band = image::imageops::crop_imm(image, 0, first_row * 8, width, rows * 8).to_image();
+        let first_tile = (first_row * tiles_x) as usize;
+        let last_tile = ((first_row + rows) * tiles_x) as usize;
+        let band_constraints = constraints.get(first_tile..last_tile.min(constraints.len())).unwrap_or(&[]);
+        let built = build_palettes_for_tiles(&band, palette_count, background_color, band_constraints, seed)?;
+
+        let (entries, tile_map, changes) = {
+            // Pixel count of every color per built palette, to rank the changes
+            let tile_infos = extract_tile_colors_with_frequency(&band);
+            let mut usage: Vec<HashMap<String, usize>> = vec![HashMap::new(); built.palette_colors.len()];
+            for (tile_idx, info) in tile_infos.iter().enumerate() {
+                if built.empty_tiles.get(tile_idx).copied().unwrap_or(false) {
+                    continue;
+                }
+                let palette_idx = built.tile_palette_map.get(tile_idx).copied().unwrap_or(0);
+                if let Some(counts) = usage.get_mut(palette_idx) {
+                    for (color, count) in info.color_counts.iter() {
+                        *counts.entry(color.clone()).or_insert(0) += count;
+                    }
+                }
+            }
+
+            // Match every built palette to the previous slot sharing the most colors
+            let mut pairs: Vec<(usize, usize, usize)> = Vec::new();
+            for (k, colors) in built.palette_colors.iter().enumerate() {
+                for (slot, prev) in previous.iter().enumerate() {
+                    let overlap = colors.iter().filter(|c| **c != color0 && prev.contains(c)).count();
+                    pairs.push((overlap, k, slot));
+                }
+            }
+            pairs.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+            let mut slot_of = vec![usize::MAX; built.palette_colors.len()];
+            let mut taken = vec![false; previous.len()];
+            for (_, k, slot) in pairs {
+                if slot_of[k] == usize::MAX && !taken[slot] {
+                    slot_of[k] = slot;
+                    taken[slot] = true;
+                }
+            }
+
+            // Place the missing colors on entries the band does not need, starting from the previous colors
+            let mut entries = previous.clone();
+            let mut candidates: Vec<(usize, usize, usize, String)> = Vec::new(); // (pixels, slot, index, color)
+            for (k, colors) in built.palette_colors.iter().enumerate() {
+                let slot = slot_of[k];
+                if slot == usize::MAX {
+                    continue;
+                }
+                let needed: HashSet<&String> = colors.iter().filter(|c| **c != color0).collect();
+                let mut free = (1..16).filter(|&i| !needed.contains(&entries[slot][i]));
+                for color in colors.iter().filter(|c| **c != color0) {
+                    if entries[slot][1..].contains(color) {
+                        continue;
+                    }
+                    if let Some(index) = free.next() {
+                        let pixels = usage[k].get(color).copied().unwrap_or(0);
+                        candidates.push((pixels, slot, index, color.clone()));
+                    }
+                }
+            }
+
+            // Keep the most used changes within the budget
+            candidates.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+            let dropped = candidates.len() > max_changes;
+            candidates.truncate(max_changes);
+            for (_, slot, index, color) in candidates.iter() {
+                entries[*slot][*index] = color.clone();
+            }
+
+            // Remap the tiles; when changes were dropped, pick the best palette again
+            let tile_map: Vec<usize> = if dropped {
+                tile_infos
+                    .iter()
+                    .enumerate()
+                    .map(|(tile_idx, info)| {
+                        if built.empty_tiles.get(tile_idx).copied().unwrap_or(false) {
+                            0
+                        } else {
+                            best_cluster_for_tile(&entries, &info.colors, &color0)
+                        }
+                    })
+                    .collect()
+            } else {
+                built
+                    .tile_palette_map
+                    .iter()
+                    .map(|&k| slot_of.get(k).copied().filter(|&slot| slot != usize::MAX).unwrap_or(0))
+                    .collect()
+            };
+            (entries, tile_map, candidates.len())
+        };
+
+        for palette in entries.iter() {
+            let mut colors: Vec<String> = Vec::new();
+            for color in palette.iter() {
+                if !colors.contains(color) {
+                    colors.push(color.clone());
+                }
+            }
+            result.palette_colors.push(colors);
+        }
+        result.palettes.extend(entries.iter().cloned());
+        result.tile_palette_map.extend(tile_map.iter().map(|&slot| band_index * 16 + slot));
+        result.empty_tiles.extend(built.empty_tiles.iter().copied());
+        // The first band is loaded up front, only later bands cost raster changes
+        band_changes.push(if band_index == 0 { 0 } else { changes });
+
+        previous = entries;
+        first_row += rows;
+    }
+
+    Ok((result, band_changes))
+}
+
+/// Palette entries reloaded on one raster line: (VCE color index, RGB333 word)
+type PaletteChanges = Vec<(u16, u16)>;
+
+/// Palette entries to reload at the start of each band after the first one:
+/// (scanline, changes). Palettes are grouped by 16 per band.
+fn palette_raster_changes(palettes: &[Vec<String>], band_rows: u32, first_line: u32) -> Vec<(u16, PaletteChanges)> {
+    let band_count = palettes.len() / 16;
+    let mut table = Vec::new();
+    for band in 1..band_count {
+        let mut changes = Vec::new();
+        for slot in 0..16 {
+            let current = &palettes[band * 16 + slot];
+            let before = &palettes[(band - 1) * 16 + slot];
+            for index in 0..16 {
+                let word = current.get(index).map(|c| color_to_pce_word(c)).unwrap_or(0);
+                let previous_word = before.get(index).map(|c| color_to_pce_word(c)).unwrap_or(0);
+                if word != previous_word {
+                    changes.push(((slot * 16 + index) as u16, word));
+                }
+            }
+        }
+        let line = first_line + band as u32 * band_rows * 8;
+        table.push((line as u16, changes));
+    }
+    table
+}
+
+/// Palettes and tiles used by each raster band of a converted frame
+fn raster_band_usage(
+    bands: &[RasterBand],
+    palette_result: &TilePaletteResult,
+    tile_to_unique: &[usize],
+    tiles_x: u32,
+) -> Vec<RasterBandUsage> {
+    use std::collections::HashSet;
+
+    bands
+        .iter()
+        .map(|band| {
+            let mut palettes: Vec<usize> = Vec::new();
+            let mut unique: HashSet<usize> = HashSet::new();
+            let mut tile_count = 0;
+            let first_tile = (band.start_line / 8 * tiles_x) as usize;
+            let last_tile = ((band.end_line.div_ceil(8) * tiles_x) as usize).min(tile_to_unique.len());
+            for (tile_idx, &unique_idx) in tile_to_unique.iter().enumerate().take(last_tile).skip(first_tile) {
+                if palette_result.empty_tiles.get(tile_idx).copied().unwrap_or(false) {
+                    continue;
+                }
+                tile_count += 1;
+                unique.insert(unique_idx);
+                let slot = palette_result.tile_palette_map.get(tile_idx).copied().unwrap_or(0) % 16;
+                if !palettes.contains(&slot) {
+                    palettes.push(slot);
+                }
+            }
+            palettes.sort_unstable();
+            RasterBandUsage {
+                start_line: band.start_line,
+                end_line: band.end_line,
+                palettes,
+                tile_count,
+                unique_tile_count: unique.len(),
+            }
+        })
+        .collect()
+}
 
-        // Rebuild palettes from assigned non-empty tiles only
-        clusters = rebuild_clusters_with_frequency_filtered(
-            &tile_infos,
-            &tile_palette_map,
-            &empty_tiles,
-            palette_slots,
-            &global_color0,
-            seed,
-        );
+/// Horizontal screen band of a raster split: from start_line to end_line (exclusive, image lines),
+/// displayed with its own scroll values and laid out at its own place in the BAT
+#[derive(Clone)]
+struct RasterBand {
+    start_line: u32,
+    end_line: u32,
+    scroll_x: i32,
+    scroll_y: i32,
+    bat_x: Option<u32>,  // BAT column of the band content (None = image offset)
+    bat_y: Option<u32>,  // BAT row of the band content (None = where the image offset puts it)
+}
 
-        // Log iteration state
-        log_content.push_str(&format!("--- Iteration {} ---\n", iteration + 1));
+impl RasterBand {
+    /// BAT position (in tiles) of the band content for an image placed at (offset_x, offset_y)
+    fn bat_origin(&self, offset_x: u32, offset_y: u32) -> (u32, u32) {
+        (
+            self.bat_x.unwrap_or(offset_x),
+            self.bat_y.unwrap_or(offset_y + self.start_line / 8),
+        )
+    }
 
-        // Count tiles per palette
-        let mut palette_tile_counts = vec![0usize; palette_slots];
-        for &p in tile_palette_map.iter() {
-            if p < palette_slots {
-                palette_tile_counts[p] += 1;
-            }
-        }
+    /// BXR/BYR values that show the first line of the band content on its first screen line
+    /// (the VDC restarts counting BAT lines from BYR when it is rewritten mid-frame)
+    fn scroll_registers(&self, offset_x: u32, offset_y: u32) -> (u16, u16) {
+        let (bat_x, bat_y) = self.bat_origin(offset_x, offset_y);
+        let bxr = (bat_x as i32 * 8 + self.scroll_x) & 0x3FF;
+        let byr = (bat_y as i32 * 8 + self.scroll_y) & 0x1FF;
+        (bxr as u16, byr as u16)
+    }
+}
 
-        for (i, cluster) in clusters.iter().enumerate() {
-            if palette_tile_counts[i] > 0 || (cluster.len() > 1 || (cluster.len() == 1 && cluster[0] != global_color0)) {
-                log_content.push_str(&format!("  Palette {} ({} tiles): {} colors\n", i, palette_tile_counts[i], cluster.len()));
-                // Log first 8 colors of each palette
-                let preview: Vec<&str> = cluster.iter().take(8).map(|s| s.as_str()).collect();
-                log_content.push_str(&format!("    Colors: {:?}\n", preview));
+/// Parse raster band declarations, one band per line: "start_line,scroll_x,scroll_y[,bat_x,bat_y]".
+/// Empty lines and lines starting with '#' are ignored. A band starting at line 0 is added when missing.
+fn parse_raster_bands(text: &str, image_height: u32) -> Result<Vec<RasterBand>, String> {
+    let mut bands: Vec<RasterBand> = Vec::new();
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+        if fields.len() != 3 && fields.len() != 5 {
+            return Err(format!("Bande raster ligne {} : 3 ou 5 valeurs attendues", line_number + 1));
+        }
+        let number = |index: usize| -> Result<i32, String> {
+            fields[index]
+                .parse::<i32>()
+                .map_err(|_| format!("Bande raster ligne {} : valeur invalide '{}'", line_number + 1, fields[index]))
+        };
+        let start_line = number(0)?;
+        if start_line < 0 || start_line % 8 != 0 || start_line as u32 >= image_height {
+            return Err(format!(
+                "Bande raster ligne {} : la ligne de départ doit être un multiple de 8 dans l'image",
+                line_number + 1
+            ));
+        }
+        let (bat_x, bat_y) = if fields.len() == 5 {
+            let (bat_x, bat_y) = (number(3)?, number(4)?);
+            if bat_x < 0 || bat_y < 0 {
+                return Err(format!("Bande raster ligne {} : position BAT négative", line_number + 1));
+            }
+            (Some(bat_x as u32), Some(bat_y as u32))
+        } else {
+            (None, None)
+        };
+        if let Some(last) = bands.last() {
+            if start_line as u32 <= last.start_line {
+                return Err(format!("Bande raster ligne {} : les bandes doivent être croissantes", line_number + 1));
             }
         }
-        log_content.push_str("\n");
+        bands.push(RasterBand {
+            start_line: start_line as u32,
+            end_line: image_height,
+            scroll_x: number(1)?,
+            scroll_y: number(2)?,
+            bat_x,
+            bat_y,
+        });
     }
 
-    // Write log file
-    let _ = std::fs::write(&log_path, &log_content);
-    eprintln!("Clustering log written to: {:?}", log_path);
+    if bands.is_empty() {
+        return Ok(bands);
+    }
+    if bands[0].start_line > 0 {
+        bands.insert(0, RasterBand { start_line: 0, end_line: image_height, scroll_x: 0, scroll_y: 0, bat_x: None, bat_y: None });
+    }
+    for index in 1..bands.len() {
+        bands[index - 1].end_line = bands[index].start_line;
+    }
+    Ok(bands)
+}
 
-    let mut palette_colors = Vec::new();
-    let mut palettes = Vec::new();
-    for cluster in clusters.iter_mut() {
-        // Remove color0 before sorting to ensure it stays at position 0
-        cluster.retain(|c| c != &global_color0);
-        cluster.sort();
-        cluster.dedup();
-        // Truncate to 15 colors (leaving room for color0 at position 0)
-        if cluster.len() > 15 {
-            cluster.truncate(15);
+/// BAT word of an image tile: palette slot << 12 | tile VRAM address >> 4.
+/// Cells outside the image (None) and empty tiles use the empty tile with palette 0.
+fn bat_entry_word(
+    cell: Option<usize>,
+    tile_to_unique: &[usize],
+    tile_palette_map: &[usize],
+    empty_tiles: &[bool],
+    vram_base_address: u32,
+) -> u16 {
+    let (unique_idx, palette_idx) = if let Some(tile_idx) = cell {
+        let uid = tile_to_unique.get(tile_idx).copied().unwrap_or(0);
+        // Per-band palettes use band * 16 + slot, the BAT only holds the slot
+        let pid = if empty_tiles.get(tile_idx).copied().unwrap_or(false) {
+            0u16
+        } else {
+            (tile_palette_map.get(tile_idx).copied().unwrap_or(0) % 16) as u16
+        };
+        (uid, pid)
+    } else {
+        (0, 0u16)
+    };
+
+    // VRAM is word-addressed (16-bit), each tile = 16 words (32 bytes)
+    // BAT address field = (tile_word_address >> 4) & 0x0FFF
+    let tile_address = vram_base_address + (unique_idx as u32 * 16);
+    let address_field = ((tile_address >> 4) & 0x0FFF) as u16;
+    (palette_idx << 12) | address_field
+}
+
+/// The BAT address field holds 12 bits: every unique tile must lie below $8000 (words) or the
+/// BAT and map words would point at other tiles
+fn check_tiles_vram(vram_base_address: u32, unique_tile_count: usize) -> Result<(), String> {
+    let end = vram_base_address as u64 + unique_tile_count as u64 * 16;
+    if end > 0x8000 {
+        return Err(format!(
+            "Les {} tuiles uniques dépassent la VRAM : ${:04X} + {} mots > $8000",
+            unique_tile_count,
+            vram_base_address,
+            unique_tile_count * 16
+        ));
+    }
+    Ok(())
+}
+
+/// Whether a large map export is requested: "" or "none" = regular BAT only,
+/// "columns" or "rows" = full map streamed in that order
+fn parse_map_order(map_order: &str, bands: &[RasterBand]) -> Result<Option<bool>, String> {
+    let by_columns = match map_order {
+        "" | "none" => return Ok(None),
+        "columns" => true,
+        "rows" => false,
+        _ => return Err(format!("Ordre de carte inconnu : {}", map_order)),
+    };
+    if !bands.is_empty() {
+        return Err("Les bandes raster ne sont pas disponibles avec une grande carte".to_string());
+    }
+    Ok(Some(by_columns))
+}
+
+/// Full-size map of BAT words for a scroll engine: a header (width, height in tiles), then
+/// column by column (top to bottom) or row by row (left to right). Map column x goes to BAT
+/// column x % bat_width, map row y to BAT row y % bat_height.
+fn map_stream_words(
+    tiles_x: u32,
+    tiles_y: u32,
+    by_columns: bool,
+    tile_to_unique: &[usize],
+    tile_palette_map: &[usize],
+    empty_tiles: &[bool],
+    vram_base_address: u32,
+) -> Vec<u16> {
+    let mut words = Vec::with_capacity((tiles_x * tiles_y + 2) as usize);
+    words.push(tiles_x as u16);
+    words.push(tiles_y as u16);
+    let (outer, inner) = if by_columns { (tiles_x, tiles_y) } else { (tiles_y, tiles_x) };
+    for line in 0..outer {
+        for position in 0..inner {
+            let (tile_x, tile_y) = if by_columns { (line, position) } else { (position, line) };
+            let cell = Some((tile_y * tiles_x + tile_x) as usize);
+            words.push(bat_entry_word(cell, tile_to_unique, tile_palette_map, empty_tiles, vram_base_address));
         }
-        // Always insert color0 at position 0
-        cluster.insert(0, global_color0.clone());
+    }
+    words
+}
 
-        palette_colors.push(cluster.clone());
-        let mut padded = cluster.clone();
-        while padded.len() < 16 {
-            padded.push(global_color0.clone());
+/// Image tile shown by each BAT cell (row-major), None outside the image.
+/// Without bands the image is placed at the offset and clipped; with bands each band is
+/// laid out at its own BAT position and must fit without overlapping another band.
+fn layout_bat(
+    tiles_x: u32,
+    tiles_y: u32,
+    bat_width: u32,
+    bat_height: u32,
+    offset_x: u32,
+    offset_y: u32,
+    bands: &[RasterBand],
+) -> Result<Vec<Option<usize>>, String> {
+    let mut cells: Vec<Option<usize>> = vec![None; (bat_width * bat_height) as usize];
+
+    if bands.is_empty() {
+        for tile_y in 0..tiles_y {
+            for tile_x in 0..tiles_x {
+                let (bat_x, bat_y) = (tile_x + offset_x, tile_y + offset_y);
+                if bat_x < bat_width && bat_y < bat_height {
+                    cells[(bat_y * bat_width + bat_x) as usize] = Some((tile_y * tiles_x + tile_x) as usize);
+                }
+            }
         }
-        palettes.push(padded);
+        return Ok(cells);
     }
 
-    while palettes.len() < 16 {
-        palettes.push(vec![global_color0.clone(); 16]);
-        palette_colors.push(vec![global_color0.clone()]);
+    for (band_index, band) in bands.iter().enumerate() {
+        let (origin_x, origin_y) = band.bat_origin(offset_x, offset_y);
+        let first_row = band.start_line / 8;
+        let last_row = band.end_line.div_ceil(8).min(tiles_y);
+        if origin_x + tiles_x > bat_width || origin_y + (last_row - first_row) > bat_height {
+            return Err(format!("Bande raster {} : le contenu dépasse de la BAT", band_index));
+        }
+        for tile_y in first_row..last_row {
+            for tile_x in 0..tiles_x {
+                let cell = ((origin_y + tile_y - first_row) * bat_width + origin_x + tile_x) as usize;
+                if cells[cell].is_some() {
+                    return Err(format!("Bande raster {} : chevauche une autre bande dans la BAT", band_index));
+                }
+                cells[cell] = Some((tile_y * tiles_x + tile_x) as usize);
+            }
+        }
     }
+    Ok(cells)
+}
 
-    // Compact palettes: move unused palettes to the end
-    // Always compact - palettes with 0 tiles should be at the end regardless of constraints
-    let (palettes, palette_colors, tile_palette_map) = compact_palettes(
-        palettes,
-        palette_colors,
-        tile_palette_map,
-        &global_color0,
-    );
+/// Line-indexed raster table merging band scroll values and per-band palette reloads:
+/// (screen line, BXR, BYR, palette changes). The line 0 entry holds the initial scroll.
+fn raster_split_table(
+    bands: &[RasterBand],
+    palettes: &[Vec<String>],
+    palette_band_rows: u32,
+    offset_x: u32,
+    offset_y: u32,
+) -> Vec<(u16, u16, u16, PaletteChanges)> {
+    let palette_changes = if palette_band_rows > 0 && palettes.len() > 16 {
+        palette_raster_changes(palettes, palette_band_rows, 0)
+    } else {
+        Vec::new()
+    };
 
-    Ok(TilePaletteResult {
-        palettes,
-        tile_palette_map,
-        palette_colors,
-        empty_tiles,
-    })
+    let mut lines: Vec<u16> = bands.iter().map(|band| band.start_line as u16).collect();
+    lines.extend(palette_changes.iter().map(|(line, _)| *line));
+    lines.sort_unstable();
+    lines.dedup();
+
+    lines
+        .into_iter()
+        .map(|line| {
+            let band = bands
+                .iter()
+                .rev()
+                .find(|band| band.start_line <= line as u32)
+                .unwrap_or(&bands[0]);
+            let (bxr, byr) = band.scroll_registers(offset_x, offset_y);
+            let changes = palette_changes
+                .iter()
+                .find(|(change_line, _)| *change_line == line)
+                .map(|(_, changes)| changes.clone())
+                .unwrap_or_default();
+            (line, bxr, byr, changes)
+        })
+        .collect()
 }
 
 /// Compact palettes by moving unused/empty ones to the end.
@@ -1104,19 +5133,385 @@ fn apply_tile_palettes(
     image: &RgbaImage,
     palette_result: &TilePaletteResult,
 ) -> Result<RgbaImage, String> {
-    apply_tile_palettes_with_dither(image, palette_result, "none")
+    apply_tile_palettes_with_dither(image, palette_result, &DitherOptions::none(), None, None)
+}
+
+/// Dithering settings passed from the UI to apply_tile_palettes_with_dither
+#[derive(Deserialize, Clone)]
+struct DitherOptions {
+    mode: String,      // "none", "ordered" or an error-diffusion kernel (see diffusion_kernel)
+    serpentine: bool,  // Alternate scan direction on every row (error diffusion only)
+    strength: f32,     // Fraction of the quantization error that is diffused (1.0 = full)
+    cross_tile: bool,  // Let the diffused error flow across tile boundaries
+    error_clamp: f32,  // Max error per channel a pixel receives from neighboring tiles (cross_tile only)
+    ordered_matrix: String,  // Threshold matrix for ordered dithering (see threshold_matrix)
+    ordered_spread: f32,     // Amplitude of the ordered threshold (0 = derived from the RGB333 step)
+    ordered_align: String,   // "tile" (pattern restarts in each tile) or "global" (image pixel grid)
+    #[serde(skip)]
+    linear_light: bool,      // Measure and diffuse the error in linear light (error diffusion only, from TargetSettings)
+}
+
+impl DitherOptions {
+    fn none() -> Self {
+        DitherOptions {
+            mode: "none".to_string(),
+            serpentine: false,
+            strength: 1.0,
+            cross_tile: false,
+            error_clamp: 0.0,
+            ordered_matrix: "bayer8".to_string(),
+            ordered_spread: 32.0,
+            ordered_align: "tile".to_string(),
+            linear_light: false,
+        }
+    }
+
+    /// Color (0-255) in the space where the diffused error is measured
+    fn error_space(&self, color: [f32; 3]) -> [f32; 3] {
+        if self.linear_light {
+            color.map(|value| srgb_to_linear(value / 255.0) * 255.0)
+        } else {
+            color
+        }
+    }
+
+    /// Back from the error space to sRGB values for the palette matching
+    fn to_display_space(&self, color: [f32; 3]) -> [f32; 3] {
+        if self.linear_light {
+            color.map(|value| linear_to_srgb(value / 255.0) * 255.0)
+        } else {
+            color
+        }
+    }
+}
+
+/// Error-diffusion kernel: (dx, dy, weight) taps relative to the current pixel, in scan direction
+struct DiffusionKernel {
+    taps: &'static [(i32, i32, f32)],
+    divisor: f32,
+}
+
+fn diffusion_kernel(mode: &str) -> Option<DiffusionKernel> {
+    let (taps, divisor): (&'static [(i32, i32, f32)], f32) = match mode {
+        "floyd" => (&[(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)], 16.0),
+        // Atkinson only diffuses 6/8 of the error, which keeps highlights and shadows clean
+        "atkinson" => (
+            &[(1, 0, 1.0), (2, 0, 1.0), (-1, 1, 1.0), (0, 1, 1.0), (1, 1, 1.0), (0, 2, 1.0)],
+            8.0,
+        ),
+        "sierra" => (
+            &[
+                (1, 0, 5.0), (2, 0, 3.0),
+                (-2, 1, 2.0), (-1, 1, 4.0), (0, 1, 5.0), (1, 1, 4.0), (2, 1, 2.0),
+                (-1, 2, 2.0), (0, 2, 3.0), (1, 2, 2.0),
+            ],
+            32.0,
+        ),
+        "sierra2" => (
+            &[
+                (1, 0, 4.0), (2, 0, 3.0),
+                (-2, 1, 1.0), (-1, 1, 2.0), (0, 1, 3.0), (1, 1, 2.0), (2, 1, 1.0),
+            ],
+            16.0,
+        ),
+        "sierra-lite" => (&[(1, 0, 2.0), (-1, 1, 1.0), (0, 1, 1.0)], 4.0),
+        "jjn" => (
+            &[
+                (1, 0, 7.0), (2, 0, 5.0),
+                (-2, 1, 3.0), (-1, 1, 5.0), (0, 1, 7.0), (1, 1, 5.0), (2, 1, 3.0),
+                (-2, 2, 1.0), (-1, 2, 3.0), (0, 2, 5.0), (1, 2, 3.0), (2, 2, 1.0),
+            ],
+            48.0,
+        ),
+        "stucki" => (
+            &[
+                (1, 0, 8.0), (2, 0, 4.0),
+                (-2, 1, 2.0), (-1, 1, 4.0), (0, 1, 8.0), (1, 1, 4.0), (2, 1, 2.0),
+                (-2, 2, 1.0), (-1, 2, 2.0), (0, 2, 4.0), (1, 2, 2.0), (2, 2, 1.0),
+            ],
+            42.0,
+        ),
+        "burkes" => (
+            &[
+                (1, 0, 8.0), (2, 0, 4.0),
+                (-2, 1, 2.0), (-1, 1, 4.0), (0, 1, 8.0), (1, 1, 4.0), (2, 1, 2.0),
+            ],
+            32.0,
+        ),
+        _ => return None,
+    };
+    Some(DiffusionKernel { taps, divisor })
+}
+
+/// Distance between two RGB333 levels on the 0-255 scale
+const RGB333_STEP: f32 = 255.0 / 7.0;
+
+// Bayer matrices for ordered dithering (values 0..n*n-1, normalized by threshold_matrix)
+const BAYER_2X2: [[u8; 2]; 2] = [[0, 2], [3, 1]];
+
+const BAYER_4X4: [[u8; 4]; 4] = [
+    [ 0,  8,  2, 10],
+    [12,  4, 14,  6],
+    [ 3, 11,  1,  9],
+    [15,  7, 13,  5],
+];
+
+const BAYER_8X8: [[u8; 8]; 8] = [
+    [ 0, 32,  8, 40,  2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44,  4, 36, 14, 46,  6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [ 3, 35, 11, 43,  1, 33,  9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47,  7, 39, 13, 45,  5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+// Clustered-dot matrices: thresholds grow outward from the center of each dot
+const CLUSTER_4X4: [[u8; 4]; 4] = [
+    [12,  5,  6, 13],
+    [ 4,  0,  1,  7],
+    [11,  3,  2,  8],
+    [15, 10,  9, 14],
+];
+
+const CLUSTER_8X8: [[u8; 8]; 8] = [
+    [24, 10, 12, 26, 35, 47, 49, 37],
+    [ 8,  0,  2, 14, 45, 59, 61, 51],
+    [22,  6,  4, 16, 43, 57, 63, 53],
+    [30, 20, 18, 28, 33, 41, 55, 39],
+    [34, 46, 48, 36, 25, 11, 13, 27],
+    [44, 58, 60, 50,  9,  1,  3, 15],
+    [42, 56, 62, 52, 23,  7,  5, 17],
+    [32, 40, 54, 38, 31, 21, 19, 29],
+];
+
+const BLUE_NOISE_SIZE: usize = 32;
+
+/// Square threshold matrix with values normalized to [0, 1)
+struct ThresholdMatrix {
+    size: usize,
+    values: Vec<f32>,
+}
+
+impl ThresholdMatrix {
+    fn from_ranks<const N: usize>(ranks: &[[u8; N]; N]) -> Self {
+        let scale = (N * N) as f32;
+        ThresholdMatrix {
+            size: N,
+            values: ranks.iter().flatten().map(|&v| v as f32 / scale).collect(),
+        }
+    }
+
+    /// Threshold at (x, y), the matrix tiles the plane
+    fn at(&self, x: u32, y: u32) -> f32 {
+        let n = self.size;
+        self.values[(y as usize % n) * n + x as usize % n]
+    }
+}
+
+fn threshold_matrix(name: &str) -> ThresholdMatrix {
+    match name {
+        "bayer2" => ThresholdMatrix::from_ranks(&BAYER_2X2),
+        "bayer4" => ThresholdMatrix::from_ranks(&BAYER_4X4),
+        "cluster4" => ThresholdMatrix::from_ranks(&CLUSTER_4X4),
+        "cluster8" => ThresholdMatrix::from_ranks(&CLUSTER_8X8),
+        "blue-noise" => {
+            let scale = (BLUE_NOISE_SIZE * BLUE_NOISE_SIZE) as f32;
+            ThresholdMatrix {
+                size: BLUE_NOISE_SIZE,
+                values: blue_noise_ranks().iter().map(|&v| v as f32 / scale).collect(),
+            }
+        }
+        _ => ThresholdMatrix::from_ranks(&BAYER_8X8),
+    }
+}
+
+/// 32x32 blue-noise ranks, generated once with the void-and-cluster method
+fn blue_noise_ranks() -> &'static [u16] {
+    static RANKS: std::sync::OnceLock<Vec<u16>> = std::sync::OnceLock::new();
+    RANKS.get_or_init(|| {
+        const N: usize = BLUE_NOISE_SIZE;
+        const SIGMA: f32 = 1.5;
+
+        // Toroidal gaussian filter, indexed by (dy * N + dx)
+        let mut kernel = vec![0.0f32; N * N];
+        for dy in 0..N {
+            for dx in 0..N {
+                let ddx = dx.min(N - dx) as f32;
+                let ddy = dy.min(N - dy) as f32;
+                kernel[dy * N + dx] = (-(ddx * ddx + ddy * ddy) / (2.0 * SIGMA * SIGMA)).exp();
+            }
+        }
+
+        let toggle = |energy: &mut [f32], pattern: &mut [bool], index: usize, on: bool| {
+            pattern[index] = on;
+            let sign = if on { 1.0 } else { -1.0 };
+            let (ix, iy) = (index % N, index / N);
+            for y in 0..N {
+                for x in 0..N {
+                    let dx = (x + N - ix) % N;
+                    let dy = (y + N - iy) % N;
+                    energy[y * N + x] += sign * kernel[dy * N + dx];
+                }
+            }
+        };
+        // Tightest cluster: the set pixel with the highest energy; largest void: the free pixel with the lowest
+        let tightest_cluster = |energy: &[f32], pattern: &[bool]| -> usize {
+            (0..N * N)
+                .filter(|&i| pattern[i])
+                .max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+                .unwrap_or(0)
+        };
+        let largest_void = |energy: &[f32], pattern: &[bool]| -> usize {
+            (0..N * N)
+                .filter(|&i| !pattern[i])
+                .min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+                .unwrap_or(0)
+        };
+
+        // Deterministic initial pattern with ~10% of the pixels set
+        let mut pattern = vec![false; N * N];
+        let mut energy = vec![0.0f32; N * N];
+        let mut state: u32 = 0x5EED;
+        let initial_count = N * N / 10;
+        let mut placed = 0;
+        while placed < initial_count {
+            // xorshift32
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let index = state as usize % (N * N);
+            if !pattern[index] {
+                toggle(&mut energy, &mut pattern, index, true);
+                placed += 1;
+            }
+        }
+
+        // Spread the initial points: move the tightest cluster into the largest void until stable
+        for _ in 0..(N * N) {
+            let cluster = tightest_cluster(&energy, &pattern);
+            toggle(&mut energy, &mut pattern, cluster, false);
+            let void = largest_void(&energy, &pattern);
+            toggle(&mut energy, &mut pattern, void, true);
+            if void == cluster {
+                break;
+            }
+        }
+
+        let mut ranks = vec![0u16; N * N];
+
+        // Ranks below the initial count: remove points from the tightest clusters
+        {
+            let mut pattern = pattern.clone();
+            let mut energy = energy.clone();
+            for rank in (0..initial_count).rev() {
+                let cluster = tightest_cluster(&energy, &pattern);
+                toggle(&mut energy, &mut pattern, cluster, false);
+                ranks[cluster] = rank as u16;
+            }
+        }
+
+        // Remaining ranks: fill the largest voids
+        for rank in initial_count..(N * N) {
+            let void = largest_void(&energy, &pattern);
+            toggle(&mut energy, &mut pattern, void, true);
+            ranks[void] = rank as u16;
+        }
+
+        ranks
+    })
+}
+
+/// Parse a palette into RGB triplets (invalid entries are skipped)
+fn palette_to_rgb(palette: &[String]) -> Vec<[i32; 3]> {
+    palette
+        .iter()
+        .filter_map(|color| parse_hex_color(color))
+        .map(|rgba| [rgba.0[0] as i32, rgba.0[1] as i32, rgba.0[2] as i32])
+        .collect()
+}
+
+/// Index of the nearest palette color (squared RGB distance, first match wins on ties)
+fn nearest_rgb_index(r: u8, g: u8, b: u8, palette: &[[i32; 3]]) -> Option<usize> {
+    let mut best = None;
+    let mut best_dist = u32::MAX;
+    for (idx, candidate) in palette.iter().enumerate() {
+        let dr = r as i32 - candidate[0];
+        let dg = g as i32 - candidate[1];
+        let db = b as i32 - candidate[2];
+        let dist = (dr * dr + dg * dg + db * db) as u32;
+        if dist < best_dist {
+            best_dist = dist;
+            best = Some(idx);
+        }
+    }
+    best
+}
+
+/// Exact color of a pixel: its 8-bit value plus the precision of a high bit depth source
+fn fine_color(pixel: &Rgba<u8>, residuals: Option<&[[f32; 3]]>, index: usize) -> [f32; 3] {
+    let residual = residuals.and_then(|residuals| residuals.get(index)).copied().unwrap_or([0.0; 3]);
+    [0, 1, 2].map(|c| (pixel.0[c] as f32 + residual[c]).clamp(0.0, 255.0))
+}
+
+/// Nearest palette color of a dithered value: compared exactly for a high bit depth source,
+/// truncated to 8 bits otherwise like the palette building does
+fn nearest_palette_index(color: [f32; 3], palette: &[[i32; 3]], exact: bool) -> Option<usize> {
+    if !exact {
+        return nearest_rgb_index(color[0] as u8, color[1] as u8, color[2] as u8, palette);
+    }
+    let mut best = None;
+    let mut best_dist = f32::MAX;
+    for (idx, candidate) in palette.iter().enumerate() {
+        let dist: f32 = (0..3).map(|c| (color[c] - candidate[c] as f32).powi(2)).sum();
+        if dist < best_dist {
+            best_dist = dist;
+            best = Some(idx);
+        }
+    }
+    best
 }
 
+/// Map the image to its tile palettes. `strength_map` holds a per-pixel dithering strength
+/// (0.0 = flat, 1.0 = full) that scales the ordered threshold and the diffused error.
+/// `residuals` holds the precision of a high bit depth source below its 8-bit values
+/// (see `precision_residuals`); the pixels are then matched on their exact values.
 fn apply_tile_palettes_with_dither(
     image: &RgbaImage,
     palette_result: &TilePaletteResult,
-    dither_mode: &str,
+    options: &DitherOptions,
+    strength_map: Option<&[f32]>,
+    residuals: Option<&[[f32; 3]]>,
 ) -> Result<RgbaImage, String> {
+    use std::collections::HashMap;
+
     let (width, height) = image.dimensions();
     let tiles_x = width / 8;
     let tiles_y = height / 8;
 
     let mut output = image.clone();
+    let palettes_rgb: Vec<Vec<[i32; 3]>> = palette_result
+        .palette_colors
+        .iter()
+        .map(|palette| palette_to_rgb(palette))
+        .collect();
+    let kernel = diffusion_kernel(&options.mode);
+
+    if options.cross_tile {
+        if let Some(kernel) = &kernel {
+            return Ok(diffuse_across_tiles(image, palette_result, &palettes_rgb, kernel, options, strength_map, residuals));
+        }
+    }
+
+    let matrix = threshold_matrix(&options.ordered_matrix);
+    let spread = if options.ordered_spread > 0.0 { options.ordered_spread } else { RGB333_STEP };
+    let global_align = options.ordered_align == "global";
+
+    // Pattern dithering: mixing plans are cached per (palette, source color)
+    let pattern_mode = matches!(options.mode.as_str(), "knoll" | "yliluoma");
+    let plan_size = (matrix.size * matrix.size).min(MAX_PLAN_SIZE);
+    let mut plans: HashMap<(usize, [u8; 3]), Vec<usize>> = HashMap::new();
 
     // Process each tile independently to avoid cross-tile dithering artifacts
     for tile_y in 0..tiles_y {
@@ -1133,120 +5528,282 @@ fn apply_tile_palettes_with_dither(
                 .get(tile_index)
                 .copied()
                 .unwrap_or(0);
-            let palette = palette_result
-                .palette_colors
-                .get(palette_index)
-                .cloned()
-                .unwrap_or_default();
+            let empty_palette = Vec::new();
+            let palette = palettes_rgb.get(palette_index).unwrap_or(&empty_palette);
 
-            // Per-tile error buffer for Floyd-Steinberg (8x8 + padding)
-            let mut error_r: [[f32; 10]; 9] = [[0.0; 10]; 9];
-            let mut error_g: [[f32; 10]; 9] = [[0.0; 10]; 9];
-            let mut error_b: [[f32; 10]; 9] = [[0.0; 10]; 9];
-
-            // Bayer 8x8 matrix for ordered dithering (values 0-63, will be normalized)
-            const BAYER_8X8: [[u8; 8]; 8] = [
-                [ 0, 32,  8, 40,  2, 34, 10, 42],
-                [48, 16, 56, 24, 50, 18, 58, 26],
-                [12, 44,  4, 36, 14, 46,  6, 38],
-                [60, 28, 52, 20, 62, 30, 54, 22],
-                [ 3, 35, 11, 43,  1, 33,  9, 41],
-                [51, 19, 59, 27, 49, 17, 57, 25],
-                [15, 47,  7, 39, 13, 45,  5, 37],
-                [63, 31, 55, 23, 61, 29, 53, 21],
-            ];
+            // Per-tile error buffer: error never leaves the 8x8 tile
+            let mut error: [[[f32; 3]; 8]; 8] = [[[0.0; 3]; 8]; 8];
 
             // Process pixels within this tile
-            for ly in 0..8u32 {
-                for lx in 0..8u32 {
-                    let px = tile_x * 8 + lx;
-                    let py = tile_y * 8 + ly;
+            for ly in 0..8i32 {
+                // Serpentine scan: odd rows run right-to-left with mirrored kernel taps
+                let reverse = options.serpentine && kernel.is_some() && ly % 2 == 1;
+                for step in 0..8i32 {
+                    let lx = if reverse { 7 - step } else { step };
+                    let px = tile_x * 8 + lx as u32;
+                    let py = tile_y * 8 + ly as u32;
 
                     let pixel = image.get_pixel(px, py);
                     let [r, g, b, a] = pixel.0;
+                    let local_strength = strength_map
+                        .and_then(|map| map.get((py * width + px) as usize).copied())
+                        .unwrap_or(1.0);
+                    let fine = fine_color(pixel, residuals, (py * width + px) as usize);
+
+                    if pattern_mode && !palette.is_empty() && local_strength > 0.0 {
+                        let plan = plans.entry((palette_index, [r, g, b])).or_insert_with(|| {
+                            if options.mode == "knoll" {
+                                knoll_plan([r, g, b], palette, plan_size, options.strength)
+                            } else {
+                                yliluoma_plan([r, g, b], palette, plan_size)
+                            }
+                        });
+                        // The mask pulls the threshold toward the middle of the plan
+                        let (mx, my) = if global_align { (px, py) } else { (lx as u32, ly as u32) };
+                        let threshold = 0.5 + (matrix.at(mx, my) - 0.5) * local_strength;
+                        let slot = ((threshold * plan.len() as f32) as usize).min(plan.len() - 1);
+                        let color = palette[plan[slot]];
+                        output.put_pixel(px, py, Rgba([color[0] as u8, color[1] as u8, color[2] as u8, 255]));
+                        continue;
+                    }
 
                     // Add accumulated error for dithering
-                    let (adj_r, adj_g, adj_b) = if dither_mode == "floyd" {
-                        let er = error_r[ly as usize][lx as usize + 1];
-                        let eg = error_g[ly as usize][lx as usize + 1];
-                        let eb = error_b[ly as usize][lx as usize + 1];
-                        (
-                            (r as f32 + er).clamp(0.0, 255.0),
-                            (g as f32 + eg).clamp(0.0, 255.0),
-                            (b as f32 + eb).clamp(0.0, 255.0),
-                        )
-                    } else if dither_mode == "ordered" {
-                        // Ordered dithering: add threshold from Bayer matrix
-                        // Threshold is normalized to [-0.5, 0.5] * spread
-                        let threshold = (BAYER_8X8[ly as usize][lx as usize] as f32 / 64.0 - 0.5) * 32.0;
-                        (
-                            (r as f32 + threshold).clamp(0.0, 255.0),
-                            (g as f32 + threshold).clamp(0.0, 255.0),
-                            (b as f32 + threshold).clamp(0.0, 255.0),
-                        )
+                    let adjusted: [f32; 3] = if kernel.is_some() {
+                        let e = error[ly as usize][lx as usize];
+                        let base = options.error_space(fine);
+                        options.to_display_space([
+                            (base[0] + e[0] * local_strength).clamp(0.0, 255.0),
+                            (base[1] + e[1] * local_strength).clamp(0.0, 255.0),
+                            (base[2] + e[2] * local_strength).clamp(0.0, 255.0),
+                        ])
+                    } else if options.mode == "ordered" {
+                        // Ordered dithering: threshold is normalized to [-0.5, 0.5] * spread
+                        let (mx, my) = if global_align { (px, py) } else { (lx as u32, ly as u32) };
+                        let threshold = (matrix.at(mx, my) - 0.5) * spread * local_strength;
+                        [
+                            (fine[0] + threshold).clamp(0.0, 255.0),
+                            (fine[1] + threshold).clamp(0.0, 255.0),
+                            (fine[2] + threshold).clamp(0.0, 255.0),
+                        ]
                     } else {
-                        (r as f32, g as f32, b as f32)
+                        fine
                     };
 
                     // Find nearest color in tile's palette
-                    let color = format!("#{:02X}{:02X}{:02X}", adj_r as u8, adj_g as u8, adj_b as u8);
-                    let mapped = nearest_palette_color(&color, &palette)
-                        .unwrap_or_else(|| format!("#{:02X}{:02X}{:02X}", r, g, b));
-                    let mapped_rgba = parse_hex_color(&mapped).unwrap_or(Rgba([r, g, b, a]));
+                    let mapped_rgba = nearest_palette_index(adjusted, palette, residuals.is_some() || options.linear_light)
+                        .map(|idx| Rgba([palette[idx][0] as u8, palette[idx][1] as u8, palette[idx][2] as u8, 255]))
+                        .unwrap_or(Rgba([r, g, b, a]));
 
                     output.put_pixel(px, py, mapped_rgba);
 
-                    // Distribute error for Floyd-Steinberg within tile boundaries
-                    if dither_mode == "floyd" {
-                        let quant_r = mapped_rgba.0[0] as f32;
-                        let quant_g = mapped_rgba.0[1] as f32;
-                        let quant_b = mapped_rgba.0[2] as f32;
+                    // Distribute error within tile boundaries
+                    if let Some(kernel) = &kernel {
+                        // The mask already scaled the error this pixel received
+                        let scale = options.strength;
+                        let target = options.error_space(adjusted);
+                        let mapped = options.error_space([0, 1, 2].map(|c| mapped_rgba.0[c] as f32));
+                        let err = [
+                            (target[0] - mapped[0]) * scale,
+                            (target[1] - mapped[1]) * scale,
+                            (target[2] - mapped[2]) * scale,
+                        ];
+
+                        for &(dx, dy, weight) in kernel.taps.iter() {
+                            let nx = if reverse { lx - dx } else { lx + dx };
+                            let ny = ly + dy;
+                            if !(0..8).contains(&nx) || !(0..8).contains(&ny) {
+                                continue;
+                            }
+                            let factor = weight / kernel.divisor;
+                            let cell = &mut error[ny as usize][nx as usize];
+                            cell[0] += err[0] * factor;
+                            cell[1] += err[1] * factor;
+                            cell[2] += err[2] * factor;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(output)
+}
 
-                        let err_r = adj_r - quant_r;
-                        let err_g = adj_g - quant_g;
-                        let err_b = adj_b - quant_b;
+/// Largest mixing plan for pattern dithering (bigger matrices, like blue noise, are sampled)
+const MAX_PLAN_SIZE: usize = 64;
 
-                        let lx_idx = lx as usize + 1;
-                        let ly_idx = ly as usize;
+fn luma(color: &[i32; 3]) -> i32 {
+    color[0] * 299 + color[1] * 587 + color[2] * 114
+}
 
-                        // Floyd-Steinberg error distribution: 7/16, 3/16, 5/16, 1/16
-                        // Only distribute to pixels within tile bounds
+/// Knoll pattern dithering: pick `size` colors whose average converges to the source color,
+/// feeding back the accumulated error; the plan is sorted by luminance
+fn knoll_plan(color: [u8; 3], palette: &[[i32; 3]], size: usize, error_factor: f32) -> Vec<usize> {
+    let source = [color[0] as f32, color[1] as f32, color[2] as f32];
+    let mut error = [0.0f32; 3];
+    let mut plan = Vec::with_capacity(size);
+    for _ in 0..size {
+        let attempt = [
+            (source[0] + error[0] * error_factor).clamp(0.0, 255.0),
+            (source[1] + error[1] * error_factor).clamp(0.0, 255.0),
+            (source[2] + error[2] * error_factor).clamp(0.0, 255.0),
+        ];
+        let index = nearest_rgb_index(attempt[0] as u8, attempt[1] as u8, attempt[2] as u8, palette).unwrap_or(0);
+        let chosen = palette[index];
+        for c in 0..3 {
+            error[c] += source[c] - chosen[c] as f32;
+        }
+        plan.push(index);
+    }
+    plan.sort_by_key(|&index| luma(&palette[index]));
+    plan
+}
 
-                        // Right pixel (7/16) - only if not at right edge of tile
-                        if lx < 7 {
-                            error_r[ly_idx][lx_idx + 1] += err_r * 7.0 / 16.0;
-                            error_g[ly_idx][lx_idx + 1] += err_g * 7.0 / 16.0;
-                            error_b[ly_idx][lx_idx + 1] += err_b * 7.0 / 16.0;
-                        }
+/// Yliluoma pattern dithering (algorithm 1): best mix of two palette colors, with the ratio
+/// solved analytically per pair and a penalty on mixing very different colors
+fn yliluoma_plan(color: [u8; 3], palette: &[[i32; 3]], size: usize) -> Vec<usize> {
+    let source = [color[0] as f32, color[1] as f32, color[2] as f32];
+    let distance = |a: [f32; 3], b: [f32; 3]| -> f32 {
+        (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)
+    };
 
-                        // Bottom row - only if not at bottom edge of tile
-                        if ly < 7 {
-                            // Bottom-left pixel (3/16)
-                            if lx > 0 {
-                                error_r[ly_idx + 1][lx_idx - 1] += err_r * 3.0 / 16.0;
-                                error_g[ly_idx + 1][lx_idx - 1] += err_g * 3.0 / 16.0;
-                                error_b[ly_idx + 1][lx_idx - 1] += err_b * 3.0 / 16.0;
-                            }
+    let mut best = (0usize, 0usize, 0usize);
+    let mut best_penalty = f32::MAX;
+    for i in 0..palette.len() {
+        let a = [palette[i][0] as f32, palette[i][1] as f32, palette[i][2] as f32];
+        for (j, color_b) in palette.iter().enumerate().skip(i) {
+            let b = [color_b[0] as f32, color_b[1] as f32, color_b[2] as f32];
+            let ab = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+            let length = ab[0] * ab[0] + ab[1] * ab[1] + ab[2] * ab[2];
+
+            // Projection of the source color on the a->b segment, quantized to the plan size
+            let steps = if length > 0.0 {
+                let t = ((source[0] - a[0]) * ab[0] + (source[1] - a[1]) * ab[1] + (source[2] - a[2]) * ab[2]) / length;
+                (t.clamp(0.0, 1.0) * size as f32).round() as usize
+            } else {
+                0
+            };
+            let ratio = steps as f32 / size as f32;
+            let mix = [a[0] + ab[0] * ratio, a[1] + ab[1] * ratio, a[2] + ab[2] * ratio];
+            // Mixing penalty, lower than Yliluoma's 0.1 since tile palettes are small
+            let penalty = distance(source, mix) + length * 0.05 * ((ratio - 0.5).abs() + 0.5);
+            if penalty < best_penalty {
+                best_penalty = penalty;
+                best = (i, j, steps);
+            }
+        }
+    }
+
+    let (i, j, steps) = best;
+    // Darker color first so the plan is ordered by luminance like the Knoll plan
+    let (dark, light, light_count) = if luma(&palette[i]) <= luma(&palette[j]) {
+        (i, j, steps)
+    } else {
+        (j, i, size - steps)
+    };
+    let mut plan = vec![dark; size - light_count];
+    plan.extend(std::iter::repeat_n(light, light_count));
+    plan
+}
+
+/// Error diffusion over the whole image: each pixel is still mapped with its own tile's palette,
+/// but the error keeps flowing into neighboring tiles (clamped to options.error_clamp per channel)
+fn diffuse_across_tiles(
+    image: &RgbaImage,
+    palette_result: &TilePaletteResult,
+    palettes_rgb: &[Vec<[i32; 3]>],
+    kernel: &DiffusionKernel,
+    options: &DitherOptions,
+    strength_map: Option<&[f32]>,
+    residuals: Option<&[[f32; 3]]>,
+) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let tiles_x = width / 8;
+    let tiles_y = height / 8;
+    let mut output = image.clone();
+    let empty_palette = Vec::new();
+    let clamp = options.error_clamp.max(0.0);
+
+    let tile_at = |x: i32, y: i32| -> usize { ((y / 8) as u32 * tiles_x + (x / 8) as u32) as usize };
+    let is_empty = |tile_index: usize| palette_result.empty_tiles.get(tile_index).copied().unwrap_or(false);
+
+    // Only the rows reachable by the kernel are kept (kernel taps go at most 2 rows down).
+    // Error coming from other tiles is summed apart, to clamp the total each pixel receives.
+    let max_dy = kernel.taps.iter().map(|&(_, dy, _)| dy).max().unwrap_or(0) as usize;
+    let mut rows: Vec<Vec<[f32; 3]>> = vec![vec![[0.0; 3]; width as usize]; max_dy + 1];
+    let mut crossing_rows: Vec<Vec<[f32; 3]>> = vec![vec![[0.0; 3]; width as usize]; max_dy + 1];
+
+    for y in 0..(tiles_y * 8) as i32 {
+        let reverse = options.serpentine && y % 2 == 1;
+        for step in 0..(tiles_x * 8) as i32 {
+            let x = if reverse { (tiles_x * 8) as i32 - 1 - step } else { step };
+            let tile_index = tile_at(x, y);
+
+            // Empty tiles keep the background color and absorb no error
+            if is_empty(tile_index) {
+                continue;
+            }
 
-                            // Bottom pixel (5/16)
-                            error_r[ly_idx + 1][lx_idx] += err_r * 5.0 / 16.0;
-                            error_g[ly_idx + 1][lx_idx] += err_g * 5.0 / 16.0;
-                            error_b[ly_idx + 1][lx_idx] += err_b * 5.0 / 16.0;
+            let palette_index = palette_result.tile_palette_map.get(tile_index).copied().unwrap_or(0);
+            let palette = palettes_rgb.get(palette_index).unwrap_or(&empty_palette);
+
+            let pixel = image.get_pixel(x as u32, y as u32);
+            let [r, g, b, a] = pixel.0;
+            let local_strength = strength_map
+                .and_then(|map| map.get((y as u32 * width + x as u32) as usize).copied())
+                .unwrap_or(1.0);
+            let fine = fine_color(pixel, residuals, (y as u32 * width + x as u32) as usize);
+            // Error crossing from other tiles is clamped so a bad palette match can't bleed
+            let own = rows[0][x as usize];
+            let crossing = crossing_rows[0][x as usize];
+            let e = [0, 1, 2].map(|c| own[c] + crossing[c].clamp(-clamp, clamp));
+            let base = options.error_space(fine);
+            let target = [
+                (base[0] + e[0] * local_strength).clamp(0.0, 255.0),
+                (base[1] + e[1] * local_strength).clamp(0.0, 255.0),
+                (base[2] + e[2] * local_strength).clamp(0.0, 255.0),
+            ];
+            let adjusted = options.to_display_space(target);
+
+            let mapped_rgba = nearest_palette_index(adjusted, palette, residuals.is_some() || options.linear_light)
+                .map(|idx| Rgba([palette[idx][0] as u8, palette[idx][1] as u8, palette[idx][2] as u8, 255]))
+                .unwrap_or(Rgba([r, g, b, a]));
+            output.put_pixel(x as u32, y as u32, mapped_rgba);
+
+            // The mask already scaled the error this pixel received
+            let scale = options.strength;
+            let mapped = options.error_space([0, 1, 2].map(|c| mapped_rgba.0[c] as f32));
+            let err = [
+                (target[0] - mapped[0]) * scale,
+                (target[1] - mapped[1]) * scale,
+                (target[2] - mapped[2]) * scale,
+            ];
 
-                            // Bottom-right pixel (1/16)
-                            if lx < 7 {
-                                error_r[ly_idx + 1][lx_idx + 1] += err_r * 1.0 / 16.0;
-                                error_g[ly_idx + 1][lx_idx + 1] += err_g * 1.0 / 16.0;
-                                error_b[ly_idx + 1][lx_idx + 1] += err_b * 1.0 / 16.0;
-                            }
-                        }
-                    }
+            for &(dx, dy, weight) in kernel.taps.iter() {
+                let nx = if reverse { x - dx } else { x + dx };
+                let ny = y + dy;
+                if nx < 0 || nx >= (tiles_x * 8) as i32 || ny >= (tiles_y * 8) as i32 {
+                    continue;
                 }
+                let factor = weight / kernel.divisor;
+                let carried = [err[0] * factor, err[1] * factor, err[2] * factor];
+                let target_rows = if tile_at(nx, ny) != tile_index { &mut crossing_rows } else { &mut rows };
+                let cell = &mut target_rows[dy as usize][nx as usize];
+                cell[0] += carried[0];
+                cell[1] += carried[1];
+                cell[2] += carried[2];
+            }
+        }
+        // Shift the error rows up for the next scanline
+        for error_rows in [&mut rows, &mut crossing_rows] {
+            error_rows.rotate_left(1);
+            if let Some(last) = error_rows.last_mut() {
+                last.iter_mut().for_each(|cell| *cell = [0.0; 3]);
             }
         }
     }
 
-    Ok(output)
+    output
 }
 
 fn best_cluster_for_tile(
@@ -1329,6 +5886,583 @@ fn find_global_color0(tiles: &[Vec<String>]) -> Option<String> {
     counts.into_iter().max_by_key(|(_, count)| *count).map(|(color, _)| color)
 }
 
+// ===== Tile Reduction =====
+
+/// Encode every tile of the image and deduplicate the patterns.
+/// Empty tile is always first (32 bytes of zeros = all pixels are color index 0)
+fn build_unique_tiles(
+    image: &RgbaImage,
+    palettes: &[Vec<String>],
+    tile_palette_map: &[usize],
+    empty_tiles: &[bool],
+    pixel_indices: &[u8],  // Color index of every pixel (empty = match the colors to the palette)
+) -> (Vec<[u8; 32]>, Vec<usize>) {
+    use std::collections::HashMap;
+
+    let (width, height) = image.dimensions();
+    let tiles_x = width / 8;
+    let tiles_y = height / 8;
+    let total_tiles = (tiles_x * tiles_y) as usize;
+
+    let empty_tile: [u8; 32] = [0u8; 32];
+    let mut unique_tiles: Vec<[u8; 32]> = vec![empty_tile];
+    let mut lookup: HashMap<[u8; 32], usize> = HashMap::new();
+    lookup.insert(empty_tile, 0);
+    let mut tile_to_unique: Vec<usize> = Vec::with_capacity(total_tiles);
+
+    for tile_idx in 0..total_tiles {
+        // Empty tiles all point to the first tile (index 0)
+        if empty_tiles.get(tile_idx).copied().unwrap_or(false) {
+            tile_to_unique.push(0);
+            continue;
+        }
+
+        let tile_x = (tile_idx % tiles_x as usize) as u32;
+        let tile_y = (tile_idx / tiles_x as usize) as u32;
+        let palette_idx = tile_palette_map.get(tile_idx).copied().unwrap_or(0);
+        let palette = palettes.get(palette_idx).cloned().unwrap_or_default();
+        let tile_data = if pixel_indices.is_empty() {
+            encode_tile_planar(image, tile_x, tile_y, &palette)
+        } else {
+            encode_tile_indices(&tile_indices(pixel_indices, width, tile_x, tile_y))
+        };
+
+        let next_idx = unique_tiles.len();
+        let idx = *lookup.entry(tile_data).or_insert(next_idx);
+        if idx == next_idx {
+            unique_tiles.push(tile_data);
+        }
+        tile_to_unique.push(idx);
+    }
+
+    (unique_tiles, tile_to_unique)
+}
+
+/// Color indices (row-major) of one 8x8 tile of an image given as color indices
+fn tile_indices(pixel_indices: &[u8], width: u32, tile_x: u32, tile_y: u32) -> [u8; 64] {
+    let mut indices = [0u8; 64];
+    for line in 0..8 {
+        let start = ((tile_y * 8 + line) * width + tile_x * 8) as usize;
+        indices[(line * 8) as usize..(line * 8 + 8) as usize].copy_from_slice(&pixel_indices[start..start + 8]);
+    }
+    indices
+}
+
+/// Encode 64 color indices (row-major) to a 32-byte planar tile (inverse of decode_tile_planar)
+fn encode_tile_indices(indices: &[u8; 64]) -> [u8; 32] {
+    let mut data = [0u8; 32];
+    for (pixel, &index) in indices.iter().enumerate() {
+        let (line, bit) = (pixel / 8, 7 - pixel % 8);
+        data[line * 2] |= (index & 1) << bit;
+        data[line * 2 + 1] |= ((index >> 1) & 1) << bit;
+        data[16 + line * 2] |= ((index >> 2) & 1) << bit;
+        data[16 + line * 2 + 1] |= ((index >> 3) & 1) << bit;
+    }
+    data
+}
+
+/// Decode a 32-byte planar tile back to 64 color indices (row-major)
+fn decode_tile_planar(data: &[u8; 32]) -> [u8; 64] {
+    let mut indices = [0u8; 64];
+    for line in 0..8usize {
+        let plane1 = data[line * 2];
+        let plane2 = data[line * 2 + 1];
+        let plane3 = data[16 + line * 2];
+        let plane4 = data[16 + line * 2 + 1];
+        for px in 0..8usize {
+            let bit_pos = 7 - px;
+            indices[line * 8 + px] = ((plane1 >> bit_pos) & 1)
+                | (((plane2 >> bit_pos) & 1) << 1)
+                | (((plane3 >> bit_pos) & 1) << 2)
+                | (((plane4 >> bit_pos) & 1) << 3);
+        }
+    }
+    indices
+}
+
+/// Palette colors as RGB triplets, plus the first index holding each color.
+/// Patterns may only use "canonical" indices, otherwise the export (which re-encodes
+/// the preview by nearest color) would not reproduce a pattern borrowed from another tile.
+fn palette_tables(palette_result: &TilePaletteResult) -> (Vec<[[i32; 3]; 16]>, Vec<[bool; 16]>) {
+    let palette_rgb: Vec<[[i32; 3]; 16]> = palette_result
+        .palettes
+        .iter()
+        .map(|palette| {
+            let mut rgb = [[0i32; 3]; 16];
+            for (i, entry) in rgb.iter_mut().enumerate() {
+                let color = palette
+                    .get(i)
+                    .and_then(|c| parse_hex_color(c))
+                    .unwrap_or(Rgba([0, 0, 0, 255]));
+                *entry = [color.0[0] as i32, color.0[1] as i32, color.0[2] as i32];
+            }
+            rgb
+        })
+        .collect();
+    let palette_canonical: Vec<[bool; 16]> = palette_rgb
+        .iter()
+        .map(|rgb| {
+            let mut canonical = [true; 16];
+            for i in 1..16 {
+                canonical[i] = !rgb[..i].contains(&rgb[i]);
+            }
+            canonical
+        })
+        .collect();
+    (palette_rgb, palette_canonical)
+}
+
+/// Foreground coverage of a tile's 64 pixels, row-major (None when the whole image is opaque)
+fn tile_coverage(coverage: Option<&[bool]>, width: u32, tile_x: u32, tile_y: u32) -> Option<[bool; 64]> {
+    let coverage = coverage?;
+    let mut tile = [true; 64];
+    for (i, covered) in tile.iter_mut().enumerate() {
+        let x = tile_x * 8 + (i % 8) as u32;
+        let y = tile_y * 8 + (i / 8) as u32;
+        *covered = coverage.get((y * width + x) as usize).copied().unwrap_or(true);
+    }
+    Some(tile)
+}
+
+/// A pattern fits the coverage when color 0 (transparent on VDC1) is on exactly the uncovered pixels
+fn pattern_fits_coverage(pattern: &[u8; 64], coverage: &[bool; 64]) -> bool {
+    pattern
+        .iter()
+        .zip(coverage.iter())
+        .all(|(&index, &covered)| (index & 0x0F == 0) != covered)
+}
+
+/// Dedup-friendly dithering: walk the tiles in order and replace each dithered tile by a
+/// pattern already used by a previous tile (rendered with this tile's palette) when its RMS
+/// error against the source stays within `tolerance` of the dithered tile's own error.
+/// With a SuperGrafx coverage, a borrowed pattern must keep the tile's transparent pixels.
+/// Returns the number of tiles that reuse a pattern.
+fn reuse_tile_patterns(
+    source: &RgbaImage,
+    preview: &mut RgbaImage,
+    palette_result: &TilePaletteResult,
+    tolerance: f32,
+    coverage: Option<&[bool]>,
+) -> usize {
+    use std::collections::HashSet;
+
+    let (width, height) = preview.dimensions();
+    let tiles_x = width / 8;
+    let total_tiles = (tiles_x * (height / 8)) as usize;
+    let (palette_rgb, palette_canonical) = palette_tables(palette_result);
+
+    let mut patterns: Vec<[u8; 64]> = Vec::new();
+    let mut known: HashSet<[u8; 64]> = HashSet::new();
+    let mut reused = 0;
+
+    for tile_idx in 0..total_tiles {
+        if palette_result.empty_tiles.get(tile_idx).copied().unwrap_or(false) {
+            continue;
+        }
+        let tile_x = (tile_idx % tiles_x as usize) as u32;
+        let tile_y = (tile_idx / tiles_x as usize) as u32;
+        let palette_idx = palette_result.tile_palette_map.get(tile_idx).copied().unwrap_or(0);
+        let (Some(rgb), Some(canonical)) = (palette_rgb.get(palette_idx), palette_canonical.get(palette_idx)) else {
+            continue;
+        };
+        let palette = palette_result.palettes.get(palette_idx).cloned().unwrap_or_default();
+        let own = decode_tile_planar(&encode_tile_planar(preview, tile_x, tile_y, &palette));
+        if known.contains(&own) {
+            continue;
+        }
+        let covered = tile_coverage(coverage, width, tile_x, tile_y);
+
+        let mut source_pixels = [[0i32; 3]; 64];
+        for (i, pixel) in source_pixels.iter_mut().enumerate() {
+            let p = source.get_pixel(tile_x * 8 + (i % 8) as u32, tile_y * 8 + (i / 8) as u32);
+            *pixel = [p.0[0] as i32, p.0[1] as i32, p.0[2] as i32];
+        }
+        let pattern_error = |pattern: &[u8; 64], limit: i64| -> i64 {
+            let mut sse = 0i64;
+            for (i, &index) in pattern.iter().enumerate() {
+                let c = rgb[index as usize & 15];
+                let s = source_pixels[i];
+                sse += ((s[0] - c[0]).pow(2) + (s[1] - c[1]).pow(2) + (s[2] - c[2]).pow(2)) as i64;
+                if sse > limit {
+                    break;
+                }
+            }
+            sse
+        };
+
+        // Accept a borrowed pattern if its RMS <= own RMS + tolerance
+        let own_rms = (pattern_error(&own, i64::MAX) as f32 / 64.0).sqrt();
+        let limit_rms = own_rms + tolerance;
+        let limit = (limit_rms * limit_rms * 64.0) as i64;
+        let mut best: Option<(usize, i64)> = None;
+        for (pattern_idx, pattern) in patterns.iter().enumerate() {
+            if pattern.iter().any(|&index| !canonical[index as usize & 15]) {
+                continue;
+            }
+            if covered.is_some_and(|covered| !pattern_fits_coverage(pattern, &covered)) {
+                continue;
+            }
+            let bound = best.map_or(limit, |(_, sse)| sse.min(limit));
+            let sse = pattern_error(pattern, bound);
+            if sse <= bound && best.is_none_or(|(_, best_sse)| sse < best_sse) {
+                best = Some((pattern_idx, sse));
+            }
+        }
+
+        match best {
+            Some((pattern_idx, _)) => {
+                for (i, &index) in patterns[pattern_idx].iter().enumerate() {
+                    let c = rgb[index as usize & 15];
+                    preview.put_pixel(
+                        tile_x * 8 + (i % 8) as u32,
+                        tile_y * 8 + (i / 8) as u32,
+                        Rgba([c[0] as u8, c[1] as u8, c[2] as u8, 255]),
+                    );
+                }
+                reused += 1;
+            }
+            None => {
+                known.insert(own);
+                patterns.push(own);
+            }
+        }
+    }
+
+    reused
+}
+
+/// Users of a unique pattern that share a palette: tile count, sum of the mean source colors
+/// of their 4x4 quadrants and sum of the squared norms of these means (see reduce_unique_tiles)
+#[derive(Clone, Copy)]
+struct MeanColorSum {
+    palette: usize,
+    count: f64,
+    sum: [[f64; 3]; 4],
+    sum_sq: f64,
+}
+
+fn add_mean_color_sum(sums: &mut Vec<MeanColorSum>, added: MeanColorSum) {
+    match sums.iter_mut().find(|entry| entry.palette == added.palette) {
+        Some(entry) => {
+            entry.count += added.count;
+            for (quadrant, added) in entry.sum.iter_mut().zip(added.sum) {
+                for (value, added) in quadrant.iter_mut().zip(added) {
+                    *value += added;
+                }
+            }
+            entry.sum_sq += added.sum_sq;
+        }
+        None => sums.push(added),
+    }
+}
+
+/// Quadrant (0-3) of a pixel index in an 8x8 tile
+fn tile_quadrant(i: usize) -> usize {
+    (i / 32) * 2 + (i % 8) / 4
+}
+
+/// Merge near-identical unique tiles until the tile budget or the error threshold is reached.
+/// Errors are measured against the curved source, like reuse_tile_patterns.
+/// mode: "threshold" = merge while the RMS error of the merged tiles grows by at most threshold,
+///       "max_tiles" = merge the cheapest pairs until at most max_unique_tiles remain.
+/// A merged tile reuses the pattern of its target with its own palette, so the preview is
+/// re-rendered and tile_to_unique is remapped. With a SuperGrafx coverage, a tile only merges
+/// into patterns that keep its transparent pixels. Targets are tried in the order of a lower
+/// bound of their error, which stops the search early. Returns the number of merged patterns.
+#[allow(clippy::too_many_arguments)]
+fn reduce_unique_tiles(
+    app: Option<&AppHandle>,
+    source: &RgbaImage,
+    preview: &mut RgbaImage,
+    palette_result: &TilePaletteResult,
+    unique_tiles: &mut Vec<[u8; 32]>,
+    tile_to_unique: &mut [usize],
+    mode: &str,
+    threshold: f32,
+    max_unique_tiles: usize,
+    coverage: Option<&[bool]>,
+) -> usize {
+    let use_threshold = mode == "threshold";
+    if !use_threshold && mode != "max_tiles" {
+        return 0;
+    }
+    if !use_threshold && unique_tiles.len() <= max_unique_tiles.max(1) {
+        return 0;
+    }
+
+    let (width, _) = preview.dimensions();
+    let tiles_x = width / 8;
+
+    let (palette_rgb, palette_canonical) = palette_tables(palette_result);
+
+    let patterns: Vec<[u8; 64]> = unique_tiles.iter().map(decode_tile_planar).collect();
+    let unique_count = patterns.len();
+
+    // Source pixels of every non-empty tile, grouped by the unique pattern they use
+    let mut tile_pixels: Vec<[[i32; 3]; 64]> = vec![[[0; 3]; 64]; tile_to_unique.len()];
+    let mut tile_covered: Vec<Option<[bool; 64]>> = vec![None; tile_to_unique.len()];
+    let mut users: Vec<Vec<usize>> = vec![Vec::new(); unique_count];
+    for (tile_idx, &unique_idx) in tile_to_unique.iter().enumerate() {
+        if palette_result.empty_tiles.get(tile_idx).copied().unwrap_or(false) {
+            continue;
+        }
+        let tile_x = (tile_idx % tiles_x as usize) as u32;
+        let tile_y = (tile_idx / tiles_x as usize) as u32;
+        tile_covered[tile_idx] = tile_coverage(coverage, width, tile_x, tile_y);
+        for py in 0..8u32 {
+            for px in 0..8u32 {
+                let p = source.get_pixel(tile_x * 8 + px, tile_y * 8 + py);
+                tile_pixels[tile_idx][(py * 8 + px) as usize] =
+                    [p.0[0] as i32, p.0[1] as i32, p.0[2] as i32];
+            }
+        }
+        users[unique_idx].push(tile_idx);
+    }
+
+    let tile_palette = |tile_idx: usize| -> usize {
+        palette_result
+            .tile_palette_map
+            .get(tile_idx)
+            .copied()
+            .unwrap_or(0)
+            .min(palette_rgb.len().saturating_sub(1))
+    };
+
+    // Squared error of rendering a tile with a pattern
+    let pattern_error = |tile_idx: usize, pattern: &[u8; 64]| -> u64 {
+        let rgb = &palette_rgb[tile_palette(tile_idx)];
+        let mut err = 0u64;
+        for (pixel, &color_idx) in tile_pixels[tile_idx].iter().zip(pattern.iter()) {
+            let idx = color_idx as usize & 0x0F;
+            let dr = pixel[0] - rgb[idx][0];
+            let dg = pixel[1] - rgb[idx][1];
+            let db = pixel[2] - rgb[idx][2];
+            err += (dr * dr + dg * dg + db * db) as u64;
+        }
+        err
+    };
+    // Same, None if the pattern is not encodable or does not keep the tile's transparent pixels
+    let tile_error = |tile_idx: usize, pattern: &[u8; 64]| -> Option<u64> {
+        if tile_covered[tile_idx].is_some_and(|covered| !pattern_fits_coverage(pattern, &covered)) {
+            return None;
+        }
+        let canonical = &palette_canonical[tile_palette(tile_idx)];
+        if pattern.iter().any(|&color_idx| !canonical[color_idx as usize & 0x0F]) {
+            return None;
+        }
+        Some(pattern_error(tile_idx, pattern))
+    };
+
+    // Error of every tile with its own (dithered) pattern
+    let mut current_error: Vec<u64> = tile_to_unique
+        .iter()
+        .enumerate()
+        .map(|(tile_idx, &unique_idx)| {
+            if palette_result.empty_tiles.get(tile_idx).copied().unwrap_or(false) {
+                0
+            } else {
+                pattern_error(tile_idx, &patterns[unique_idx])
+            }
+        })
+        .collect();
+    let mut alive = vec![true; unique_count];
+    let mut alive_count = unique_count;
+
+    // Over the 16 pixels of a quadrant, the squared error is at least 16 times the squared
+    // distance between the mean source color and the mean color of the pattern rendered with the
+    // tile's palette. Summed per palette over the users of a pattern, this lower bound of the
+    // merge error costs a few operations per target.
+    let pattern_means: Vec<Vec<[[f64; 3]; 4]>> = patterns
+        .iter()
+        .map(|pattern| {
+            let mut histograms = [[0u32; 16]; 4];
+            for (i, &index) in pattern.iter().enumerate() {
+                histograms[tile_quadrant(i)][index as usize & 0x0F] += 1;
+            }
+            palette_rgb
+                .iter()
+                .map(|rgb| {
+                    histograms.map(|histogram| {
+                        let mut mean = [0f64; 3];
+                        for (&count, color) in histogram.iter().zip(rgb.iter()) {
+                            for (value, &channel) in mean.iter_mut().zip(color.iter()) {
+                                *value += (count as i32 * channel) as f64 / 16.0;
+                            }
+                        }
+                        mean
+                    })
+                })
+                .collect()
+        })
+        .collect();
+    let mut mean_sums: Vec<Vec<MeanColorSum>> = vec![Vec::new(); unique_count];
+    for (u, tiles) in users.iter().enumerate() {
+        for &t in tiles {
+            let mut sum = [[0f64; 3]; 4];
+            for (i, pixel) in tile_pixels[t].iter().enumerate() {
+                for (value, &channel) in sum[tile_quadrant(i)].iter_mut().zip(pixel.iter()) {
+                    *value += channel as f64 / 16.0;
+                }
+            }
+            let sum_sq = sum.iter().flatten().map(|value| value * value).sum();
+            add_mean_color_sum(&mut mean_sums[u], MeanColorSum { palette: tile_palette(t), count: 1.0, sum, sum_sq });
+        }
+    }
+    let lower_bound = |sums: &[MeanColorSum], v: usize| -> u64 {
+        let bound: f64 = sums
+            .iter()
+            .map(|entry| {
+                let means = &pattern_means[v][entry.palette];
+                let dot: f64 = entry.sum.iter().flatten().zip(means.iter().flatten()).map(|(a, b)| a * b).sum();
+                let norm: f64 = means.iter().flatten().map(|value| value * value).sum();
+                entry.sum_sq - 2.0 * dot + entry.count * norm
+            })
+            .sum();
+        // Errors are integers: round down with a margin for the float rounding
+        ((bound * 16.0).max(0.0).floor() as u64).saturating_sub(1)
+    };
+
+    // Best merge target for pattern u: (target, added error)
+    let best_target = |u: usize,
+                       users: &[Vec<usize>],
+                       mean_sums: &[Vec<MeanColorSum>],
+                       alive: &[bool],
+                       current_error: &[u64]|
+     -> Option<(usize, u64)> {
+        let mut best: Option<(usize, u64)> = None;
+        let pixel_count = (users[u].len() * 64).max(1) as f64;
+        let current: u64 = users[u].iter().map(|&t| current_error[t]).sum();
+        let max_rms = (current as f64 / pixel_count).sqrt() + threshold as f64;
+        // Min-heap on the bound: usually only a few targets are popped before the search stops
+        let mut targets: std::collections::BinaryHeap<std::cmp::Reverse<(u64, usize)>> = (0..unique_count)
+            .filter(|&v| v != u && alive[v])
+            .map(|v| std::cmp::Reverse((lower_bound(&mean_sums[u], v), v)))
+            .collect();
+        'targets: while let Some(std::cmp::Reverse((bound, v))) = targets.pop() {
+            // Later targets cannot do better
+            if best.is_some_and(|(_, best_cost)| bound.saturating_sub(current) >= best_cost) {
+                break;
+            }
+            if use_threshold && (bound as f64 / pixel_count).sqrt() > max_rms {
+                break;
+            }
+            let mut merged = 0u64;
+            for &t in users[u].iter() {
+                match tile_error(t, &patterns[v]) {
+                    Some(e) => merged += e,
+                    None => continue 'targets,
+                }
+                if let Some((_, best_cost)) = best {
+                    if merged.saturating_sub(current) >= best_cost {
+                        continue 'targets;
+                    }
+                }
+            }
+            if use_threshold && (merged as f64 / pixel_count).sqrt() > max_rms {
+                continue;
+            }
+            let cost = merged.saturating_sub(current);
+            if best.is_none_or(|(_, best_cost)| cost < best_cost) {
+                best = Some((v, cost));
+            }
+        }
+        best
+    };
+
+    // Pattern 0 (empty tile) is never merged away, but other tiles may merge into it
+    let mut best: Vec<Option<(usize, u64)>> = (0..unique_count)
+        .map(|u| if u == 0 { None } else { best_target(u, &users, &mean_sums, &alive, &current_error) })
+        .collect();
+    let mut merged_count = 0usize;
+    let merges_to_budget = unique_count.saturating_sub(max_unique_tiles.max(1)).max(1);
+
+    loop {
+        if !use_threshold && alive_count <= max_unique_tiles.max(1) {
+            break;
+        }
+
+        let candidate = best
+            .iter()
+            .enumerate()
+            .filter(|(u, _)| alive[*u])
+            .filter_map(|(u, entry)| entry.map(|(v, cost)| (u, v, cost)))
+            .min_by_key(|&(_, _, cost)| cost);
+        let Some((u, v, _)) = candidate else {
+            break;
+        };
+
+        // Move every tile of u onto pattern v
+        let moved = std::mem::take(&mut users[u]);
+        for &t in moved.iter() {
+            current_error[t] = tile_error(t, &patterns[v]).unwrap_or(0);
+            tile_to_unique[t] = v;
+        }
+        users[v].extend(moved);
+        for entry in std::mem::take(&mut mean_sums[u]) {
+            add_mean_color_sum(&mut mean_sums[v], entry);
+        }
+        alive[u] = false;
+        alive_count -= 1;
+        merged_count += 1;
+        best[u] = None;
+        if merged_count.is_multiple_of(64) {
+            let done = if use_threshold { 0 } else { merged_count * 5 / merges_to_budget };
+            emit_progress(app, 80 + done.min(4) as u8, &format!("Réduction des tuiles ({} fusionnées)...", merged_count));
+        }
+
+        // v gained users, and every pattern that targeted u needs a new target
+        for w in 1..unique_count {
+            if !alive[w] {
+                continue;
+            }
+            let stale = w == v || matches!(best[w], Some((target, _)) if target == u);
+            if stale {
+                best[w] = best_target(w, &users, &mean_sums, &alive, &current_error);
+            }
+        }
+    }
+
+    if merged_count == 0 {
+        return 0;
+    }
+
+    // Re-render tiles that now use another pattern
+    for (tile_idx, &unique_idx) in tile_to_unique.iter().enumerate() {
+        if palette_result.empty_tiles.get(tile_idx).copied().unwrap_or(false) {
+            continue;
+        }
+        let tile_x = (tile_idx % tiles_x as usize) as u32;
+        let tile_y = (tile_idx / tiles_x as usize) as u32;
+        let rgb = &palette_rgb[tile_palette(tile_idx)];
+        for py in 0..8u32 {
+            for px in 0..8u32 {
+                let color = rgb[patterns[unique_idx][(py * 8 + px) as usize] as usize & 0x0F];
+                preview.put_pixel(
+                    tile_x * 8 + px,
+                    tile_y * 8 + py,
+                    Rgba([color[0] as u8, color[1] as u8, color[2] as u8, 255]),
+                );
+            }
+        }
+    }
+
+    // Compact the unique tile list and remap tile_to_unique
+    let mut old_to_new = vec![0usize; unique_count];
+    let mut compacted: Vec<[u8; 32]> = Vec::with_capacity(alive_count);
+    for (old_idx, tile) in unique_tiles.iter().enumerate() {
+        if alive[old_idx] {
+            old_to_new[old_idx] = compacted.len();
+            compacted.push(*tile);
+        }
+    }
+    for idx in tile_to_unique.iter_mut() {
+        *idx = old_to_new[*idx];
+    }
+    *unique_tiles = compacted;
+
+    merged_count
+}
+
 // ===== PC-Engine Export Functions =====
 
 #[derive(Serialize)]
@@ -1351,6 +6485,13 @@ fn export_plain_text(
     bat_height: u32,      // BAT height in tiles (32, 64)
     offset_x: u32,        // Image X offset in BAT (in tiles)
     offset_y: u32,        // Image Y offset in BAT (in tiles)
+    palette_band_rows: u32,  // Band height in tile rows for per-band palettes (0 = none)
+    raster_bands: String,  // Raster split bands, one "start_line,scroll_x,scroll_y[,bat_x,bat_y]" per line
+    sgx_layer: u32,  // SuperGrafx layer: 0 = none, 1 = VDC1 (with the VPC settings), 2 = VDC2
+    overlay_sprites: Vec<OverlaySprite>,  // Color boost sprites (empty = none)
+    sprite_palettes: Vec<Vec<String>>,  // Palettes of the overlay sprites
+    map_order: String,  // Large map: "" = none, "columns" or "rows" (BAT = top-left window of the map)
+    pixel_indices: Vec<u8>,  // Color index of every pixel (indexed palettes mode, empty = match the colors)
 ) -> Result<ExportResult, String> {
     // Decode PNG image
     let img = image::load_from_memory(&image_data)
@@ -1358,9 +6499,14 @@ fn export_plain_text(
         .to_rgba8();
 
     let (width, height) = img.dimensions();
+    check_pixel_indices(&pixel_indices, width, height)?;
     let tiles_x = width / 8;
     let tiles_y = height / 8;
     let total_tiles = (tiles_x * tiles_y) as usize;
+    let bands = parse_raster_bands(&raster_bands, height)?;
+    let map_by_columns = parse_map_order(&map_order, &bands)?;
+    // A large map starts at the top-left corner of the BAT
+    let (offset_x, offset_y) = if map_by_columns.is_some() { (0, 0) } else { (offset_x, offset_y) };
 
     // Build unique tiles and mapping
     // Empty tile is always first (32 bytes of zeros = all pixels are color index 0)
@@ -1382,8 +6528,12 @@ fn export_plain_text(
         let palette_idx = tile_palette_map.get(tile_idx).copied().unwrap_or(0);
         let palette = palettes.get(palette_idx).cloned().unwrap_or_default();
 
-        // Encode tile to planar format
-        let tile_data = encode_tile_planar(&img, tile_x, tile_y, &palette);
+        // Encode tile to planar format (indexed palettes: the source indices as they are)
+        let tile_data = if pixel_indices.is_empty() {
+            encode_tile_planar(&img, tile_x, tile_y, &palette)
+        } else {
+            encode_tile_indices(&tile_indices(&pixel_indices, width, tile_x, tile_y))
+        };
 
         // Check for duplicate
         let existing_idx = unique_tiles.iter().position(|t| *t == tile_data);
@@ -1396,6 +6546,8 @@ fn export_plain_text(
         }
     }
 
+    check_tiles_vram(vram_base_address, unique_tiles.len())?;
+
     // Generate output text
     let mut output = String::new();
 
@@ -1407,8 +6559,17 @@ fn export_plain_text(
 
     // Stats
     let bat_total = (bat_width * bat_height) as usize;
+    if sgx_layer > 0 {
+        output.push_str(&format!("; SuperGrafx layer: VDC{} (palettes shared by both layers)\n", sgx_layer));
+    }
     output.push_str(&format!("; Image: {}x{} pixels ({} tiles)\n", width, height, total_tiles));
     output.push_str(&format!("; BAT: {}x{} tiles, image at offset ({},{})\n", bat_width, bat_height, offset_x, offset_y));
+    if let Some(by_columns) = map_by_columns {
+        output.push_str(&format!(
+            "; Map: {}x{} tiles streamed by {}, BAT = top-left window\n",
+            tiles_x, tiles_y, if by_columns { "columns" } else { "rows" }
+        ));
+    }
     output.push_str(&format!("; Unique tiles: {} (saved {} duplicates)\n", unique_tiles.len(), total_tiles - unique_tiles.len()));
     output.push_str(&format!("; VRAM base address: ${:04X}\n", vram_base_address));
     output.push_str(&format!("; Tiles size: {} bytes\n", unique_tiles.len() * 32));
@@ -1421,6 +6582,8 @@ fn export_plain_text(
     output.push_str("; ----------------------------------------\n");
     output.push_str("BAT:\n");
 
+    let bat_layout = layout_bat(tiles_x, tiles_y, bat_width, bat_height, offset_x, offset_y, &bands)?;
+
     for bat_y in 0..bat_height {
         if bat_y > 0 {
             output.push('\n');
@@ -1428,31 +6591,9 @@ fn export_plain_text(
         output.push_str(&format!("  ; Row {}\n", bat_y));
 
         for bat_x in 0..bat_width {
-            // Position in the source image (accounting for offset)
-            let img_x = bat_x as i32 - offset_x as i32;
-            let img_y = bat_y as i32 - offset_y as i32;
-
-            let (unique_idx, palette_idx) = if img_x >= 0 && img_y >= 0
-                && img_x < tiles_x as i32 && img_y < tiles_y as i32 {
-                // Tile within the image area
-                let tile_idx = img_y as usize * tiles_x as usize + img_x as usize;
-                let uid = tile_to_unique.get(tile_idx).copied().unwrap_or(0);
-                let pid = if empty_tiles.get(tile_idx).copied().unwrap_or(false) {
-                    0u16
-                } else {
-                    tile_palette_map.get(tile_idx).copied().unwrap_or(0) as u16
-                };
-                (uid, pid)
-            } else {
-                // Outside image area = empty tile (index 0, palette 0)
-                (0, 0u16)
-            };
-
-            // VRAM is word-addressed (16-bit), each tile = 16 words (32 bytes)
-            // BAT address field = (tile_word_address >> 4) & 0x0FFF
-            let tile_address = vram_base_address + (unique_idx as u32 * 16);
-            let address_field = ((tile_address >> 4) & 0x0FFF) as u16;
-            let bat_word = (palette_idx << 12) | address_field;
+            // Image tile laid out at this BAT cell (offset or raster band position)
+            let cell = bat_layout[(bat_y * bat_width + bat_x) as usize];
+            let bat_word = bat_entry_word(cell, &tile_to_unique, &tile_palette_map, &empty_tiles, vram_base_address);
 
             if bat_x == 0 {
                 output.push_str(&format!("  .dw ${:04X}", bat_word));
@@ -1463,6 +6604,27 @@ fn export_plain_text(
     }
     output.push_str("\n\n");
 
+    // MAP - full-size map for a scroll engine, the BAT above being its first screen
+    if let Some(by_columns) = map_by_columns {
+        let words = map_stream_words(tiles_x, tiles_y, by_columns, &tile_to_unique, &tile_palette_map, &empty_tiles, vram_base_address);
+        let (unit, line_length) = if by_columns { ("Column", tiles_y) } else { ("Row", tiles_x) };
+        output.push_str("; ----------------------------------------\n");
+        output.push_str("; MAP - Width, height (tiles), then BAT words ");
+        output.push_str(if by_columns { "column by column (top to bottom)\n" } else { "row by row (left to right)\n" });
+        output.push_str(&format!("; Map column x goes to BAT column x % {}, map row y to BAT row y % {}\n", bat_width, bat_height));
+        output.push_str("; ----------------------------------------\n");
+        output.push_str("MAP:\n");
+        output.push_str(&format!("  .dw {},{}\n", words[0], words[1]));
+        for (line, chunk) in words[2..].chunks(line_length as usize).enumerate() {
+            output.push_str(&format!("  ; {} {}\n", unit, line));
+            for part in chunk.chunks(16) {
+                let hex: Vec<String> = part.iter().map(|word| format!("${:04X}", word)).collect();
+                output.push_str(&format!("  .dw {}\n", hex.join(",")));
+            }
+        }
+        output.push('\n');
+    }
+
     // TILES data
     output.push_str("; ----------------------------------------\n");
     output.push_str("; TILES - Planar format (32 bytes per tile)\n");
@@ -1502,7 +6664,8 @@ fn export_plain_text(
     output.push_str("; ----------------------------------------\n");
     output.push_str("PALETTES:\n");
 
-    for (pal_idx, palette) in palettes.iter().enumerate() {
+    // With per-band palettes, only the first band is loaded up front (see RASTER_PALETTES)
+    for (pal_idx, palette) in palettes.iter().take(16).enumerate() {
         output.push_str(&format!("  ; Palette {}\n", pal_idx));
         output.push_str("  .dw ");
 
@@ -1522,6 +6685,66 @@ fn export_plain_text(
         output.push('\n');
     }
 
+    if !bands.is_empty() {
+        output.push_str("\n; ----------------------------------------\n");
+        output.push_str("; RASTER - Scroll and palette entries to load per screen line\n");
+        for (band_index, band) in bands.iter().enumerate() {
+            let (bat_x, bat_y) = band.bat_origin(offset_x, offset_y);
+            output.push_str(&format!(
+                "; Band {}: lines {}-{}, BAT ({},{}), scroll ({},{})\n",
+                band_index, band.start_line, band.end_line - 1, bat_x, bat_y, band.scroll_x, band.scroll_y
+            ));
+        }
+        output.push_str("; Format: .dw line, BXR, BYR, count, then count x (.dw VCE index, color); $FFFF = end\n");
+        output.push_str("; ----------------------------------------\n");
+        output.push_str("RASTER:\n");
+        for (line, bxr, byr, changes) in raster_split_table(&bands, &palettes, palette_band_rows, offset_x, offset_y) {
+            output.push_str(&format!("  .dw ${:04X},${:04X},${:04X},${:04X}\n", line, bxr, byr, changes.len()));
+            for chunk in changes.chunks(8) {
+                let words: Vec<String> = chunk
+                    .iter()
+                    .map(|(index, word)| format!("${:04X},${:04X}", index, word))
+                    .collect();
+                output.push_str(&format!("  .dw {}\n", words.join(",")));
+            }
+        }
+        output.push_str("  .dw $FFFF\n");
+    } else if palette_band_rows > 0 && palettes.len() > 16 {
+        output.push_str("\n; ----------------------------------------\n");
+        output.push_str("; RASTER_PALETTES - Palette entries to reload per band\n");
+        output.push_str(&format!("; Bands of {} lines, lines relative to the top of the BAT\n", palette_band_rows * 8));
+        output.push_str("; Format: .dw line, count, then count x (.dw VCE index, color); $FFFF = end\n");
+        output.push_str("; ----------------------------------------\n");
+        output.push_str("RASTER_PALETTES:\n");
+        for (line, changes) in palette_raster_changes(&palettes, palette_band_rows, offset_y * 8) {
+            output.push_str(&format!("  .dw ${:04X},${:04X}\n", line, changes.len()));
+            for chunk in changes.chunks(8) {
+                let words: Vec<String> = chunk
+                    .iter()
+                    .map(|(index, word)| format!("${:04X},${:04X}", index, word))
+                    .collect();
+                output.push_str(&format!("  .dw {}\n", words.join(",")));
+            }
+        }
+        output.push_str("  .dw $FFFF\n");
+    }
+
+    if !overlay_sprites.is_empty() {
+        let (sprite_address, patterns, satb) = overlay_sprite_data(&overlay_sprites, vram_base_address, unique_tiles.len(), offset_x, offset_y)?;
+        let description = format!("{} color boost sprites (16x16, 64 words each)", patterns.len());
+        push_sprite_asm(&mut output, &description, sprite_address, &patterns, &satb, &sprite_palettes);
+    }
+
+    if sgx_layer == 1 {
+        output.push_str("\n; ----------------------------------------\n");
+        output.push_str("; VPC - SuperGrafx priority ($0008) and window widths ($000A, $000C)\n");
+        output.push_str("; $3333 = both VDCs enabled in every region, VDC1 in front\n");
+        output.push_str("; ----------------------------------------\n");
+        output.push_str("VPC:\n");
+        let words: Vec<String> = SGX_VPC_REGISTERS.iter().map(|word| format!("${:04X}", word)).collect();
+        output.push_str(&format!("  .dw {}\n", words.join(",")));
+    }
+
     Ok(ExportResult {
         plain_text: output,
         tile_count: total_tiles,
@@ -1530,6 +6753,14 @@ fn export_plain_text(
     })
 }
 
+/// Color indices sent along with an exported image must cover it exactly
+fn check_pixel_indices(pixel_indices: &[u8], width: u32, height: u32) -> Result<(), String> {
+    if !pixel_indices.is_empty() && pixel_indices.len() != (width * height) as usize {
+        return Err("Les indices de couleur ne correspondent pas à l'image exportée".to_string());
+    }
+    Ok(())
+}
+
 /// Debug flag for encode_tile_planar - only log first tile
 static DEBUG_TILE_LOGGED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
 
@@ -1612,6 +6843,157 @@ fn encode_tile_planar(
     data
 }
 
+/// Encode a 16x16 sprite cell (palette indices, row-major) to PC-Engine sprite format (64 words):
+/// plane 0 for lines 0-15, then planes 1, 2 and 3, bit 15 = leftmost pixel
+fn encode_sprite_cell(pixels: &[u8]) -> [u16; 64] {
+    let mut data = [0u16; 64];
+    for plane in 0..4 {
+        for line in 0..16 {
+            let mut word = 0u16;
+            for px in 0..16 {
+                let index = pixels.get(line * 16 + px).copied().unwrap_or(0);
+                word |= (((index >> plane) & 1) as u16) << (15 - px);
+            }
+            data[plane * 16 + line] = word;
+        }
+    }
+    data
+}
+
+/// SATB entry (4 words) for a sprite at screen position (x, y): Y + 64, X + 32,
+/// pattern address >> 5 and attributes (palette, SPBG in front of the background, size, flips)
+fn satb_entry(x: i32, y: i32, pattern_address: u32, attributes: u16) -> [u16; 4] {
+    [
+        ((y + 64) & 0x3FF) as u16,
+        ((x + 32) & 0x3FF) as u16,
+        ((pattern_address >> 5) & 0x7FE) as u16,
+        attributes,
+    ]
+}
+
+/// Sprites laid out in VRAM: address of the first pattern, words of each pattern and SATB entries
+type SpriteVramData = (u32, Vec<Vec<u16>>, Vec<[u16; 4]>);
+
+/// Sprite patterns (64 words each) and SATB (64 entries) of the overlay sprites, whose patterns
+/// are placed in VRAM right after the background tiles and whose positions include the BAT
+/// offset of the image (in tiles)
+fn overlay_sprite_data(
+    sprites: &[OverlaySprite],
+    vram_base_address: u32,
+    unique_tile_count: usize,
+    offset_x: u32,
+    offset_y: u32,
+) -> Result<SpriteVramData, String> {
+    // Sprite patterns must start on a 64-word boundary
+    let tiles_end = vram_base_address + unique_tile_count as u32 * 16;
+    let sprite_address = tiles_end.div_ceil(64) * 64;
+    if sprite_address + sprites.len() as u32 * 64 > 0x8000 {
+        return Err("Les sprites de couleur dépassent la VRAM".to_string());
+    }
+
+    let patterns: Vec<Vec<u16>> = sprites.iter().map(|sprite| encode_sprite_cell(&sprite.pixels).to_vec()).collect();
+    let mut satb = vec![[0u16; 4]; SPRITE_LIMIT];
+    for (index, sprite) in sprites.iter().take(SPRITE_LIMIT).enumerate() {
+        // Palette in bits 0-3, SPBG (bit 7) puts the sprite in front of the background
+        let attributes = (sprite.palette as u16 & 0x0F) | 0x0080;
+        // The sprites follow the image where the BAT offset puts it on screen
+        let x = sprite.x as i32 + offset_x as i32 * 8;
+        let y = sprite.y as i32 + offset_y as i32 * 8;
+        satb[index] = satb_entry(x, y, sprite_address + index as u32 * 64, attributes);
+    }
+    Ok((sprite_address, patterns, satb))
+}
+
+/// Append the SPRITE_PATTERNS, SATB and SPRITE_PALETTES sections of an assembly export
+fn push_sprite_asm(
+    output: &mut String,
+    description: &str,
+    sprite_address: u32,
+    patterns: &[Vec<u16>],
+    satb: &[[u16; 4]],
+    palettes: &[Vec<String>],
+) {
+    push_sprite_patterns_asm(output, description, sprite_address, patterns);
+
+    output.push_str("\n; ----------------------------------------\n");
+    output.push_str(&format!("; SATB - Sprite attribute table ({} entries: Y, X, pattern, attributes)\n", satb.len()));
+    if satb.len() > SPRITE_LIMIT {
+        output.push_str(&format!("; Warning: more than {} entries, split the table across frames or screens\n", SPRITE_LIMIT));
+    }
+    output.push_str("; ----------------------------------------\n");
+    output.push_str("SATB:\n");
+    for entry in satb {
+        output.push_str(&format!("  .dw ${:04X},${:04X},${:04X},${:04X}\n", entry[0], entry[1], entry[2], entry[3]));
+    }
+
+    push_sprite_palettes_asm(output, palettes);
+}
+
+/// Append the SPRITE_PATTERNS section of an assembly export
+fn push_sprite_patterns_asm(output: &mut String, description: &str, sprite_address: u32, patterns: &[Vec<u16>]) {
+    output.push_str("\n; ----------------------------------------\n");
+    output.push_str(&format!("; SPRITE_PATTERNS - {}\n", description));
+    output.push_str(&format!("; VRAM address: ${:04X}, per 16x16 cell: planes 0-3 lines 0-15\n", sprite_address));
+    output.push_str("; ----------------------------------------\n");
+    output.push_str("SPRITE_PATTERNS:\n");
+    for (sprite_idx, pattern) in patterns.iter().enumerate() {
+        output.push_str(&format!("  ; Sprite {}\n", sprite_idx));
+        for chunk in pattern.chunks(16) {
+            let words: Vec<String> = chunk.iter().map(|word| format!("${:04X}", word)).collect();
+            output.push_str(&format!("  .dw {}\n", words.join(",")));
+        }
+    }
+}
+
+/// Append the SPRITE_PALETTES section of an assembly export
+fn push_sprite_palettes_asm(output: &mut String, palettes: &[Vec<String>]) {
+    output.push_str("\n; ----------------------------------------\n");
+    output.push_str("; SPRITE_PALETTES - VCE $100+ (color 0 = transparent)\n");
+    output.push_str("; ----------------------------------------\n");
+    output.push_str("SPRITE_PALETTES:\n");
+    for (pal_idx, palette) in palettes.iter().take(16).enumerate() {
+        output.push_str(&format!("  ; Sprite palette {}\n", pal_idx));
+        let words: Vec<String> = (0..16)
+            .map(|index| format!("${:04X}", palette.get(index).map(|c| color_to_pce_word(c)).unwrap_or(0)))
+            .collect();
+        output.push_str(&format!("  .dw {}\n", words.join(",")));
+    }
+}
+
+/// Sprite patterns, SATB and sprite palettes as binary data, each with its own endianness
+fn sprite_binaries(
+    patterns: &[Vec<u16>],
+    satb: &[[u16; 4]],
+    palettes: &[Vec<String>],
+    patterns_big_endian: bool,
+    satb_big_endian: bool,
+    pal_big_endian: bool,
+) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+    let push_word = |data: &mut Vec<u8>, word: u16, big_endian: bool| {
+        if big_endian {
+            data.extend_from_slice(&word.to_be_bytes());
+        } else {
+            data.extend_from_slice(&word.to_le_bytes());
+        }
+    };
+    let mut pattern_data = Vec::new();
+    for word in patterns.iter().flatten() {
+        push_word(&mut pattern_data, *word, patterns_big_endian);
+    }
+    let mut satb_data = Vec::new();
+    for word in satb.iter().flatten() {
+        push_word(&mut satb_data, *word, satb_big_endian);
+    }
+    let mut pal_data = Vec::new();
+    for palette in palettes.iter().take(16) {
+        for index in 0..16 {
+            let word = palette.get(index).map(|c| color_to_pce_word(c)).unwrap_or(0);
+            push_word(&mut pal_data, word, pal_big_endian);
+        }
+    }
+    (pattern_data, satb_data, pal_data)
+}
+
 /// Convert a hex color (#RRGGBB) to PC-Engine 9-bit RGB333 word
 /// PCE format: 0000 000G GGRR RBBB
 /// G=bits 6-8, R=bits 3-5, B=bits 0-2
@@ -1636,6 +7018,12 @@ struct BinaryExportResult {
     bat: Vec<u8>,
     tiles: Vec<u8>,
     palettes: Vec<u8>,
+    raster: Vec<u8>,  // Per-band palette reload and raster split table (empty without bands)
+    vpc: Vec<u8>,  // SuperGrafx VPC registers (VDC1 layer only, empty otherwise)
+    sprites: Vec<u8>,  // Overlay sprite patterns (empty without overlay)
+    satb: Vec<u8>,  // Overlay SATB, 64 entries (empty without overlay)
+    sprite_palettes: Vec<u8>,  // Overlay sprite palettes, VCE $100+ (empty without overlay)
+    map: Vec<u8>,  // Large map in streaming order (empty without map)
     tile_count: usize,
     unique_tile_count: usize,
     // Debug info
@@ -1663,6 +7051,13 @@ fn export_binaries(
     bat_height: u32,      // BAT height in tiles (32, 64)
     offset_x: u32,        // Image X offset in BAT (in tiles)
     offset_y: u32,        // Image Y offset in BAT (in tiles)
+    palette_band_rows: u32,  // Band height in tile rows for per-band palettes (0 = none)
+    raster_bands: String,  // Raster split bands, one "start_line,scroll_x,scroll_y[,bat_x,bat_y]" per line
+    sgx_layer: u32,  // SuperGrafx layer: 0 = none, 1 = VDC1 (with the VPC settings), 2 = VDC2
+    overlay_sprites: Vec<OverlaySprite>,  // Color boost sprites (empty = none)
+    sprite_palettes: Vec<Vec<String>>,  // Palettes of the overlay sprites
+    map_order: String,  // Large map: "" = none, "columns" or "rows" (BAT = top-left window of the map)
+    pixel_indices: Vec<u8>,  // Color index of every pixel (indexed palettes mode, empty = match the colors)
 ) -> Result<BinaryExportResult, String> {
     // Decode PNG image
     let img = image::load_from_memory(&image_data)
@@ -1670,9 +7065,14 @@ fn export_binaries(
         .to_rgba8();
 
     let (width, height) = img.dimensions();
+    check_pixel_indices(&pixel_indices, width, height)?;
     let tiles_x = width / 8;
     let tiles_y = height / 8;
     let total_tiles = (tiles_x * tiles_y) as usize;
+    let bands = parse_raster_bands(&raster_bands, height)?;
+    let map_by_columns = parse_map_order(&map_order, &bands)?;
+    // A large map starts at the top-left corner of the BAT
+    let (offset_x, offset_y) = if map_by_columns.is_some() { (0, 0) } else { (offset_x, offset_y) };
 
     // Reset debug flag for tile logging
     DEBUG_TILE_LOGGED.store(false, std::sync::atomic::Ordering::SeqCst);
@@ -1753,8 +7153,12 @@ fn export_binaries(
             eprintln!("ERROR: Tile {} has empty palette (palette_idx={}), palettes.len()={}", tile_idx, palette_idx, palettes.len());
         }
 
-        // Encode tile to planar format
-        let tile_data = encode_tile_planar(&img, tile_x, tile_y, &palette);
+        // Encode tile to planar format (indexed palettes: the source indices as they are)
+        let tile_data = if pixel_indices.is_empty() {
+            encode_tile_planar(&img, tile_x, tile_y, &palette)
+        } else {
+            encode_tile_indices(&tile_indices(&pixel_indices, width, tile_x, tile_y))
+        };
 
         // Check for duplicate
         let existing_idx = unique_tiles.iter().position(|t| *t == tile_data);
@@ -1780,37 +7184,19 @@ fn export_binaries(
         if bat_big_endian { "big" } else { "little" },
         if pal_big_endian { "big" } else { "little" },
         if tiles_big_endian { "big" } else { "little" });
+    check_tiles_vram(vram_base_address, unique_tiles.len())?;
 
     // Generate BAT binary (16-bit words) - full BAT size with image positioned at offset
+    // (or each raster band at its own position)
+    let bat_layout = layout_bat(tiles_x, tiles_y, bat_width, bat_height, offset_x, offset_y, &bands)?;
     let bat_total = (bat_width * bat_height) as usize;
     let mut bat_data: Vec<u8> = Vec::with_capacity(bat_total * 2);
 
     for bat_y in 0..bat_height {
         for bat_x in 0..bat_width {
-            // Position in the source image (accounting for offset)
-            let img_x = bat_x as i32 - offset_x as i32;
-            let img_y = bat_y as i32 - offset_y as i32;
-
-            let (unique_idx, palette_idx) = if img_x >= 0 && img_y >= 0
-                && img_x < tiles_x as i32 && img_y < tiles_y as i32 {
-                // Tile within the image area
-                let tile_idx = img_y as usize * tiles_x as usize + img_x as usize;
-                let uid = tile_to_unique.get(tile_idx).copied().unwrap_or(0);
-                let pid = if empty_tiles.get(tile_idx).copied().unwrap_or(false) {
-                    0u16
-                } else {
-                    tile_palette_map.get(tile_idx).copied().unwrap_or(0) as u16
-                };
-                (uid, pid)
-            } else {
-                // Outside image area = empty tile (index 0, palette 0)
-                (0, 0u16)
-            };
-
-            // VRAM is word-addressed (16-bit), each tile = 16 words (32 bytes)
-            let tile_address = vram_base_address + (unique_idx as u32 * 16);
-            let address_field = ((tile_address >> 4) & 0x0FFF) as u16;
-            let bat_word = (palette_idx << 12) | address_field;
+            // Image tile laid out at this BAT cell (offset or raster band position)
+            let cell = bat_layout[(bat_y * bat_width + bat_x) as usize];
+            let bat_word = bat_entry_word(cell, &tile_to_unique, &tile_palette_map, &empty_tiles, vram_base_address);
 
             if bat_big_endian {
                 bat_data.push((bat_word >> 8) as u8);
@@ -1874,6 +7260,66 @@ fn export_binaries(
         }
     }
 
+    // Generate RASTER table for per-band palettes: line, count, (index, color) * count, $FFFF
+    // With raster bands: line, BXR, BYR, count, (index, color) * count, $FFFF
+    let mut raster_data: Vec<u8> = Vec::new();
+    let mut push_word = |word: u16| {
+        if pal_big_endian {
+            raster_data.push((word >> 8) as u8);
+            raster_data.push((word & 0xFF) as u8);
+        } else {
+            raster_data.push((word & 0xFF) as u8);
+            raster_data.push((word >> 8) as u8);
+        }
+    };
+    if !bands.is_empty() {
+        for (line, bxr, byr, changes) in raster_split_table(&bands, &palettes, palette_band_rows, offset_x, offset_y) {
+            push_word(line);
+            push_word(bxr);
+            push_word(byr);
+            push_word(changes.len() as u16);
+            for (index, word) in changes {
+                push_word(index);
+                push_word(word);
+            }
+        }
+        push_word(0xFFFF);
+    } else if palette_band_rows > 0 && palettes.len() > 16 {
+        for (line, changes) in palette_raster_changes(&palettes, palette_band_rows, offset_y * 8) {
+            push_word(line);
+            push_word(changes.len() as u16);
+            for (index, word) in changes {
+                push_word(index);
+                push_word(word);
+            }
+        }
+        push_word(0xFFFF);
+    }
+
+    // Large map: header and BAT words in streaming order, with the BAT endianness
+    let map_data: Vec<u8> = match map_by_columns {
+        Some(by_columns) => map_stream_words(tiles_x, tiles_y, by_columns, &tile_to_unique, &tile_palette_map, &empty_tiles, vram_base_address)
+            .iter()
+            .flat_map(|word| if bat_big_endian { word.to_be_bytes() } else { word.to_le_bytes() })
+            .collect(),
+        None => Vec::new(),
+    };
+
+    // SuperGrafx VPC registers, written low byte first like the hardware ports
+    let vpc_data: Vec<u8> = if sgx_layer == 1 {
+        SGX_VPC_REGISTERS.iter().flat_map(|word| word.to_le_bytes()).collect()
+    } else {
+        Vec::new()
+    };
+
+    // Overlay sprites: patterns follow the tiles endianness, SATB the BAT one, palettes the palette one
+    let (sprite_data, satb_data, sprite_pal_data) = if overlay_sprites.is_empty() {
+        (Vec::new(), Vec::new(), Vec::new())
+    } else {
+        let (_, patterns, satb) = overlay_sprite_data(&overlay_sprites, vram_base_address, unique_tiles.len(), offset_x, offset_y)?;
+        sprite_binaries(&patterns, &satb, &sprite_palettes, tiles_big_endian, bat_big_endian, pal_big_endian)
+    };
+
     let empty_count = empty_tiles.iter().filter(|&&b| b).count();
 
     // Build debug info string for JavaScript console
@@ -1918,6 +7364,12 @@ fn export_binaries(
         bat: bat_data,
         tiles: tiles_data,
         palettes: pal_data,
+        raster: raster_data,
+        vpc: vpc_data,
+        sprites: sprite_data,
+        satb: satb_data,
+        sprite_palettes: sprite_pal_data,
+        map: map_data,
         tile_count: total_tiles,
         unique_tile_count: unique_tiles.len(),
         image_width: width,
@@ -1930,17 +7382,291 @@ fn export_binaries(
     })
 }
 
-/// Save binary export to disk - creates a directory and writes 3 files
+/// Sprite patterns and SATB of a sprite conversion. Patterns start at the VRAM address rounded up
+/// to the sprite size (see `sprite_pattern_cells`).
+/// SATB positions are relative to the top-left corner of the image.
+fn sprite_pattern_data(sprites: &[SpriteCell], vram_base_address: u32) -> Result<SpriteVramData, String> {
+    let sprite_words = sprites
+        .first()
+        .map(|sprite| sprite_pattern_cells(sprite.width, sprite.height))
+        .unwrap_or(1)
+        * 64;
+    let sprite_address = vram_base_address.div_ceil(sprite_words) * sprite_words;
+    if sprite_address + sprites.len() as u32 * sprite_words > 0x8000 {
+        return Err("Les motifs de sprites dépassent la VRAM".to_string());
+    }
+
+    let mut patterns = Vec::with_capacity(sprites.len());
+    let mut satb = Vec::with_capacity(sprites.len());
+    for (index, sprite) in sprites.iter().enumerate() {
+        patterns.push(encode_sprite_pattern(sprite));
+        let attributes = (sprite.palette as u16 & 0x0F) | sprite_size_attributes(sprite.width, sprite.height);
+        satb.push(satb_entry(sprite.x as i32, sprite.y as i32, sprite_address + index as u32 * sprite_words, attributes));
+    }
+    Ok((sprite_address, patterns, satb))
+}
+
+/// 16x16 cells a sprite takes in VRAM. With CGY set the VDC reads each lower row of cells 2 cells
+/// further (the pattern code bit 1 is ignored), so rows always take 2 cells when the sprite is
+/// taller than 16, even when it is only 16 wide (16x32 = 4 cells, 16x64 = 8 cells).
+fn sprite_pattern_cells(width: u32, height: u32) -> u32 {
+    if height > 16 {
+        2 * (height / 16)
+    } else {
+        (width / 16).max(1)
+    }
+}
+
+/// Sprite pattern of any size, as laid out in VRAM: its 16x16 cells row by row, each row padded
+/// with an unused cell when a tall sprite is only 16 wide (see `sprite_pattern_cells`)
+fn encode_sprite_pattern(sprite: &SpriteCell) -> Vec<u16> {
+    let mut words = Vec::with_capacity((sprite_pattern_cells(sprite.width, sprite.height) * 64) as usize);
+    for cell_y in 0..sprite.height / 16 {
+        for cell_x in 0..sprite.width / 16 {
+            let mut cell = [0u8; 256];
+            for y in 0..16 {
+                for x in 0..16 {
+                    let offset = ((cell_y * 16 + y) * sprite.width + cell_x * 16 + x) as usize;
+                    cell[(y * 16 + x) as usize] = sprite.pixels.get(offset).copied().unwrap_or(0);
+                }
+            }
+            words.extend_from_slice(&encode_sprite_cell(&cell));
+        }
+        if sprite.width == 16 && sprite.height > 16 {
+            words.extend_from_slice(&[0u16; 64]);
+        }
+    }
+    words
+}
+
+/// SATB attribute bits for the sprite size: CGX (bit 8) = 32 wide, CGY (bits 12-13) = 32 or 64 high,
+/// plus SPBG (bit 7) to show the sprite in front of the background
+fn sprite_size_attributes(width: u32, height: u32) -> u16 {
+    let cgx = if width == 32 { 0x0100 } else { 0 };
+    let cgy = match height {
+        32 => 0x1000,
+        64 => 0x3000,
+        _ => 0,
+    };
+    0x0080 | cgx | cgy
+}
+
+/// Export a sprite conversion as PC-Engine assembly data
 #[tauri::command]
-fn save_binaries_to_disk(
-    base_path: String,
-    bat_data: Vec<u8>,
-    tiles_data: Vec<u8>,
-    pal_data: Vec<u8>,
-) -> Result<(), String> {
+fn export_sprite_plain_text(
+    sprites: Vec<SpriteCell>,
+    palettes: Vec<Vec<String>>,
+    vram_base_address: u32,  // Sprite patterns VRAM address (rounded up to the sprite size)
+) -> Result<String, String> {
+    let (sprite_address, patterns, satb) = sprite_pattern_data(&sprites, vram_base_address)?;
+
+    let mut output = String::new();
+    output.push_str("; ========================================\n");
+    output.push_str("; PC-Engine Sprite Data\n");
+    output.push_str("; Generated by Image2PCE II\n");
+    output.push_str("; ========================================\n");
+    let (width, height) = sprites.first().map(|sprite| (sprite.width, sprite.height)).unwrap_or((16, 16));
+    output.push_str(&format!("; Sprites: {} of {}x{} pixels\n", sprites.len(), width, height));
+    output.push_str(&format!("; Patterns size: {} bytes\n", patterns.iter().map(|p| p.len() * 2).sum::<usize>()));
+
+    let description = format!("{} sprites of {}x{} ({} words each)", sprites.len(), width, height, sprite_pattern_cells(width, height) * 64);
+    push_sprite_asm(&mut output, &description, sprite_address, &patterns, &satb, &palettes);
+    Ok(output)
+}
+
+#[derive(Serialize)]
+struct SpriteBinaryExportResult {
+    patterns: Vec<u8>,
+    satb: Vec<u8>,
+    palettes: Vec<u8>,
+    sprite_count: usize,
+}
+
+/// Export a sprite conversion as binary data (patterns, SATB template and sprite palettes)
+#[tauri::command]
+fn export_sprite_binaries(
+    sprites: Vec<SpriteCell>,
+    palettes: Vec<Vec<String>>,
+    vram_base_address: u32,  // Sprite patterns VRAM address (rounded up to the sprite size)
+    patterns_big_endian: bool,
+    satb_big_endian: bool,
+    pal_big_endian: bool,
+) -> Result<SpriteBinaryExportResult, String> {
+    let (_, patterns, satb) = sprite_pattern_data(&sprites, vram_base_address)?;
+    let (patterns, satb, palettes) = sprite_binaries(&patterns, &satb, &palettes, patterns_big_endian, satb_big_endian, pal_big_endian);
+    Ok(SpriteBinaryExportResult {
+        patterns,
+        satb,
+        palettes,
+        sprite_count: sprites.len(),
+    })
+}
+
+/// Animation laid out in VRAM: address of the first pattern, words of each unique pattern and
+/// the metasprite table of each frame
+type AnimationVramData = (u32, Vec<Vec<u16>>, Vec<Vec<[u16; 4]>>);
+
+/// Unique patterns and metasprite tables of a sprite animation. Each metasprite entry is
+/// Y offset, X offset (signed, from the frame top-left corner), pattern address >> 5 and
+/// attributes (palette, size, SPBG, X flip bit 11, Y flip bit 15).
+fn sprite_animation_data(
+    patterns: &[SpriteCell],
+    frames: &[Vec<MetaspriteEntry>],
+    vram_base_address: u32,
+) -> Result<AnimationVramData, String> {
+    let (sprite_address, pattern_words, _) = sprite_pattern_data(patterns, vram_base_address)?;
+    // Unique patterns follow each other at the size the VDC reads, not the pixel size
+    let sprite_words = patterns
+        .first()
+        .map(|sprite| sprite_pattern_cells(sprite.width, sprite.height))
+        .unwrap_or(1)
+        * 64;
+    let mut metasprites = Vec::with_capacity(frames.len());
+    for entries in frames {
+        let mut table = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let pattern = patterns.get(entry.pattern).ok_or("Motif de sprite introuvable")?;
+            let mut attributes = (entry.palette as u16 & 0x0F) | sprite_size_attributes(pattern.width, pattern.height);
+            if entry.h_flip {
+                attributes |= 0x0800;
+            }
+            if entry.v_flip {
+                attributes |= 0x8000;
+            }
+            let address = sprite_address + entry.pattern as u32 * sprite_words;
+            table.push([entry.y as i16 as u16, entry.x as i16 as u16, ((address >> 5) & 0x7FE) as u16, attributes]);
+        }
+        metasprites.push(table);
+    }
+    Ok((sprite_address, pattern_words, metasprites))
+}
+
+/// Export a sprite animation as PC-Engine assembly data: unique patterns, metasprite tables
+/// and the shared sprite palettes
+#[tauri::command]
+fn export_sprite_animation_plain_text(
+    patterns: Vec<SpriteCell>,
+    frames: Vec<Vec<MetaspriteEntry>>,
+    palettes: Vec<Vec<String>>,
+    vram_base_address: u32,  // Sprite patterns VRAM address (rounded up to the sprite size)
+) -> Result<String, String> {
+    let (sprite_address, pattern_words, metasprites) = sprite_animation_data(&patterns, &frames, vram_base_address)?;
+
+    let mut output = String::new();
+    output.push_str("; ========================================\n");
+    output.push_str("; PC-Engine Sprite Animation Data\n");
+    output.push_str("; Generated by Image2PCE II\n");
+    output.push_str("; ========================================\n");
+    let (width, height) = patterns.first().map(|sprite| (sprite.width, sprite.height)).unwrap_or((16, 16));
+    output.push_str(&format!("; Frames: {}, unique sprites: {} of {}x{} pixels\n", frames.len(), patterns.len(), width, height));
+    output.push_str(&format!("; Patterns size: {} bytes\n", pattern_words.iter().map(|p| p.len() * 2).sum::<usize>()));
+
+    let description = format!("{} unique sprites of {}x{} ({} words each)", patterns.len(), width, height, sprite_pattern_cells(width, height) * 64);
+    push_sprite_patterns_asm(&mut output, &description, sprite_address, &pattern_words);
+
+    output.push_str("\n; ----------------------------------------\n");
+    output.push_str("; METASPRITES - Frame table, then per frame: sprite count and\n");
+    output.push_str("; Y, X (offsets from the frame corner), pattern, attributes per sprite\n");
+    output.push_str("; Add the object position plus 64 (Y) and 32 (X) before writing the SATB\n");
+    output.push_str("; ----------------------------------------\n");
+    output.push_str("METASPRITES:\n");
+    let labels: Vec<String> = (0..metasprites.len()).map(|index| format!("FRAME_{}", index)).collect();
+    for chunk in labels.chunks(8) {
+        output.push_str(&format!("  .dw {}\n", chunk.join(",")));
+    }
+    for (frame_idx, table) in metasprites.iter().enumerate() {
+        output.push_str(&format!("FRAME_{}:\n", frame_idx));
+        if table.len() > SPRITES_PER_LINE {
+            output.push_str(&format!("  ; Warning: {} sprites, some lines may exceed {} sprites\n", table.len(), SPRITES_PER_LINE));
+        }
+        output.push_str(&format!("  .dw {}\n", table.len()));
+        for entry in table {
+            output.push_str(&format!("  .dw ${:04X},${:04X},${:04X},${:04X}\n", entry[0], entry[1], entry[2], entry[3]));
+        }
+    }
+
+    push_sprite_palettes_asm(&mut output, &palettes);
+    Ok(output)
+}
+
+#[derive(Serialize)]
+struct SpriteAnimationExportResult {
+    patterns: Vec<u8>,
+    metasprites: Vec<u8>,
+    palettes: Vec<u8>,
+    pattern_count: usize,
+    frame_count: usize,
+}
+
+/// Export a sprite animation as binary data. The metasprite file holds the frame count,
+/// then for each frame its sprite count followed by 4 words per sprite.
+#[tauri::command]
+fn export_sprite_animation_binaries(
+    patterns: Vec<SpriteCell>,
+    frames: Vec<Vec<MetaspriteEntry>>,
+    palettes: Vec<Vec<String>>,
+    vram_base_address: u32,  // Sprite patterns VRAM address (rounded up to the sprite size)
+    patterns_big_endian: bool,
+    metasprites_big_endian: bool,
+    pal_big_endian: bool,
+) -> Result<SpriteAnimationExportResult, String> {
+    let (_, pattern_words, metasprites) = sprite_animation_data(&patterns, &frames, vram_base_address)?;
+    let (pattern_data, _, pal_data) = sprite_binaries(&pattern_words, &[], &palettes, patterns_big_endian, false, pal_big_endian);
+
+    let mut words = vec![metasprites.len() as u16];
+    for table in &metasprites {
+        words.push(table.len() as u16);
+        words.extend(table.iter().flatten());
+    }
+    let metasprite_data = words
+        .iter()
+        .flat_map(|word| if metasprites_big_endian { word.to_be_bytes() } else { word.to_le_bytes() })
+        .collect();
+
+    Ok(SpriteAnimationExportResult {
+        patterns: pattern_data,
+        metasprites: metasprite_data,
+        palettes: pal_data,
+        pattern_count: patterns.len(),
+        frame_count: frames.len(),
+    })
+}
+
+/// Files written by save_binaries_to_disk, each one only when not empty
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct BinaryFiles {
+    bat: Vec<u8>,
+    tiles: Vec<u8>,
+    palettes: Vec<u8>,
+    raster: Vec<u8>,  // Per-band palette table, written as .ras
+    vpc: Vec<u8>,  // SuperGrafx VPC registers, written as .vpc
+    sprites: Vec<u8>,  // Sprite patterns (.spr), SATB (.sat) and sprite palettes (.spal)
+    satb: Vec<u8>,
+    sprite_palettes: Vec<u8>,
+    metasprites: Vec<u8>,  // Metasprite tables of a sprite animation, written as .msp
+    map: Vec<u8>,  // Large map in streaming order, written as .map
+}
+
+/// Save binary export to disk - creates a directory and writes 3 files (plus the optional raster and VPC tables)
+#[tauri::command]
+fn save_binaries_to_disk(base_path: String, files: BinaryFiles) -> Result<(), String> {
     use std::fs;
     use std::path::Path;
 
+    let BinaryFiles {
+        bat: bat_data,
+        tiles: tiles_data,
+        palettes: pal_data,
+        raster: raster_data,
+        vpc: vpc_data,
+        sprites: sprite_data,
+        satb: satb_data,
+        sprite_palettes: sprite_pal_data,
+        metasprites: metasprite_data,
+        map: map_data,
+    } = files;
+
     let base = Path::new(&base_path);
 
     // Get the filename without extension for directory name
@@ -1961,12 +7687,45 @@ fn save_binaries_to_disk(
     let tiles_path = dir_path.join(format!("{}.tiles", dir_name));
     let pal_path = dir_path.join(format!("{}.pal", dir_name));
 
-    fs::write(&bat_path, &bat_data)
-        .map_err(|e| format!("Failed to write BAT file: {}", e))?;
-    fs::write(&tiles_path, &tiles_data)
-        .map_err(|e| format!("Failed to write tiles file: {}", e))?;
-    fs::write(&pal_path, &pal_data)
-        .map_err(|e| format!("Failed to write palette file: {}", e))?;
+    // Sprite-only exports have no background data
+    if !bat_data.is_empty() {
+        fs::write(&bat_path, &bat_data)
+            .map_err(|e| format!("Failed to write BAT file: {}", e))?;
+        fs::write(&tiles_path, &tiles_data)
+            .map_err(|e| format!("Failed to write tiles file: {}", e))?;
+        fs::write(&pal_path, &pal_data)
+            .map_err(|e| format!("Failed to write palette file: {}", e))?;
+    }
+
+    if !raster_data.is_empty() {
+        let raster_path = dir_path.join(format!("{}.ras", dir_name));
+        fs::write(&raster_path, &raster_data)
+            .map_err(|e| format!("Failed to write raster file: {}", e))?;
+    }
+
+    if !vpc_data.is_empty() {
+        let vpc_path = dir_path.join(format!("{}.vpc", dir_name));
+        fs::write(&vpc_path, &vpc_data)
+            .map_err(|e| format!("Failed to write VPC file: {}", e))?;
+    }
+
+    if !map_data.is_empty() {
+        let map_path = dir_path.join(format!("{}.map", dir_name));
+        fs::write(&map_path, &map_data)
+            .map_err(|e| format!("Failed to write map file: {}", e))?;
+    }
+
+    if !sprite_data.is_empty() {
+        for (extension, data) in [("spr", &sprite_data), ("sat", &satb_data), ("spal", &sprite_pal_data), ("msp", &metasprite_data)] {
+            // Animations have metasprite tables instead of a SATB
+            if data.is_empty() {
+                continue;
+            }
+            let sprite_path = dir_path.join(format!("{}.{}", dir_name, extension));
+            fs::write(&sprite_path, data)
+                .map_err(|e| format!("Failed to write sprite file: {}", e))?;
+        }
+    }
 
     Ok(())
 }
@@ -2326,7 +8085,500 @@ pub fn run() {
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_window_state::Builder::new().build())
-        .invoke_handler(tauri::generate_handler![open_image, run_conversion, export_plain_text, export_binaries, save_binaries_to_disk, save_html_report, save_project, load_project])
+        .invoke_handler(tauri::generate_handler![open_image, open_folder, load_source_preview, read_aseprite_info, read_aseprite_layer_groups, run_conversion, run_palette_sweep, validate_pce_art, run_sprite_conversion, run_sprite_animation, export_plain_text, export_binaries, export_sprite_plain_text, export_sprite_binaries, export_sprite_animation_plain_text, export_sprite_animation_binaries, save_binaries_to_disk, save_html_report, save_project, load_project])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn pcx_header(bits: u8, planes: u8, width: u16, height: u16, bytes_per_line: u16, rle: bool) -> Vec<u8> {
+        let mut data = vec![0u8; 128];
+        data[0] = 0x0A;
+        data[1] = 5;
+        data[2] = rle as u8;
+        data[3] = bits;
+        data[8..10].copy_from_slice(&(width - 1).to_le_bytes());
+        data[10..12].copy_from_slice(&(height - 1).to_le_bytes());
+        data[65] = planes;
+        data[66..68].copy_from_slice(&bytes_per_line.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn pcx_rle_256_colors() {
+        let mut data = pcx_header(8, 1, 4, 2, 4, true);
+        // Row 0: a run of 3, then a literal; row 1: literals, then a value above 0xBF as a run of 1
+        data.extend([0xC3, 0xC5, 0x07, 0x01, 0x02, 0x03, 0xC1, 0xD0]);
+        data.push(0x0C);
+        let mut palette = vec![0u8; 768];
+        palette[0xC5 * 3..0xC5 * 3 + 3].copy_from_slice(&[1, 2, 3]);
+        data.extend(palette);
+
+        let PcxImage::Indexed(image) = read_pcx(&data).unwrap() else { panic!("indexed PCX expected") };
+        assert_eq!((image.width, image.height), (4, 2));
+        assert_eq!(image.indices, vec![0xC5, 0xC5, 0xC5, 0x07, 0x01, 0x02, 0x03, 0xD0]);
+        assert_eq!(image.palette[0xC5], [1, 2, 3]);
+    }
+
+    #[test]
+    fn pcx_ega_planes() {
+        let mut data = pcx_header(1, 4, 8, 1, 1, false);
+        data[16 + 15 * 3..16 + 16 * 3].copy_from_slice(&[9, 9, 9]);
+        // Plane N gives bit N: pixels 0-3 take one plane each, pixel 7 all of them
+        data.extend([0b1000_0001, 0b0100_0001, 0b0010_0001, 0b0001_0001]);
+
+        let PcxImage::Indexed(image) = read_pcx(&data).unwrap() else { panic!("indexed PCX expected") };
+        assert_eq!(image.indices, vec![1, 2, 4, 8, 0, 0, 0, 15]);
+        assert_eq!(image.palette.len(), 16);
+        assert_eq!(image.palette[15], [9, 9, 9]);
+    }
+
+    #[test]
+    fn pcx_size_past_the_data() {
+        let mut data = pcx_header(8, 4, 0xFFFF, 0xFFFF, 0xFFFF, true);
+        data.extend([0, 0]);
+        assert_eq!(read_pcx(&data).err().as_deref(), Some("Fichier PCX tronqué"));
+    }
+
+    /// BMP with a Windows (40 bytes) or OS/2 (12 bytes) header, `rows` already padded
+    fn bmp(header_size: u32, width: i32, height: i32, bits: u16, palette: &[[u8; 3]], rows: &[u8]) -> Vec<u8> {
+        let entry_size = if header_size == 12 { 3 } else { 4 };
+        let pixel_offset = 14 + header_size as usize + palette.len() * entry_size;
+        let mut data = b"BM".to_vec();
+        data.extend(((pixel_offset + rows.len()) as u32).to_le_bytes());
+        data.extend([0; 4]);
+        data.extend((pixel_offset as u32).to_le_bytes());
+        data.extend(header_size.to_le_bytes());
+        if header_size == 12 {
+            data.extend((width as u16).to_le_bytes());
+            data.extend((height as u16).to_le_bytes());
+            data.extend(1u16.to_le_bytes());
+            data.extend(bits.to_le_bytes());
+        } else {
+            data.extend(width.to_le_bytes());
+            data.extend(height.to_le_bytes());
+            data.extend(1u16.to_le_bytes());
+            data.extend(bits.to_le_bytes());
+            data.extend([0; 16]);
+            data.extend((palette.len() as u32).to_le_bytes());
+            data.extend([0; 4]);
+        }
+        for color in palette {
+            data.extend([color[2], color[1], color[0]]);
+            if entry_size == 4 {
+                data.push(0);
+            }
+        }
+        data.extend(rows);
+        data
+    }
+
+    #[test]
+    fn bmp_row_order() {
+        let palette = [[0, 0, 0], [10, 20, 30], [40, 50, 60]];
+        // Rows are padded to 4 bytes, the bottom row comes first
+        let image = read_indexed_bmp(&bmp(40, 3, 2, 8, &palette, &[2, 2, 2, 0, 0, 1, 2, 0])).unwrap().unwrap();
+        assert_eq!(image.indices, vec![0, 1, 2, 2, 2, 2]);
+        assert_eq!(image.palette, palette.to_vec());
+        // A negative height stores the top row first
+        let image = read_indexed_bmp(&bmp(40, 3, -2, 8, &palette, &[2, 2, 2, 0, 0, 1, 2, 0])).unwrap().unwrap();
+        assert_eq!(image.indices, vec![2, 2, 2, 0, 1, 2]);
+    }
+
+    #[test]
+    fn bmp_os2_header() {
+        let palette: Vec<[u8; 3]> = (0..16).map(|index| [index * 16, 0, 255 - index * 16]).collect();
+        let image = read_indexed_bmp(&bmp(12, 3, 1, 4, &palette, &[0x12, 0x30, 0, 0])).unwrap().unwrap();
+        assert_eq!(image.indices, vec![1, 2, 3]);
+        assert_eq!(image.palette[3], [48, 0, 207]);
+    }
+
+    #[test]
+    fn bmp_size_past_the_data() {
+        let data = bmp(40, 0x7FFF_FFFF, 0x7FFF_FFFF, 8, &[[0, 0, 0]], &[0; 16]);
+        assert_eq!(read_indexed_bmp(&data).err().as_deref(), Some("Fichier BMP tronqué"));
+    }
+
+    fn ase_chunk(kind: u16, body: &[u8]) -> Vec<u8> {
+        let mut chunk = ((body.len() + 6) as u32).to_le_bytes().to_vec();
+        chunk.extend(kind.to_le_bytes());
+        chunk.extend(body);
+        chunk
+    }
+
+    fn ase_layer(flags: u16, kind: u16, level: u16, name: &str) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend(flags.to_le_bytes());
+        body.extend(kind.to_le_bytes());
+        body.extend(level.to_le_bytes());
+        body.extend([0; 6]);
+        body.push(255);
+        body.extend([0; 3]);
+        body.extend((name.len() as u16).to_le_bytes());
+        body.extend(name.as_bytes());
+        ase_chunk(0x2004, &body)
+    }
+
+    /// Image cel (zlib compressed or raw) at (0, 0)
+    fn ase_cel(layer: u16, z_index: i16, width: u16, height: u16, pixels: &[u8], compressed: bool) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend(layer.to_le_bytes());
+        body.extend([0; 4]);
+        body.push(255);
+        body.extend((if compressed { 2u16 } else { 0 }).to_le_bytes());
+        body.extend(z_index.to_le_bytes());
+        body.extend([0; 5]);
+        body.extend(width.to_le_bytes());
+        body.extend(height.to_le_bytes());
+        if compressed {
+            let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(pixels).unwrap();
+            body.extend(encoder.finish().unwrap());
+        } else {
+            body.extend(pixels);
+        }
+        ase_chunk(0x2005, &body)
+    }
+
+    fn ase_link(layer: u16, z_index: i16, frame: u16) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend(layer.to_le_bytes());
+        body.extend([0; 4]);
+        body.push(255);
+        body.extend(1u16.to_le_bytes());
+        body.extend(z_index.to_le_bytes());
+        body.extend([0; 5]);
+        body.extend(frame.to_le_bytes());
+        ase_chunk(0x2005, &body)
+    }
+
+    /// RGBA file; each frame gives its chunks and the padding after them (the frame size covers it)
+    fn ase_file(width: u16, height: u16, frames: &[(Vec<Vec<u8>>, usize)]) -> Vec<u8> {
+        let mut data = vec![0u8; 128];
+        data[4..6].copy_from_slice(&0xA5E0u16.to_le_bytes());
+        data[6..8].copy_from_slice(&(frames.len() as u16).to_le_bytes());
+        data[8..10].copy_from_slice(&width.to_le_bytes());
+        data[10..12].copy_from_slice(&height.to_le_bytes());
+        data[12..14].copy_from_slice(&32u16.to_le_bytes());
+        data[14..18].copy_from_slice(&1u32.to_le_bytes());
+        for (chunks, padding) in frames {
+            let body = chunks.concat();
+            data.extend(((16 + body.len() + padding) as u32).to_le_bytes());
+            data.extend(0xF1FAu16.to_le_bytes());
+            // Old chunk count only
+            data.extend((chunks.len() as u16).to_le_bytes());
+            data.extend(100u16.to_le_bytes());
+            data.extend([0; 6]);
+            data.extend(body);
+            data.extend(vec![0xEE; *padding]);
+        }
+        let size = data.len() as u32;
+        data[0..4].copy_from_slice(&size.to_le_bytes());
+        data
+    }
+
+    fn rgba_pixels(count: usize, color: [u8; 4]) -> Vec<u8> {
+        color.repeat(count)
+    }
+
+    #[test]
+    fn aseprite_chunk_offsets() {
+        // An unknown chunk and the padding at the end of the frame are skipped by their sizes
+        let frame = vec![
+            ase_chunk(0x2007, &[0xAA; 11]),
+            ase_layer(1, 0, 0, "a"),
+            ase_cel(0, 0, 2, 2, &rgba_pixels(4, [255, 0, 0, 255]), true),
+        ];
+        let next = vec![ase_cel(0, 0, 2, 2, &rgba_pixels(4, [0, 0, 255, 255]), false)];
+        let file = read_aseprite(&ase_file(2, 2, &[(frame, 5), (next, 0)])).unwrap();
+        assert_eq!(file.layers.len(), 1);
+        assert_eq!(file.frames.len(), 2);
+        assert_eq!(file.composite(None, 0).unwrap().get_pixel(1, 1).0, [255, 0, 0, 255]);
+        assert_eq!(file.composite(None, 1).unwrap().get_pixel(0, 0).0, [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn aseprite_linked_cels() {
+        let frame = vec![
+            ase_layer(1, 0, 0, "a"),
+            ase_layer(1, 0, 0, "b"),
+            ase_cel(0, 0, 2, 2, &rgba_pixels(4, [255, 0, 0, 255]), false),
+        ];
+        // Frame 1 links layer 0 to frame 0 and draws it over layer 1 with its own z-index
+        let next = vec![ase_link(0, 1, 0), ase_cel(1, 0, 2, 2, &rgba_pixels(4, [0, 255, 0, 255]), false)];
+        let file = read_aseprite(&ase_file(2, 2, &[(frame, 0), (next, 0)])).unwrap();
+        assert_eq!(file.frames[1][0].pixels, file.frames[0][0].pixels);
+        assert_eq!(file.frames[1][0].z_index, 1);
+        assert_eq!(file.composite(None, 1).unwrap().get_pixel(0, 0).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn aseprite_z_index_order() {
+        let red = rgba_pixels(1, [255, 0, 0, 255]);
+        let green = rgba_pixels(1, [0, 255, 0, 255]);
+        let layers = || vec![ase_layer(1, 0, 0, "a"), ase_layer(1, 0, 0, "b")];
+        // Layer order first
+        let chunks = [layers(), vec![ase_cel(0, 0, 1, 1, &red, false), ase_cel(1, 0, 1, 1, &green, false)]].concat();
+        let file = read_aseprite(&ase_file(1, 1, &[(chunks, 0)])).unwrap();
+        assert_eq!(file.composite(None, 0).unwrap().get_pixel(0, 0).0, [0, 255, 0, 255]);
+        // A z-index of 1 moves layer 0 to the order of layer 1, and in front of it
+        let chunks = [layers(), vec![ase_cel(0, 1, 1, 1, &red, false), ase_cel(1, 0, 1, 1, &green, false)]].concat();
+        let file = read_aseprite(&ase_file(1, 1, &[(chunks, 0)])).unwrap();
+        assert_eq!(file.composite(None, 0).unwrap().get_pixel(0, 0).0, [255, 0, 0, 255]);
+        // A z-index of -1 moves layer 1 behind layer 0
+        let chunks = [layers(), vec![ase_cel(0, 0, 1, 1, &red, false), ase_cel(1, -1, 1, 1, &green, false)]].concat();
+        let file = read_aseprite(&ase_file(1, 1, &[(chunks, 0)])).unwrap();
+        assert_eq!(file.composite(None, 0).unwrap().get_pixel(0, 0).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn aseprite_size_limits() {
+        // A compressed cel far larger than the canvas is refused before inflating it
+        let chunks = vec![ase_layer(1, 0, 0, "a"), ase_cel(0, 0, 0xFFFF, 0xFFFF, &[0; 4], true)];
+        let error = read_aseprite(&ase_file(2, 2, &[(chunks, 0)])).err().unwrap();
+        assert!(error.starts_with("Cel Aseprite trop grande"), "{}", error);
+        // So is a huge canvas, before any cel or canvas is allocated
+        let chunks = vec![ase_layer(1, 0, 0, "a"), ase_cel(0, 0, 16, 16, &[0; 16 * 16 * 4], true)];
+        let error = read_aseprite(&ase_file(0xFFFF, 0xFFFF, &[(chunks, 0)])).err().unwrap();
+        assert!(error.starts_with("Image Aseprite trop grande"), "{}", error);
+        // An empty canvas has no layer groups
+        let path = std::env::temp_dir().join("image2pce_empty_canvas.aseprite");
+        std::fs::write(&path, ase_file(0, 0, &[(vec![ase_layer(1, 0, 0, "pal1")], 0)])).unwrap();
+        let groups = read_aseprite_layer_groups(path.to_string_lossy().to_string(), 0, 2, 2);
+        let _ = std::fs::remove_file(&path);
+        assert!(groups.is_err());
+    }
+
+    #[test]
+    fn layer_palette_group_names() {
+        assert_eq!(layer_palette_group("pal3_sky"), Some(3));
+        assert_eq!(layer_palette_group("PAL12"), Some(12));
+        assert_eq!(layer_palette_group("pal16"), None);
+        assert_eq!(layer_palette_group("palette"), None);
+        assert_eq!(layer_palette_group("pal_3"), None);
+        assert_eq!(layer_palette_group("sky"), None);
+    }
+
+    #[test]
+    fn tile_passes_keep_transparent_pixels() {
+        let palettes = vec![vec!["#000000".to_string(), "#FFFFFF".to_string()]];
+        let palette_result = TilePaletteResult {
+            palettes: palettes.clone(),
+            tile_palette_map: vec![0, 0],
+            palette_colors: palettes,
+            empty_tiles: vec![false, false],
+        };
+        // Tile 0 is white, the left half of tile 1 is outside the SuperGrafx coverage
+        let source = RgbaImage::from_pixel(16, 8, Rgba([255, 255, 255, 255]));
+        let coverage: Vec<bool> = (0..16 * 8).map(|i| i % 16 < 8 || i % 16 >= 12).collect();
+        let mut preview = source.clone();
+        for y in 0..8 {
+            for x in 8..12 {
+                preview.put_pixel(x, y, Rgba([0, 0, 0, 255]));
+            }
+        }
+        // The white pattern of tile 0 is closer to the source but would paint the transparent pixels
+        let mut reused = preview.clone();
+        assert_eq!(reuse_tile_patterns(&source, &mut reused, &palette_result, 1000.0, None), 1);
+        let mut reused = preview.clone();
+        assert_eq!(reuse_tile_patterns(&source, &mut reused, &palette_result, 1000.0, Some(&coverage)), 0);
+        assert_eq!(reused, preview);
+        // Same for merging the two unique tiles
+        let reduce = |coverage: Option<&[bool]>| {
+            let mut reduced = preview.clone();
+            let (mut unique_tiles, mut tile_to_unique) =
+                build_unique_tiles(&reduced, &palette_result.palettes, &palette_result.tile_palette_map, &palette_result.empty_tiles, &[]);
+            let merged = reduce_unique_tiles(None, &source, &mut reduced, &palette_result, &mut unique_tiles, &mut tile_to_unique, "max_tiles", 0.0, 1, coverage);
+            (merged, reduced)
+        };
+        assert!(reduce(None).0 > 0);
+        let (merged, reduced) = reduce(Some(&coverage));
+        assert_eq!(merged, 0);
+        assert_eq!(reduced, preview);
+    }
+
+    #[test]
+    fn sprite_palette_ties() {
+        // Sixteen colors of equal weight: the fifteen kept do not depend on the set order
+        let colors: Vec<([u8; 3], f32)> = (0..16u8).map(|i| ([i * 16, 0, 0], 1.0)).collect();
+        let reversed: Vec<([u8; 3], f32)> = colors.iter().rev().copied().collect();
+        let (palettes, _) = pack_sprite_palettes(&[colors], 16);
+        let (palettes_reversed, _) = pack_sprite_palettes(&[reversed], 16);
+        assert_eq!(palettes, palettes_reversed);
+        assert_eq!(palettes[0].len(), 15);
+        assert!(!palettes[0].contains(&[240, 0, 0]));
+    }
+
+    #[test]
+    fn sprite_cell_encoding() {
+        // Index 5 (planes 0 and 2) at line 3, leftmost pixel; index 8 (plane 3) at line 15, rightmost pixel
+        let mut pixels = [0u8; 256];
+        pixels[3 * 16] = 5;
+        pixels[15 * 16 + 15] = 8;
+        let data = encode_sprite_cell(&pixels);
+        let mut expected = [0u16; 64];
+        expected[3] = 0x8000;
+        expected[2 * 16 + 3] = 0x8000;
+        expected[3 * 16 + 15] = 0x0001;
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn tall_sprite_patterns_are_padded() {
+        assert_eq!(sprite_pattern_cells(16, 16), 1);
+        assert_eq!(sprite_pattern_cells(32, 16), 2);
+        assert_eq!(sprite_pattern_cells(16, 32), 4);
+        assert_eq!(sprite_pattern_cells(16, 64), 8);
+        assert_eq!(sprite_pattern_cells(32, 64), 8);
+        for height in [32, 64] {
+            let sprite = SpriteCell { x: 0, y: 0, width: 16, height, palette: 0, pixels: vec![1; 16 * height as usize] };
+            let words = encode_sprite_pattern(&sprite);
+            assert_eq!(words.len(), (sprite_pattern_cells(16, height) * 64) as usize);
+            // Each row of cells is the 16x16 cell followed by an empty cell
+            for row in 0..(height / 16) as usize {
+                assert!(words[row * 128..row * 128 + 16].iter().all(|&w| w == 0xFFFF));
+                assert!(words[row * 128 + 64..row * 128 + 128].iter().all(|&w| w == 0));
+            }
+        }
+    }
+
+    #[test]
+    fn satb_entry_layout() {
+        let attributes = 0x0003 | sprite_size_attributes(32, 64);
+        assert_eq!(satb_entry(0, 0, 0x5000, attributes), [64, 32, 0x280, 0x3183]);
+        // Off-screen positions wrap to 10 bits, bit 0 of the pattern code is cleared
+        assert_eq!(satb_entry(-40, -70, 0x5020, 0), [0x3FA, 0x3F8, 0x280, 0]);
+        assert_eq!(sprite_size_attributes(16, 16), 0x0080);
+        assert_eq!(sprite_size_attributes(32, 16), 0x0180);
+        assert_eq!(sprite_size_attributes(16, 32), 0x1080);
+        assert_eq!(sprite_size_attributes(16, 64), 0x3080);
+    }
+
+    #[test]
+    fn animation_patterns_and_metasprites() {
+        // A 16x32 frame and its horizontal mirror share one pattern
+        let pixels: Vec<u8> = (0..16 * 32).map(|i| if i % 16 < 4 { 1 } else { 0 }).collect();
+        let sprite = SpriteCell { x: 0, y: 0, width: 16, height: 32, palette: 2, pixels };
+        let mirrored = SpriteCell { pixels: flip_sprite_pixels(&sprite.pixels, 16, 32, true, false), ..sprite.clone() };
+        let mut patterns = Vec::new();
+        let mut pattern_index = std::collections::HashMap::new();
+        assert_eq!(dedup_sprite_pattern(&mut patterns, &mut pattern_index, &sprite), (0, false, false));
+        assert_eq!(dedup_sprite_pattern(&mut patterns, &mut pattern_index, &mirrored), (0, true, false));
+        assert_eq!(patterns.len(), 1);
+
+        // Two unique 16x32 patterns take 4 cells (256 words) each, from an aligned base address
+        let second = SpriteCell { palette: 3, pixels: vec![1; 16 * 32], ..sprite.clone() };
+        let entry = |pattern, h_flip, v_flip| MetaspriteEntry { x: -8, y: 16, pattern, palette: 2 + pattern, h_flip, v_flip };
+        let frames = vec![vec![entry(0, true, false), entry(1, false, true)]];
+        let (address, words, metasprites) = sprite_animation_data(&[sprite, second], &frames, 0x4010).unwrap();
+        assert_eq!(address, 0x4100);
+        assert_eq!(words.len(), 2);
+        assert_eq!(words[0].len(), 256);
+        assert_eq!(metasprites[0][0], [16, (-8i16) as u16, 0x4100 >> 5, 0x0800 | 0x1080 | 2]);
+        assert_eq!(metasprites[0][1], [16, (-8i16) as u16, 0x4200 >> 5, 0x8000 | 0x1080 | 3]);
+    }
+
+    #[test]
+    fn map_stream_and_bat_layout() {
+        // 2x2 tiles: unique tiles 0, 1, 2, 1 at $1000, palette 17 is slot 1 of band 1
+        let tile_to_unique = [0, 1, 2, 1];
+        let tile_palette_map = [0, 17, 2, 3];
+        let empty_tiles = [false; 4];
+        let words = |tile: usize| ((tile_palette_map[tile] as u16 % 16) << 12) | (0x100 + tile_to_unique[tile] as u16);
+        let columns = map_stream_words(2, 2, true, &tile_to_unique, &tile_palette_map, &empty_tiles, 0x1000);
+        assert_eq!(columns, vec![2, 2, words(0), words(2), words(1), words(3)]);
+        let rows = map_stream_words(2, 2, false, &tile_to_unique, &tile_palette_map, &empty_tiles, 0x1000);
+        assert_eq!(rows, vec![2, 2, words(0), words(1), words(2), words(3)]);
+        assert_eq!(rows[3], 0x1101);
+
+        // Without bands the image is clipped at the BAT edge
+        let cells = layout_bat(3, 2, 4, 4, 2, 1, &[]).unwrap();
+        assert_eq!(cells[4 + 2], Some(0));
+        assert_eq!(cells[8 + 3], Some(4));
+        assert_eq!(cells.iter().flatten().count(), 4);
+        // A band moved onto the BAT rows of another one is rejected
+        let band = |start_line, end_line, bat_y| RasterBand { start_line, end_line, scroll_x: 0, scroll_y: 0, bat_x: None, bat_y };
+        assert!(layout_bat(2, 2, 4, 4, 0, 0, &[band(0, 8, None), band(8, 16, Some(2))]).is_ok());
+        assert!(layout_bat(2, 2, 4, 4, 0, 0, &[band(0, 8, None), band(8, 16, Some(0))]).is_err());
+        assert!(layout_bat(2, 2, 4, 4, 0, 0, &[band(0, 8, None), band(8, 16, Some(4))]).is_err());
+
+        // Unique tiles must end at or below $8000
+        assert!(check_tiles_vram(0x7000, 256).is_ok());
+        assert!(check_tiles_vram(0x7000, 257).is_err());
+    }
+
+    #[test]
+    fn band_palette_changes() {
+        // Two bands of one tile row with one palette: 15 reds on top, 15 greens below
+        let image = RgbaImage::from_fn(16, 16, |x, y| {
+            let level = 32 + 14 * x.min(14) as u8;
+            if y < 8 { Rgba([level, 0, 0, 255]) } else { Rgba([0, level, 0, 255]) }
+        });
+        let (result, band_changes) = build_band_palettes(&image, 1, "#000000", &[], 1, 1, 16).unwrap();
+        assert_eq!(result.palettes.len(), 32);
+        assert_eq!(band_changes[0], 0);
+        assert!(band_changes[1] > 0);
+        assert!(result.tile_palette_map[2..].iter().all(|&palette| palette / 16 == 1));
+
+        // Only the entries that differ from the first band are reloaded, at the band's first line
+        let table = palette_raster_changes(&result.palettes, 1, 24);
+        assert_eq!(table.len(), 1);
+        let (line, changes) = &table[0];
+        assert_eq!(*line, 24 + 8);
+        assert_eq!(changes.len(), band_changes[1]);
+        let word = |palette: usize, index: usize| color_to_pce_word(&result.palettes[palette][index]);
+        let differing = (0..16 * 16).filter(|&i| word(i / 16, i % 16) != word(16 + i / 16, i % 16)).count();
+        assert_eq!(changes.len(), differing);
+        for &(color_index, new_word) in changes {
+            let (slot, index) = (color_index as usize / 16, color_index as usize % 16);
+            assert_ne!(word(slot, index), new_word);
+            assert_eq!(word(16 + slot, index), new_word);
+        }
+
+        // The BAT word only holds the slot of the band's palette
+        let tile_to_unique = [0, 1, 2, 3];
+        let bat_word = bat_entry_word(Some(2), &tile_to_unique, &result.tile_palette_map, &result.empty_tiles, 0x1000);
+        assert_eq!(bat_word >> 12, (result.tile_palette_map[2] % 16) as u16);
+        assert_eq!(bat_word & 0x0FFF, 0x102);
+    }
+
+    #[test]
+    fn exact_palette_cover() {
+        // Two tiles of 8 colors each fit in two palettes but not in one
+        let image = RgbaImage::from_fn(16, 8, |x, y| {
+            let level = (y * 36) as u8;
+            if x < 8 { Rgba([level, 0, 36, 255]) } else { Rgba([0, level, 72, 255]) }
+        });
+        let result = exact_tile_palettes(&image, 2, "#000000", &[]).unwrap();
+        assert_ne!(result.tile_palette_map[0], result.tile_palette_map[1]);
+        for (tile, x) in [(0, 0), (1, 8)] {
+            let palette = &result.palettes[result.tile_palette_map[tile]];
+            for y in 0..8 {
+                let pixel = image.get_pixel(x, y);
+                assert!(palette.contains(&format!("#{:02X}{:02X}{:02X}", pixel[0], pixel[1], pixel[2])));
+            }
+        }
+        let error = exact_tile_palettes(&image, 1, "#000000", &[]).err().unwrap();
+        assert!(error.starts_with("Aucune répartition exacte"));
+
+        // 49 disjoint sets of 5 colors for 16 palettes holding 3 sets each: the search gives up
+        let sets: Vec<ColorSet> = (0..49)
+            .map(|index| {
+                let mut set = ColorSet::default();
+                (0..5).for_each(|color| set.insert(1 + index * 5 + color));
+                set
+            })
+            .collect();
+        let mut steps = 0;
+        assert!(cover_color_sets(&sets[..48], &mut [ColorSet::default(); 16], &mut steps));
+        assert!(steps < EXACT_COVER_MAX_STEPS);
+        // Counting from near the limit keeps the test short, the search would run far past it
+        let mut steps = EXACT_COVER_MAX_STEPS - 100_000;
+        assert!(!cover_color_sets(&sets, &mut [ColorSet::default(); 16], &mut steps));
+        assert_eq!(steps, EXACT_COVER_MAX_STEPS);
+    }
+}
@@ -1,6 +1,17 @@
 const { invoke, convertFileSrc } = window.__TAURI__.core;
 const { listen } = window.__TAURI__.event;
 
+// Video mode presets: output size and pixel aspect ratio (displayed width / height of a pixel)
+// for each dot clock on an NTSC screen
+const VIDEO_MODES = {
+  "256x224": { width: 256, height: 224, pixelAspect: 8 / 7 },
+  "256x240": { width: 256, height: 240, pixelAspect: 8 / 7 },
+  "336x224": { width: 336, height: 224, pixelAspect: 6 / 7 },
+  "336x240": { width: 336, height: 240, pixelAspect: 6 / 7 },
+  "512x224": { width: 512, height: 224, pixelAspect: 4 / 7 },
+  "512x240": { width: 512, height: 240, pixelAspect: 4 / 7 },
+};
+
 const state = {
   inputImage: null,
   inputFilename: null,      // Just the filename (without path)
@@ -8,6 +19,18 @@ const state = {
   inputHeight: 0,           // Original source image height
   outputPreview: null,
   outputImageBase64: null,
+  flicker: null, // Second frame of a flicker conversion (palettes, tiles, blended preview)
+  paletteBandRows: 0, // Band height (tile rows) of the last conversion with per-band palettes
+  rasterBands: "", // Raster split bands declared for the last conversion
+  sgxLayerPath: null, // Background (VDC2) layer image for the SuperGrafx mode
+  sgx: null, // VDC2 layer of a SuperGrafx conversion (tiles, composite preview)
+  spriteOverlay: null, // Color boost sprites of the last conversion (sprites, palettes, composite preview)
+  spriteConversion: null, // Result of the last conversion with the sprite target (sprites, palettes)
+  spriteAnimation: null, // Result of the last sprite animation conversion (patterns, metasprites, palettes)
+  aseprite: null, // Layers and frame count of an Aseprite source
+  pixelIndices: [], // Color index of every pixel of an indexed palettes conversion (empty = export matches colors)
+  outputPixelAspect: 1, // Pixel aspect ratio of the last conversion, for the screen aspect preview
+  animationFolderPath: null, // Folder of frame images for the sprite animation target
   originalImageData: null, // Store original ImageData for blur processing
   palettes: [],
   tilePaletteMap: [],
@@ -38,6 +61,7 @@ const state = {
     isDrawing: false,
     tool: "brush", // "brush", "eraser", "circle", "rectangle", "polygon"
     brushSize: 20,
+    brushStrength: 100,     // Dithering strength painted by brush and shapes (0-100%)
     // Shape drawing state
     shapeStart: null, // { x, y } start point for shapes
     previewCanvas: null, // temporary canvas for shape preview
@@ -123,12 +147,113 @@ function hexToRGB333(hex) {
   };
 }
 
+// Source formats the webview cannot display: the backend decodes them to PNG
+const DECODED_SOURCE_EXTENSIONS = ["pcx", "tga", "tif", "tiff", "qoi", "ase", "aseprite"];
+
+async function getSourceImageUrl(path) {
+  const extension = path.split(".").pop().toLowerCase();
+  if (DECODED_SOURCE_EXTENSIONS.includes(extension)) {
+    const base64 = await invoke("load_source_preview", { inputPath: path, sourceTransform: getSourceTransform() });
+    return `data:image/png;base64,${base64}`;
+  }
+  return convertFileSrc(path);
+}
+
+function isAsepritePath(path) {
+  return /\.(ase|aseprite)$/i.test(path);
+}
+
+/**
+ * Show the layer and frame selection for an Aseprite source (hidden for other formats).
+ * keepSelection keeps the current (or restored) layer and frame when they still exist.
+ */
+async function loadAsepriteInfo(path, keepSelection) {
+  const row = document.querySelector("#aseprite-row");
+  const layerSelect = document.querySelector("#ase-layer");
+  const frameInput = document.querySelector("#ase-frame");
+  const wanted = keepSelection ? (layerSelect.dataset.restore ?? layerSelect.value) : "";
+  delete layerSelect.dataset.restore;
+  state.aseprite = null;
+  layerSelect.innerHTML = '<option value="">Calques visibles</option>';
+  if (!isAsepritePath(path)) {
+    layerSelect.value = "";
+    row.hidden = true;
+    return;
+  }
+
+  const info = await invoke("read_aseprite_info", { inputPath: path });
+  state.aseprite = info;
+  info.layers.forEach((layer, index) => {
+    const option = document.createElement("option");
+    option.value = String(index);
+    const indent = "\u00A0\u00A0".repeat(layer.level);
+    option.textContent = `${indent}${layer.name}${layer.group ? " /" : ""}${layer.visible ? "" : " (masqué)"}`;
+    layerSelect.appendChild(option);
+  });
+  layerSelect.value = info.layers[parseInt(wanted, 10)] ? wanted : "";
+  frameInput.max = info.frame_count;
+  const frame = parseInt(frameInput.value, 10) || 1;
+  frameInput.value = keepSelection ? Math.min(Math.max(frame, 1), info.frame_count) : 1;
+  row.hidden = false;
+}
+
+/**
+ * Redraw the Aseprite source after a layer or frame change (same canvas size,
+ * so the mask and palette groups stay as they are)
+ */
+async function refreshAsepriteSource() {
+  const sourceImg = document.querySelector("#source-image");
+  if (!sourceImg || !state.inputImage || !state.aseprite) return;
+  try {
+    const fileUrl = await getSourceImageUrl(state.inputImage);
+    sourceImg.onload = null;
+    sourceImg.src = fileUrl;
+    markProjectDirty();
+  } catch (error) {
+    alert("Impossible d'afficher le calque: " + error);
+  }
+}
+
+/**
+ * Fill the palette groups from the visible layers named pal<N>_ of the Aseprite source
+ */
+async function importAsepriteLayerGroups() {
+  if (!state.aseprite || !state.inputImage) return;
+  const { gridWidth, gridHeight } = state.paletteGroups;
+  try {
+    const groups = await invoke("read_aseprite_layer_groups", {
+      inputPath: state.inputImage,
+      frame: getSourceTransform().frame,
+      gridWidth,
+      gridHeight,
+    });
+    groups.forEach((group, index) => {
+      if (group >= 0) {
+        state.paletteGroups.assignments[Math.floor(index / gridWidth)][index % gridWidth] = group;
+      }
+    });
+    renderPaletteGroupsOverlay();
+    savePaletteGroupsState();
+  } catch (error) {
+    alert("Impossible de lire les groupes des calques: " + error);
+  }
+}
+
 async function openImage() {
   const selected = await invoke("open_image");
   if (!selected) {
     return;
   }
 
+  let fileUrl;
+  try {
+    await loadAsepriteInfo(selected, false);
+    fileUrl = await getSourceImageUrl(selected);
+  } catch (error) {
+    alert("Impossible d'ouvrir l'image: " + error);
+    return;
+  }
+
   // Mark project as dirty when a new image is loaded
   markProjectDirty();
 
@@ -138,7 +263,6 @@ async function openImage() {
   state.inputImage = selected;
   const inputMeta = document.querySelector("#input-meta");
   const inputCanvas = document.querySelector("#input-canvas");
-  const fileUrl = convertFileSrc(selected);
   inputCanvas.innerHTML = `
     <div class="viewer__stage">
       <div class="viewer__image-wrapper">
@@ -161,6 +285,11 @@ async function openImage() {
     state.inputHeight = h;
     inputMeta.textContent = `${filename} (${w}×${h})`;
 
+    // A large map follows the size of each new source image
+    if (document.querySelector("#large-map")?.checked) {
+      setLargeMapSize(Math.ceil(w / 8), Math.ceil(h / 8));
+    }
+
     initMaskCanvas(w, h);
     initPaletteGroupsCanvas();
   };
@@ -184,20 +313,23 @@ function updateSizeConstraints() {
   const batSize = batSizeEl.value;
   const [batW, batH] = batSize.split("x").map(Number);
 
-  // Update slider max values based on BAT size
-  widthSlider.max = batW;
-  heightSlider.max = batH;
+  // Update slider max values based on BAT size (a large map goes up to the source size)
+  const largeMap = document.querySelector("#large-map")?.checked || false;
+  const sourceTilesX = Math.ceil((state.inputWidth || 0) / 8);
+  const sourceTilesY = Math.ceil((state.inputHeight || 0) / 8);
+  widthSlider.max = largeMap ? Math.max(batW, sourceTilesX) : batW;
+  heightSlider.max = largeMap ? Math.max(batH, sourceTilesY) : batH;
 
   // Clamp current values if they exceed new max
   let imgW = parseInt(widthSlider.value, 10);
   let imgH = parseInt(heightSlider.value, 10);
 
-  if (imgW > batW) {
-    imgW = batW;
+  if (imgW > widthSlider.max) {
+    imgW = parseInt(widthSlider.max, 10);
     widthSlider.value = imgW;
   }
-  if (imgH > batH) {
-    imgH = batH;
+  if (imgH > heightSlider.max) {
+    imgH = parseInt(heightSlider.max, 10);
     heightSlider.value = imgH;
   }
 
@@ -205,9 +337,15 @@ function updateSizeConstraints() {
   document.querySelector("#output-width-value").textContent = `${imgW} (${imgW * 8} px)`;
   document.querySelector("#output-height-value").textContent = `${imgH} (${imgH * 8} px)`;
 
-  // Update offset max values (image + offset must fit in BAT)
-  const maxOffsetX = batW - imgW;
-  const maxOffsetY = batH - imgH;
+  // Update offset max values (image + offset must fit in BAT, a large map starts at 0,0)
+  const maxOffsetX = Math.max(0, batW - imgW);
+  const maxOffsetY = Math.max(0, batH - imgH);
+  offsetXEl.disabled = largeMap;
+  offsetYEl.disabled = largeMap;
+  if (largeMap) {
+    offsetXEl.value = 0;
+    offsetYEl.value = 0;
+  }
 
   offsetXEl.max = maxOffsetX;
   offsetYEl.max = maxOffsetY;
@@ -226,6 +364,18 @@ function updateSizeConstraints() {
   }
 }
 
+/**
+ * Set the output size of a large map, once the slider max allows sizes beyond the BAT
+ */
+function setLargeMapSize(widthTiles, heightTiles) {
+  updateSizeConstraints();
+  if (widthTiles > 0 && heightTiles > 0) {
+    document.querySelector("#output-width-tiles").value = widthTiles;
+    document.querySelector("#output-height-tiles").value = heightTiles;
+    updateSizeConstraints();
+  }
+}
+
 function initMaskCanvas(width, height) {
   const maskCanvas = document.querySelector("#mask-canvas");
   if (!maskCanvas) return;
@@ -465,7 +615,7 @@ function finalizeShape(event) {
   // Determine fill color based on shapeFillMode (Shift toggles temporarily)
   const baseIsWhite = state.mask.shapeFillMode === "white";
   const useWhite = event.shiftKey ? !baseIsWhite : baseIsWhite;
-  const fillColor = useWhite ? "#FFFFFF" : "#000000";
+  const fillColor = useWhite ? "#FFFFFF" : maskDitherColor();
 
   ctx.fillStyle = fillColor;
 
@@ -536,7 +686,7 @@ function finalizePolygon(event) {
   // Determine fill color
   const baseIsWhite = state.mask.shapeFillMode === "white";
   const useWhite = event?.shiftKey ? !baseIsWhite : baseIsWhite;
-  const fillColor = useWhite ? "#FFFFFF" : "#000000";
+  const fillColor = useWhite ? "#FFFFFF" : maskDitherColor();
 
   ctx.fillStyle = fillColor;
   ctx.beginPath();
@@ -699,7 +849,7 @@ function drawOnMask(event) {
     const brushRadius = state.mask.brushSize / 2;
     ctx.beginPath();
     ctx.arc(x, y, brushRadius, 0, Math.PI * 2);
-    ctx.fillStyle = tool === "brush" ? "#000000" : "#FFFFFF";
+    ctx.fillStyle = tool === "brush" ? maskDitherColor() : "#FFFFFF";
     ctx.fill();
   } else if ((tool === "circle" || tool === "rectangle") && state.mask.shapeStart && state.mask.previewCanvas) {
     // Shape preview drawing
@@ -842,16 +992,23 @@ function invertMask() {
   ctx.putImageData(imageData, 0, 0);
 }
 
+// Gray level painted for "with dithering": black at 100%, lighter for partial strength
+function maskDitherColor() {
+  const level = Math.round(255 * (1 - state.mask.brushStrength / 100));
+  const hex = level.toString(16).padStart(2, "0");
+  return `#${hex}${hex}${hex}`;
+}
+
 function getMaskData() {
   if (!state.mask.ctx || !state.mask.width || !state.mask.height) {
     return null;
   }
   const imageData = state.mask.ctx.getImageData(0, 0, state.mask.width, state.mask.height);
-  // Convert to binary array (0 or 255 for each pixel)
+  // Grayscale array: black = 0 (full dithering) to white = 255 (no dithering)
   const maskData = new Uint8Array(state.mask.width * state.mask.height);
   for (let i = 0; i < maskData.length; i++) {
-    // Use red channel to determine mask value (black = 0, white = 255)
-    maskData[i] = imageData.data[i * 4] < 128 ? 0 : 255;
+    // Use red channel as the mask value
+    maskData[i] = imageData.data[i * 4];
   }
   return Array.from(maskData);
 }
@@ -891,8 +1048,146 @@ function updateProgress(percent, text) {
   }
 }
 
+/**
+ * Gather the conversion parameters from the settings panel
+ * (shared by run_conversion and run_palette_sweep)
+ */
+function getConversionParams() {
+  const resizeMethod = document.querySelector("#resize-method").value;
+  const paletteCount = parseInt(
+    document.querySelector("#palette-count").value,
+    10,
+  );
+  const ditherMode = document.querySelector("#dither-mode").value;
+  const color0Mode = document.querySelector("#color0-mode").value;
+  const keepRatio = document.querySelector("#keep-ratio").checked;
+  const tileReductionMode = document.querySelector("#tile-reduction-mode")?.value || "none";
+  const tileReductionThreshold = parseFloat(document.querySelector("#tile-reduction-threshold")?.value) || 0;
+  const maxUniqueTiles = parseInt(document.querySelector("#max-unique-tiles")?.value, 10) || 1;
+  const serpentine = document.querySelector("#dither-serpentine")?.checked || false;
+  const linearLight = document.querySelector("#linear-light")?.checked || false;
+  const ditherStrengthValue = parseInt(document.querySelector("#dither-strength")?.value, 10);
+  const ditherStrength = (isNaN(ditherStrengthValue) ? 100 : ditherStrengthValue) / 100;
+  const crossTileDither = document.querySelector("#dither-cross-tile")?.checked || false;
+  const ditherErrorClampValue = parseFloat(document.querySelector("#dither-error-clamp")?.value);
+  const ditherErrorClamp = isNaN(ditherErrorClampValue) ? 32 : Math.max(0, ditherErrorClampValue);
+  const orderedMatrix = document.querySelector("#ordered-matrix")?.value || "bayer8";
+  const orderedSpreadValue = parseFloat(document.querySelector("#ordered-spread")?.value);
+  const orderedSpread = isNaN(orderedSpreadValue) ? 32 : Math.max(0, orderedSpreadValue);
+  const orderedAlign = document.querySelector("#ordered-align")?.value || "tile";
+  const patternReuseTolerance = Math.max(0, parseFloat(document.querySelector("#pattern-reuse-tolerance")?.value) || 0);
+  const flicker = document.querySelector("#flicker-mode")?.checked || false;
+  const paletteBandRows = Math.max(0, parseInt(document.querySelector("#palette-band-rows")?.value, 10) || 0);
+  const maxBandPaletteChanges = Math.max(0, parseInt(document.querySelector("#max-band-palette-changes")?.value, 10) || 0);
+  const rasterBands = document.querySelector("#raster-bands")?.value || "";
+  const sgxMode = document.querySelector("#sgx-mode")?.value || "none";
+  const sgxSplitColors = document.querySelector("#sgx-split-colors")?.value || "";
+  const spriteOverlay = document.querySelector("#sprite-overlay")?.checked || false;
+  const maxOverlaySprites = Math.min(64, Math.max(1, parseInt(document.querySelector("#max-overlay-sprites")?.value, 10) || 64));
+  const conversionTarget = document.querySelector("#conversion-target")?.value || "bg";
+  const [spriteCellWidth, spriteCellHeight] = (document.querySelector("#sprite-cell-size")?.value || "16x16").split("x").map(Number);
+  const pixelAspect = getPixelAspect();
+  const sourceTransform = getSourceTransform();
+  const gridAlign = document.querySelector("#grid-align")?.value || "none";
+  const gridAlignStep = parseInt(document.querySelector("#grid-align-step")?.value, 10) || 1;
+  const lossless = document.querySelector("#lossless")?.checked || false;
+  const indexedPalettes = document.querySelector("#indexed-palettes")?.checked || false;
+  const animationSource = document.querySelector("#animation-source")?.value || "sheet";
+  const animationFrameWidth = Math.max(1, parseInt(document.querySelector("#animation-frame-width")?.value, 10) || 32);
+  const animationFrameHeight = Math.max(1, parseInt(document.querySelector("#animation-frame-height")?.value, 10) || 32);
+  const animationFrameCount = Math.max(0, parseInt(document.querySelector("#animation-frame-count")?.value, 10) || 0);
+
+  // Get output dimensions from tile sliders
+  const widthTiles = parseInt(document.querySelector("#output-width-tiles").value, 10);
+  const heightTiles = parseInt(document.querySelector("#output-height-tiles").value, 10);
+  const targetWidth = widthTiles * 8;
+  const targetHeight = heightTiles * 8;
+
+  // Use fixed color0 if mode is "fixed", otherwise use background-color input
+  const backgroundColor = color0Mode === "fixed"
+    ? state.fixedColor0
+    : document.querySelector("#background-color").value;
+
+  // Get the curve lookup table for RGB333 quantization
+  const curveLut = getCurveLUT();
+
+  // Get dither mask data if enabled
+  const useDitherMask = document.querySelector("#dither-mask")?.checked || false;
+  const maskData = useDitherMask ? getMaskData() : null;
+
+  // Get palette group constraints
+  const paletteGroupConstraints = getPaletteGroupConstraints();
+
+  return {
+    inputPath: state.inputImage,
+    sourceTransform,
+    // Nested settings, as read by ConversionSettings on the Rust side
+    settings: {
+      target: {
+        width: targetWidth,
+        height: targetHeight,
+        pixel_aspect: pixelAspect,
+        resize_method: resizeMethod,
+        keep_ratio: keepRatio,
+        background_color: backgroundColor,
+        curve_lut: curveLut,
+        grid_align: gridAlign,
+        grid_align_step: gridAlignStep,
+        linear_light: linearLight,
+      },
+      palettes: {
+        count: paletteCount,
+        group_constraints: paletteGroupConstraints,
+        seed: state.seed,
+        band_rows: paletteBandRows,
+        max_band_changes: maxBandPaletteChanges,
+      },
+      dither: {
+        mode: ditherMode,
+        serpentine,
+        strength: ditherStrength,
+        cross_tile: crossTileDither,
+        error_clamp: ditherErrorClamp,
+        ordered_matrix: orderedMatrix,
+        ordered_spread: orderedSpread,
+        ordered_align: orderedAlign,
+      },
+      dither_mask: useDitherMask && maskData !== null
+        ? { values: maskData, width: state.mask.width || 0, height: state.mask.height || 0 }
+        : null,
+      reduction: {
+        mode: tileReductionMode,
+        threshold: tileReductionThreshold,
+        max_unique_tiles: maxUniqueTiles,
+        pattern_reuse_tolerance: patternReuseTolerance,
+      },
+      modes: {
+        flicker,
+        raster_bands: rasterBands,
+        sgx_mode: sgxMode,
+        sgx_layer_path: state.sgxLayerPath || "",
+        sgx_split_colors: sgxSplitColors,
+        sprite_overlay: spriteOverlay,
+        max_overlay_sprites: maxOverlaySprites,
+        lossless,
+        indexed_palettes: indexedPalettes,
+      },
+    },
+    conversionTarget,
+    spriteCellWidth,
+    spriteCellHeight,
+    animationSource,
+    animationFrameWidth,
+    animationFrameHeight,
+    animationFrameCount,
+  };
+}
+
 async function runConversion() {
-  if (!state.inputImage) {
+  // A folder of frames replaces the source image for the sprite animation target
+  const fromFolder = document.querySelector("#conversion-target")?.value === "animation"
+    && document.querySelector("#animation-source")?.value === "folder";
+  if (fromFolder ? !state.animationFolderPath : !state.inputImage) {
     console.warn("Aucune image source sélectionnée.");
     return;
   }
@@ -914,25 +1209,8 @@ async function runConversion() {
   // Wait for browser to paint the progress bar (double rAF ensures paint is complete)
   await new Promise(resolve => requestAnimationFrame(() => requestAnimationFrame(resolve)));
 
-  const resizeMethod = document.querySelector("#resize-method").value;
-  const paletteCount = parseInt(
-    document.querySelector("#palette-count").value,
-    10,
-  );
-  const ditherMode = document.querySelector("#dither-mode").value;
-  const color0Mode = document.querySelector("#color0-mode").value;
-  const keepRatio = document.querySelector("#keep-ratio").checked;
-
-  // Get output dimensions from tile sliders
-  const widthTiles = parseInt(document.querySelector("#output-width-tiles").value, 10);
-  const heightTiles = parseInt(document.querySelector("#output-height-tiles").value, 10);
-  const targetWidth = widthTiles * 8;
-  const targetHeight = heightTiles * 8;
-
-  // Use fixed color0 if mode is "fixed", otherwise use background-color input
-  const backgroundColor = color0Mode === "fixed"
-    ? state.fixedColor0
-    : document.querySelector("#background-color").value;
+  const params = getConversionParams();
+  const { width: targetWidth, height: targetHeight } = params.settings.target;
 
   // Listen for progress events from Rust backend
   const unlisten = await listen("conversion-progress", (event) => {
@@ -940,34 +1218,19 @@ async function runConversion() {
     updateProgress(percent, stage);
   });
 
-  try {
-    // Get the curve lookup table for RGB333 quantization
-    const curveLut = getCurveLUT();
+  state.outputPixelAspect = params.settings.target.pixel_aspect;
 
-    // Get dither mask data if enabled
-    const useDitherMask = document.querySelector("#dither-mask")?.checked || false;
-    const maskData = useDitherMask ? getMaskData() : null;
-
-    // Get palette group constraints
-    const paletteGroupConstraints = getPaletteGroupConstraints();
+  try {
+    if (params.conversionTarget === "sprites") {
+      await runSpriteConversion(params);
+      return;
+    }
+    if (params.conversionTarget === "animation") {
+      await runSpriteAnimation(params);
+      return;
+    }
 
-    const conversionResult = await invoke("run_conversion", {
-      inputPath: state.inputImage,
-      resizeMethod,
-      paletteCount,
-      ditherMode,
-      backgroundColor,
-      keepRatio,
-      curveLut,
-      targetWidth,
-      targetHeight,
-      useDitherMask: useDitherMask && maskData !== null,
-      ditherMask: maskData || [],
-      maskWidth: state.mask.width || 0,
-      maskHeight: state.mask.height || 0,
-      paletteGroupConstraints,
-      seed: state.seed,
-    });
+    const conversionResult = await invoke("run_conversion", params);
 
     const {
       preview_base64: previewBase64,
@@ -978,6 +1241,18 @@ async function runConversion() {
       unique_tile_count: uniqueTileCount,
       tile_to_unique: tileToUnique,
       was_pre_resized: wasPreResized,
+      merged_tile_count: mergedTileCount,
+      reused_tile_count: reusedTileCount,
+      flicker,
+      palette_band_rows: paletteBandRows,
+      band_palette_changes: bandPaletteChanges,
+      raster_bands: rasterBandUsage,
+      sgx,
+      sprite_overlay: spriteOverlayResult,
+      grid_offset: gridOffset,
+      pixel_grid: pixelGrid,
+      warnings,
+      pixel_indices: pixelIndices,
     } = conversionResult;
 
     // Update input meta to show pre-resize info if applicable
@@ -995,11 +1270,23 @@ async function runConversion() {
     state.emptyTiles = emptyTiles;
     state.tileToUnique = tileToUnique;
     state.outputImageBase64 = previewBase64;
+    state.pixelIndices = pixelIndices || [];
+    state.flicker = flicker || null;
+    state.paletteBandRows = paletteBandRows || 0;
+    state.rasterBands = rasterBandUsage.length > 0 ? params.settings.modes.raster_bands : "";
+    state.sgx = sgx || null;
+    state.spriteOverlay = spriteOverlayResult || null;
+    state.spriteConversion = null;
+    state.spriteAnimation = null;
 
     // Calculate tile stats
     const duplicates = tileCount - uniqueTileCount;
-    const tilesBytes = uniqueTileCount * 32 + 32; // +32 for empty tile
-    const batBytes = tileCount * 2;
+    // +32 for empty tile; flicker frame B keeps its own tiles in VRAM right after
+    const tilesBytes = uniqueTileCount * 32 + 32
+      + (state.flicker ? state.flicker.unique_tile_count * 32 + 32 : 0);
+    // A large map only keeps one BAT screen in VRAM
+    const [batW, batH] = (document.querySelector("#bat-size")?.value || "32x32").split("x").map(Number);
+    const batBytes = getMapOrder() ? batW * batH * 2 : tileCount * 2;
     state.tileStats = {
       total: tileCount,
       unique: uniqueTileCount,
@@ -1024,22 +1311,31 @@ async function runConversion() {
     state.outputWidth = targetWidth;
     state.outputHeight = targetHeight;
 
-    // Load image into canvas and store original data
-    const img = new Image();
-    img.onload = () => {
-      const canvas = document.querySelector("#output-image-canvas");
-      const ctx = canvas.getContext("2d");
-      ctx.imageSmoothingEnabled = false;
-      ctx.drawImage(img, 0, 0);
-      state.originalImageData = ctx.getImageData(0, 0, targetWidth, targetHeight);
-
-      // Apply current blur setting
-      applyCrtBlur();
-
-      // Update histogram
-      drawHistogram();
-    };
-    img.src = `data:image/png;base64,${previewBase64}`;
+    // Load image into canvas (blended frames by default in flicker mode)
+    const flickerView = document.querySelector("#flicker-view");
+    if (flickerView) {
+      flickerView.hidden = !state.flicker;
+      flickerView.value = "blend";
+    }
+    // SuperGrafx mode: both layers composited by default
+    const sgxView = document.querySelector("#sgx-view");
+    if (sgxView) {
+      sgxView.hidden = !state.sgx;
+      sgxView.value = "composite";
+    }
+    // Color boost sprites: background and sprites composited by default
+    const overlayView = document.querySelector("#overlay-view");
+    if (overlayView) {
+      overlayView.hidden = !state.spriteOverlay;
+      overlayView.value = "composite";
+    }
+    if (state.sgx) {
+      drawOutputPreview(state.sgx.composite_preview_base64);
+    } else if (state.spriteOverlay) {
+      drawOutputPreview(state.spriteOverlay.composite_preview_base64);
+    } else {
+      drawOutputPreview(state.flicker ? state.flicker.blended_preview_base64 : previewBase64);
+    }
 
     // Calculate VRAM usage
     const totalVram = tilesBytes + batBytes;
@@ -1047,7 +1343,33 @@ async function runConversion() {
     const vramExceeded = totalVram > 65536;
 
     const outputMeta = document.querySelector("#output-meta");
-    outputMeta.innerHTML = `${tileCount} tuiles (${uniqueTileCount} uniques, ${duplicates} doublons)<br>` +
+    const mergedInfo = mergedTileCount > 0 ? `, ${mergedTileCount} fusionnées` : "";
+    const reusedInfo = reusedTileCount > 0 ? `, ${reusedTileCount} motifs réutilisés` : "";
+    const flickerInfo = state.flicker
+      ? `<br>Flicker : image B ${state.flicker.unique_tile_count} uniques, ${state.flicker.shared_unique_tile_count} uniques en partageant les tuiles`
+      : "";
+    const bandInfo = state.paletteBandRows > 0
+      ? `<br>${bandPaletteChanges.length} bandes de ${state.paletteBandRows * 8} lignes, couleurs rechargées : ${bandPaletteChanges.slice(1).join(", ")}`
+      : "";
+    const sgxInfo = state.sgx
+      ? `<br>SuperGrafx : VDC2 ${state.sgx.unique_tile_count} tuiles uniques`
+      : "";
+    const overlayInfo = state.spriteOverlay
+      ? `<br>Sprites de couleur : ${state.spriteOverlay.sprites.length} sprites, ${state.spriteOverlay.palettes.length} palettes`
+      : "";
+    const mapInfo = getMapOrder()
+      ? `<br>Grande carte : ${targetWidth / 8}×${targetHeight / 8} tuiles, carte ${(tileCount * 2 / 1024).toFixed(1)} Ko hors VRAM`
+      : "";
+    const gridInfo = gridOffset
+      ? `<br>Alignement grille : décalage de ${gridOffset[0]}, ${gridOffset[1]} px`
+      : "";
+    const pixelGridInfo = pixelGrid
+      ? `<br>Pixel art : ×${formatPixelScale(pixelGrid.scale_x)} × ${formatPixelScale(pixelGrid.scale_y)}, ${pixelGrid.native_width}×${pixelGrid.native_height} pixels d'origine`
+      : "";
+    const rasterInfo = rasterBandUsage
+      .map((band) => `<br>Lignes ${band.start_line}-${band.end_line - 1} : palettes ${band.palettes.join(", ") || "—"}, ${band.tile_count} tuiles (${band.unique_tile_count} uniques)`)
+      .join("");
+    outputMeta.innerHTML = `${tileCount} tuiles (${uniqueTileCount} uniques, ${duplicates} doublons${mergedInfo}${reusedInfo})${flickerInfo}${sgxInfo}${overlayInfo}${mapInfo}${gridInfo}${pixelGridInfo}${bandInfo}${rasterInfo}${formatWarnings(warnings)}<br>` +
       `<span class="${vramExceeded ? 'vram-exceeded' : ''}">VRAM: ${vramKb} Ko (BAT: ${batBytes} + Tuiles: ${tilesBytes})${vramExceeded ? ' — capacité VRAM dépassée' : ''}</span>`;
 
     // Update VRAM gauge
@@ -1067,6 +1389,331 @@ async function runConversion() {
   }
 }
 
+function formatPixelScale(scale) {
+  return Number.isInteger(scale) ? `${scale}` : scale.toFixed(2);
+}
+
+/**
+ * Conversion warnings, one per line (each starts with a line break)
+ */
+function formatWarnings(warnings) {
+  return (warnings || [])
+    .map((warning) => `<br><span class="conversion-warning">⚠ ${warning}</span>`)
+    .join("");
+}
+
+/**
+ * VRAM bytes of one sprite pattern: a sprite taller than 16 takes 2 cells per row of cells,
+ * even when 16 wide (the VDC skips the second cell)
+ */
+function spritePatternBytes(width, height) {
+  const cells = height > 16 ? 2 * (height / 16) : width / 16;
+  return cells * 128;
+}
+
+/**
+ * Sprite target: cut the image into sprite cells and show the result
+ */
+async function runSpriteConversion(params) {
+  const { spriteCellWidth, spriteCellHeight } = params;
+  const { width: targetWidth, height: targetHeight } = params.settings.target;
+  const result = await invoke("run_sprite_conversion", {
+    inputPath: params.inputPath,
+    sourceTransform: params.sourceTransform,
+    settings: params.settings,
+    cellWidth: spriteCellWidth,
+    cellHeight: spriteCellHeight,
+  });
+
+  clearBackgroundResult();
+  state.spriteConversion = result;
+  showSpritePreview(result.preview_base64, targetWidth, targetHeight);
+
+  const spriteCount = result.sprites.length;
+  const patternBytes = spriteCount * spritePatternBytes(spriteCellWidth, spriteCellHeight);
+  const satbExceeded = spriteCount > 64;
+  document.querySelector("#output-meta").innerHTML =
+    `${spriteCount} sprites ${spriteCellWidth}×${spriteCellHeight} (${result.cell_count - spriteCount} cellules vides), ${result.palettes.length} palettes de sprites${formatWarnings(result.warnings)}<br>` +
+    `<span class="${satbExceeded ? 'vram-exceeded' : ''}">Motifs : ${(patternBytes / 1024).toFixed(1)} Ko${satbExceeded ? ' — plus de 64 sprites dans la SATB' : ''}</span>`;
+
+  applyZoom("output");
+  renderPalettes(result.palettes);
+}
+
+/**
+ * Sprite animation target: convert every frame with shared sprite palettes and show the frames
+ */
+async function runSpriteAnimation(params) {
+  const { spriteCellWidth, spriteCellHeight, animationSource } = params;
+  const result = await invoke("run_sprite_animation", {
+    source: {
+      path: animationSource === "folder" ? state.animationFolderPath : params.inputPath,
+      kind: animationSource,
+      frame_width: params.animationFrameWidth,
+      frame_height: params.animationFrameHeight,
+      frame_count: params.animationFrameCount,
+    },
+    settings: params.settings,
+    cellWidth: spriteCellWidth,
+    cellHeight: spriteCellHeight,
+  });
+
+  clearBackgroundResult();
+  state.spriteAnimation = result;
+  const frameCount = result.frames.length;
+  const columns = Math.min(frameCount, 8);
+  const rows = Math.ceil(frameCount / columns);
+  showSpritePreview(result.preview_base64, columns * result.frame_width, rows * result.frame_height);
+
+  const patternCount = result.patterns.length;
+  const patternBytes = patternCount * spritePatternBytes(spriteCellWidth, spriteCellHeight);
+  const maxFrameSprites = Math.max(0, ...result.frames.map((entries) => entries.length));
+  const frameExceeded = maxFrameSprites > 64;
+  document.querySelector("#output-meta").innerHTML =
+    `${frameCount} images ${result.frame_width}×${result.frame_height}, ${result.sprite_count} sprites ${spriteCellWidth}×${spriteCellHeight} → ${patternCount} motifs uniques (${result.flipped_count} retournés), ${result.palettes.length} palettes de sprites<br>` +
+    `<span class="${frameExceeded ? 'vram-exceeded' : ''}">Motifs : ${(patternBytes / 1024).toFixed(1)} Ko, ${maxFrameSprites} sprites au plus par image${frameExceeded ? ' — plus de 64 sprites' : ''}</span>`;
+
+  applyZoom("output");
+  renderPalettes(result.palettes);
+}
+
+/**
+ * Forget the background data of a previous conversion so it is not exported along with sprites
+ */
+function clearBackgroundResult() {
+  state.spriteConversion = null;
+  state.spriteAnimation = null;
+  state.outputImageBase64 = null;
+  state.palettes = [];
+  state.tilePaletteMap = [];
+  state.emptyTiles = [];
+  state.tileToUnique = [];
+  state.pixelIndices = [];
+  state.flicker = null;
+  state.sgx = null;
+  state.spriteOverlay = null;
+  state.paletteBandRows = 0;
+  state.rasterBands = "";
+  ["#flicker-view", "#sgx-view", "#overlay-view"].forEach((selector) => {
+    const el = document.querySelector(selector);
+    if (el) el.hidden = true;
+  });
+}
+
+/**
+ * Show the preview of a sprite conversion in a new output canvas
+ */
+function showSpritePreview(base64, width, height) {
+  const outputCanvas = document.querySelector("#output-canvas");
+  outputCanvas.innerHTML = `
+    <div class="viewer__stage">
+      <div class="viewer__image-wrapper">
+        <canvas id="output-image-canvas" class="viewer__image" width="${width}" height="${height}"></canvas>
+      </div>
+    </div>
+  `;
+  state.outputWidth = width;
+  state.outputHeight = height;
+  drawOutputPreview(base64);
+}
+
+/**
+ * Draw a converted frame into the output canvas and keep its pixels for the CRT effects
+ */
+function drawOutputPreview(base64) {
+  const img = new Image();
+  img.onload = () => {
+    const canvas = document.querySelector("#output-image-canvas");
+    if (!canvas) return;
+    const ctx = canvas.getContext("2d");
+    ctx.imageSmoothingEnabled = false;
+    ctx.drawImage(img, 0, 0);
+    state.originalImageData = ctx.getImageData(0, 0, canvas.width, canvas.height);
+
+    // Apply current blur setting
+    applyCrtBlur();
+
+    // Update histogram
+    drawHistogram();
+  };
+  img.src = `data:image/png;base64,${base64}`;
+}
+
+/**
+ * Frames to export: the converted image, frames A and B (with suffixes) in flicker mode,
+ * or the VDC1 and VDC2 layers in SuperGrafx mode
+ */
+/**
+ * VRAM address of the tiles of an export frame. Both flicker frames alternate at 60 Hz, so the
+ * tiles of frame B follow those of frame A; the VDC2 layer has its own VRAM.
+ */
+function getFrameVramAddress(frame, vramAddress, firstFrameUniqueTileCount) {
+  return frame.suffix === "_b" ? vramAddress + firstFrameUniqueTileCount * 16 : vramAddress;
+}
+
+function getExportFrames() {
+  const frameA = {
+    suffix: "",
+    imageBase64: state.outputImageBase64,
+    palettes: state.palettes,
+    tilePaletteMap: state.tilePaletteMap,
+    emptyTiles: state.emptyTiles,
+    pixelIndices: state.pixelIndices,
+  };
+  if (state.sgx) {
+    return [
+      { ...frameA, suffix: "_vdc1", sgxLayer: 1 },
+      {
+        suffix: "_vdc2",
+        imageBase64: state.sgx.preview_base64,
+        palettes: state.palettes,
+        tilePaletteMap: state.sgx.tile_palette_map,
+        emptyTiles: state.sgx.empty_tiles,
+        sgxLayer: 2,
+      },
+    ];
+  }
+  if (!state.flicker) {
+    return [frameA];
+  }
+  return [
+    { ...frameA, suffix: "_a" },
+    {
+      suffix: "_b",
+      imageBase64: state.flicker.preview_base64,
+      palettes: state.flicker.palettes,
+      tilePaletteMap: state.flicker.tile_palette_map,
+      emptyTiles: state.flicker.empty_tiles,
+    },
+  ];
+}
+
+function base64ToBytes(base64) {
+  const binaryString = atob(base64);
+  const bytes = new Uint8Array(binaryString.length);
+  for (let i = 0; i < binaryString.length; i++) {
+    bytes[i] = binaryString.charCodeAt(i);
+  }
+  return bytes;
+}
+
+// Insert a suffix before the file extension (or at the end if there is none)
+function withFileSuffix(path, suffix) {
+  if (!suffix) return path;
+  const match = path.match(/^(.*?)(\.[^.\\/]*)?$/);
+  return `${match[1]}${suffix}${match[2] || ""}`;
+}
+
+/**
+ * Convert at several palette counts and show the results as a comparison strip
+ */
+async function runPaletteSweep() {
+  if (!state.inputImage || state.isConverting) {
+    return;
+  }
+
+  const strip = document.querySelector("#palette-sweep-strip");
+  const countsInput = document.querySelector("#palette-sweep-counts")?.value || "";
+  const paletteCounts = countsInput
+    .split(/[,;\s]+/)
+    .map((value) => parseInt(value, 10))
+    .filter((value) => value >= 1 && value <= 16);
+  if (!strip || paletteCounts.length === 0) {
+    return;
+  }
+
+  state.isConverting = true;
+  strip.innerHTML = "<p>Comparaison en cours...</p>";
+  showProgress(true, "Comparaison des palettes...");
+
+  const unlisten = await listen("conversion-progress", (event) => {
+    const { percent, stage } = event.payload;
+    updateProgress(percent, stage);
+  });
+
+  try {
+    const { inputPath, sourceTransform, settings } = getConversionParams();
+    const paletteCount = settings.palettes.count;
+    const entries = await invoke("run_palette_sweep", {
+      inputPath,
+      sourceTransform,
+      settings,
+      paletteCounts,
+      previewWidth: 128,
+    });
+
+    strip.innerHTML = "";
+    entries.forEach((entry) => {
+      const card = document.createElement("button");
+      card.className = "palette-sweep__card";
+      card.classList.toggle("is-selected", entry.palette_count === paletteCount);
+      card.title = "Utiliser ce nombre de palettes";
+      const psnr = Number.isFinite(entry.psnr) ? `${entry.psnr.toFixed(1)} dB` : "∞";
+      card.innerHTML = `
+        <img src="data:image/png;base64,${entry.preview_base64}" alt="${entry.palette_count} palettes" />
+        <span class="palette-sweep__title">${entry.palette_count} palettes (${entry.used_palette_count} utilisées)</span>
+        <span class="palette-sweep__stats">${entry.unique_tile_count} tuiles uniques</span>
+        <span class="palette-sweep__stats">MSE ${entry.mse.toFixed(1)} — PSNR ${psnr}</span>
+      `;
+      card.addEventListener("click", () => {
+        const input = document.querySelector("#palette-count");
+        if (input) input.value = entry.palette_count;
+        saveSettings();
+        strip.querySelectorAll(".palette-sweep__card").forEach((el) => el.classList.remove("is-selected"));
+        card.classList.add("is-selected");
+      });
+      strip.appendChild(card);
+    });
+  } catch (error) {
+    console.error("Palette sweep error:", error);
+    strip.innerHTML = "<p>Erreur lors de la comparaison</p>";
+  } finally {
+    state.isConverting = false;
+    unlisten();
+    setTimeout(() => showProgress(false), 500);
+  }
+}
+
+/**
+ * Check the PCE rules on the placed source and list the tiles that break them
+ */
+async function runArtValidation() {
+  if (!state.inputImage || state.isConverting) {
+    return;
+  }
+
+  const report = document.querySelector("#art-validation-report");
+  if (!report) {
+    return;
+  }
+  report.innerHTML = "<p>Validation en cours...</p>";
+
+  try {
+    const { inputPath, sourceTransform, settings } = getConversionParams();
+    const result = await invoke("validate_pce_art", { inputPath, sourceTransform, settings });
+    const { violations, tile_count: tileCount, color_count: colorCount, palettes, cover_error: coverError, grid_offset: gridOffset } = result;
+    const gridInfo = gridOffset ? `<p>Alignement grille : décalage de ${gridOffset[0]}, ${gridOffset[1]} px</p>` : "";
+
+    if (violations.length > 0) {
+      const shown = violations.slice(0, 200);
+      const more = violations.length > shown.length ? `<li>… ${violations.length - shown.length} autres</li>` : "";
+      report.innerHTML =
+        `<p class="vram-exceeded">${violations.length} tuiles sur ${tileCount} ne respectent pas les contraintes PCE</p>` +
+        `<ul>${shown.map((v) => `<li>Tuile ${v.tile_x}, ${v.tile_y} : ${v.messages.join(", ")}</li>`).join("")}${more}</ul>` + gridInfo;
+    } else if (coverError) {
+      report.innerHTML =
+        `<p>Tuiles conformes (${colorCount} couleurs en plus de la couleur 0)</p>` +
+        `<p class="vram-exceeded">${coverError}</p>` + gridInfo;
+    } else {
+      report.innerHTML =
+        `<p>Image conforme : ${colorCount} couleurs en plus de la couleur 0, ${palettes.length} palettes exactes</p>` + gridInfo;
+    }
+  } catch (error) {
+    console.error("Validation error:", error);
+    report.innerHTML = `<p class="vram-exceeded">Erreur de validation : ${error}</p>`;
+  }
+}
+
 function renderPalettes(palettes, tilePaletteMap = []) {
   const grid = document.querySelector("#palettes-grid");
   const summary = document.querySelector("#palette-summary");
@@ -1101,7 +1748,9 @@ function renderPalettes(palettes, tilePaletteMap = []) {
     card.dataset.paletteIndex = index;
     const title = document.createElement("div");
     title.className = "palette-card__title";
-    title.textContent = `Palette ${index} (${usageCount} tuiles)`;
+    title.textContent = state.paletteBandRows > 0
+      ? `Bande ${Math.floor(index / 16)} · Palette ${index % 16} (${usageCount} tuiles)`
+      : `Palette ${index} (${usageCount} tuiles)`;
     const colors = document.createElement("div");
     colors.className = "palette-card__colors";
     palette.forEach((color) => {
@@ -1310,8 +1959,124 @@ function applyZoom(target) {
     return;
   }
   const zoom = Number(slider.value);
+  const zoomX = target === "output" ? zoom * getDisplayAspect() : zoom;
   const dragState = state.drag[target];
-  stage.style.transform = `translate(${dragState.x}px, ${dragState.y}px) scale(${zoom})`;
+  stage.style.transform = `translate(${dragState.x}px, ${dragState.y}px) scale(${zoomX}, ${zoom})`;
+}
+
+/**
+ * Pixel aspect ratio of the selected video mode (1 = square pixels)
+ */
+function getPixelAspect() {
+  const mode = VIDEO_MODES[document.querySelector("#video-mode")?.value];
+  return mode ? mode.pixelAspect : 1;
+}
+
+/**
+ * Crop, scale and pan of the source (same fields as the Rust SourceTransform)
+ */
+function getSourceTransform() {
+  const read = (selector, parse) => parse(document.querySelector(selector)?.value) || 0;
+  const layerValue = document.querySelector("#ase-layer")?.value || "";
+  return {
+    crop_x: Math.max(0, read("#source-crop-x", (v) => parseInt(v, 10))),
+    crop_y: Math.max(0, read("#source-crop-y", (v) => parseInt(v, 10))),
+    crop_width: Math.max(0, read("#source-crop-width", (v) => parseInt(v, 10))),
+    crop_height: Math.max(0, read("#source-crop-height", (v) => parseInt(v, 10))),
+    scale: Math.min(16, Math.max(0, read("#source-scale", parseFloat))),
+    offset_x: read("#source-pan-x", (v) => parseInt(v, 10)),
+    offset_y: read("#source-pan-y", (v) => parseInt(v, 10)),
+    pixel_art: document.querySelector("#pixel-art")?.checked || false,
+    layer: layerValue === "" ? null : parseInt(layerValue, 10),
+    frame: Math.max(0, (parseInt(document.querySelector("#ase-frame")?.value, 10) || 1) - 1),
+  };
+}
+
+/**
+ * Crop rectangle clamped to the source size (mirrors SourceTransform::crop_rect)
+ */
+function getSourceCropRect(transform, srcWidth, srcHeight) {
+  if (!transform.crop_width || !transform.crop_height) {
+    return { x: 0, y: 0, width: srcWidth, height: srcHeight };
+  }
+  const x = Math.min(transform.crop_x, Math.max(0, srcWidth - 1));
+  const y = Math.min(transform.crop_y, Math.max(0, srcHeight - 1));
+  return {
+    x,
+    y,
+    width: Math.min(transform.crop_width, srcWidth - x),
+    height: Math.min(transform.crop_height, srcHeight - y),
+  };
+}
+
+/**
+ * Position and size of the cropped source in the output, in pixels
+ * (mirrors SourceTransform::placement in Rust)
+ */
+function getSourcePlacement(transform, srcWidth, srcHeight, dstWidth, dstHeight, keepRatio, pixelAspect) {
+  let width, height;
+  if (transform.scale > 0) {
+    width = Math.max(1, Math.round(srcWidth * transform.scale / pixelAspect));
+    height = Math.max(1, Math.round(srcHeight * transform.scale));
+  } else if (keepRatio) {
+    const srcRatio = srcWidth / srcHeight;
+    const dstRatio = dstWidth * pixelAspect / dstHeight;
+    if (srcRatio > dstRatio) {
+      // Source is wider - fit to width
+      width = dstWidth;
+      height = Math.min(Math.max(Math.round(dstWidth * pixelAspect / srcRatio), 1), dstHeight);
+    } else {
+      // Source is taller - fit to height
+      height = dstHeight;
+      width = Math.min(Math.max(Math.round(dstHeight * srcRatio / pixelAspect), 1), dstWidth);
+    }
+  } else {
+    width = dstWidth;
+    height = dstHeight;
+  }
+  // Centered, then panned
+  return {
+    x: Math.floor((dstWidth - width) / 2) + transform.offset_x,
+    y: Math.floor((dstHeight - height) / 2) + transform.offset_y,
+    width,
+    height,
+  };
+}
+
+function resetSourceTransform() {
+  ["#source-crop-x", "#source-crop-y", "#source-crop-width", "#source-crop-height", "#source-scale", "#source-pan-x", "#source-pan-y"]
+    .forEach((selector) => {
+      const el = document.querySelector(selector);
+      if (el) el.value = 0;
+    });
+  saveSettings();
+}
+
+/**
+ * Horizontal stretch of the output preview: the pixel aspect of the last conversion
+ * when the screen aspect preview is on
+ */
+function getDisplayAspect() {
+  return document.querySelector("#display-aspect")?.checked ? state.outputPixelAspect || 1 : 1;
+}
+
+/**
+ * Apply a video mode preset: output size in tiles, with a BAT wide enough for it
+ */
+function applyVideoMode() {
+  const mode = VIDEO_MODES[document.querySelector("#video-mode")?.value];
+  if (!mode) return;
+
+  const batSizeEl = document.querySelector("#bat-size");
+  const [batW] = (batSizeEl?.value || "32x32").split("x").map(Number);
+  const widthTiles = Math.ceil(mode.width / 8);
+  if (batSizeEl && batW < widthTiles) {
+    batSizeEl.value = "64x32";
+  }
+  updateSizeConstraints();
+  document.querySelector("#output-width-tiles").value = widthTiles;
+  document.querySelector("#output-height-tiles").value = Math.ceil(mode.height / 8);
+  updateSizeConstraints();
 }
 
 function setupDrag(target) {
@@ -1422,8 +2187,11 @@ function setupTileHover() {
     const stageRect = stage.getBoundingClientRect();
     const imgRect = img.getBoundingClientRect();
 
+    // The screen aspect preview stretches the stage horizontally
+    const zoomX = zoom * getDisplayAspect();
+
     // Calculate image offset within the stage (due to flexbox centering)
-    const imgOffsetX = (imgRect.left - stageRect.left) / zoom;
+    const imgOffsetX = (imgRect.left - stageRect.left) / zoomX;
     const imgOffsetY = (imgRect.top - stageRect.top) / zoom;
 
     // Calculate mouse position relative to image
@@ -1431,7 +2199,7 @@ function setupTileHover() {
     const mouseY = event.clientY - imgRect.top;
 
     // Convert to image coordinates
-    const imgX = mouseX / zoom;
+    const imgX = mouseX / zoomX;
     const imgY = mouseY / zoom;
 
     // Get output dimensions from state (default to 256 if not set)
@@ -1920,6 +2688,9 @@ function getTileAndPixelFromEvent(event) {
 function saveTileEditorState() {
   if (!state.originalImageData) return;
 
+  // Edited pixels no longer follow the source indices: the export matches colors again
+  state.pixelIndices = [];
+
   // Remove any redo states
   if (state.tileEditor.historyIndex < state.tileEditor.history.length - 1) {
     state.tileEditor.history = state.tileEditor.history.slice(
@@ -2513,9 +3284,12 @@ function getPaletteGroupConstraints() {
   const outputWidthTiles = parseInt(document.querySelector("#output-width-tiles").value, 10);
   const outputHeightTiles = parseInt(document.querySelector("#output-height-tiles").value, 10);
   const keepRatio = document.querySelector("#keep-ratio")?.checked || false;
+  const transform = getSourceTransform();
+  const isDefaultTransform = !transform.crop_width && !transform.crop_height && !transform.scale &&
+    !transform.offset_x && !transform.offset_y;
 
   // If dimensions match and no keep ratio, use direct mapping
-  if (!keepRatio && gridWidth === outputWidthTiles && gridHeight === outputHeightTiles) {
+  if (!keepRatio && isDefaultTransform && gridWidth === outputWidthTiles && gridHeight === outputHeightTiles) {
     const constraints = [];
     for (let y = 0; y < gridHeight; y++) {
       for (let x = 0; x < gridWidth; x++) {
@@ -2524,75 +3298,45 @@ function getPaletteGroupConstraints() {
       }
     }
     return constraints;
-  }
-
-  // Get source image dimensions
-  const sourceImg = document.querySelector("#source-image");
-  if (!sourceImg) {
-    return [];
-  }
-  const srcWidth = sourceImg.naturalWidth;
-  const srcHeight = sourceImg.naturalHeight;
-
-  // Target dimensions in pixels
-  const dstWidth = outputWidthTiles * 8;
-  const dstHeight = outputHeightTiles * 8;
-
-  // Calculate scaled dimensions (same logic as Rust resize_to_target)
-  let scaledWidth, scaledHeight, offsetX, offsetY;
-
-  if (!keepRatio) {
-    // Simple stretch to fill
-    scaledWidth = dstWidth;
-    scaledHeight = dstHeight;
-    offsetX = 0;
-    offsetY = 0;
-  } else {
-    // Calculate scaled dimensions keeping aspect ratio
-    const srcRatio = srcWidth / srcHeight;
-    const dstRatio = dstWidth / dstHeight;
-
-    if (srcRatio > dstRatio) {
-      // Source is wider - fit to width
-      scaledWidth = dstWidth;
-      scaledHeight = Math.min(Math.round(dstWidth / srcRatio), dstHeight);
-    } else {
-      // Source is taller - fit to height
-      scaledHeight = dstHeight;
-      scaledWidth = Math.min(Math.round(dstHeight * srcRatio), dstWidth);
-    }
+  }
 
-    // Calculate offsets to center
-    offsetX = Math.floor((dstWidth - scaledWidth) / 2);
-    offsetY = Math.floor((dstHeight - scaledHeight) / 2);
+  // Get source image dimensions
+  const sourceImg = document.querySelector("#source-image");
+  if (!sourceImg) {
+    return [];
   }
+  const srcWidth = sourceImg.naturalWidth;
+  const srcHeight = sourceImg.naturalHeight;
+
+  // Target dimensions in pixels
+  const dstWidth = outputWidthTiles * 8;
+  const dstHeight = outputHeightTiles * 8;
 
-  // Convert pixel offsets to tile offsets
-  const tileOffsetX = Math.floor(offsetX / 8);
-  const tileOffsetY = Math.floor(offsetY / 8);
-  const scaledWidthTiles = Math.ceil(scaledWidth / 8);
-  const scaledHeightTiles = Math.ceil(scaledHeight / 8);
+  // Same crop and placement as the Rust resize_to_target and resize_mask
+  const crop = getSourceCropRect(transform, srcWidth, srcHeight);
+  const placement = getSourcePlacement(transform, crop.width, crop.height, dstWidth, dstHeight, keepRatio, getPixelAspect());
 
-  // Create constraints array for OUTPUT tiles
+  // Create constraints array for OUTPUT tiles, sampled at the tile centers
   const constraints = [];
   for (let outY = 0; outY < outputHeightTiles; outY++) {
     for (let outX = 0; outX < outputWidthTiles; outX++) {
-      // Check if this output tile is within the actual image area
-      if (outX >= tileOffsetX && outX < tileOffsetX + scaledWidthTiles &&
-          outY >= tileOffsetY && outY < tileOffsetY + scaledHeightTiles) {
-        // Map output tile to source virtual tile
-        const localX = outX - tileOffsetX;
-        const localY = outY - tileOffsetY;
-        const srcX = Math.floor(localX * gridWidth / scaledWidthTiles);
-        const srcY = Math.floor(localY * gridHeight / scaledHeightTiles);
-
-        // Get constraint from source grid
-        const group = assignments[srcY]?.[srcX];
-        constraints.push(group !== null && group !== undefined ? group : -1);
-      } else {
+      const localX = outX * 8 + 4 - placement.x;
+      const localY = outY * 8 + 4 - placement.y;
+      if (localX < 0 || localY < 0 || localX >= placement.width || localY >= placement.height) {
         // Tile is outside image area (padding) - no constraint
         constraints.push(-1);
+        continue;
       }
+
+      // Map output tile to source pixel, then to the virtual tile grid of the source
+      const srcPixelX = crop.x + Math.min(Math.floor(localX * crop.width / placement.width), crop.width - 1);
+      const srcPixelY = crop.y + Math.min(Math.floor(localY * crop.height / placement.height), crop.height - 1);
+      const srcX = Math.min(Math.floor(srcPixelX * gridWidth / srcWidth), gridWidth - 1);
+      const srcY = Math.min(Math.floor(srcPixelY * gridHeight / srcHeight), gridHeight - 1);
+
+      // Get constraint from source grid
+      const group = assignments[srcY]?.[srcX];
+      constraints.push(group !== null && group !== undefined ? group : -1);
     }
   }
 
@@ -2984,20 +3728,32 @@ function getVramAddress() {
   return isNaN(parsed) ? 0x4000 : parsed;
 }
 
+/**
+ * Streaming order of the large map export, "" when the image fits the BAT
+ */
+function getMapOrder() {
+  if (!document.querySelector("#large-map")?.checked) {
+    return "";
+  }
+  return document.querySelector("#map-order")?.value || "columns";
+}
+
 async function exportPlainText() {
+  if (state.spriteConversion) {
+    await exportSpritePlainText();
+    return;
+  }
+  if (state.spriteAnimation) {
+    await exportSpriteAnimationPlainText();
+    return;
+  }
+
   if (!state.outputImageBase64 || state.palettes.length === 0) {
     console.warn("Aucune image convertie à exporter");
     return;
   }
 
   try {
-    // Convert base64 to byte array
-    const binaryString = atob(state.outputImageBase64);
-    const imageData = new Uint8Array(binaryString.length);
-    for (let i = 0; i < binaryString.length; i++) {
-      imageData[i] = binaryString.charCodeAt(i);
-    }
-
     const vramAddress = getVramAddress();
 
     // Get BAT size and offset settings
@@ -3006,18 +3762,30 @@ async function exportPlainText() {
     const offsetX = parseInt(document.querySelector("#offset-x")?.value, 10) || 0;
     const offsetY = parseInt(document.querySelector("#offset-y")?.value, 10) || 0;
 
-    // Call Rust export function
-    const result = await invoke("export_plain_text", {
-      imageData: Array.from(imageData),
-      palettes: state.palettes,
-      tilePaletteMap: state.tilePaletteMap,
-      emptyTiles: state.emptyTiles,
-      vramBaseAddress: vramAddress,
-      batWidth,
-      batHeight,
-      offsetX,
-      offsetY,
-    });
+    // Call Rust export function (once per frame in flicker mode)
+    const frames = getExportFrames();
+    const results = [];
+    for (const frame of frames) {
+      const result = await invoke("export_plain_text", {
+        imageData: Array.from(base64ToBytes(frame.imageBase64)),
+        palettes: frame.palettes,
+        tilePaletteMap: frame.tilePaletteMap,
+        emptyTiles: frame.emptyTiles,
+        vramBaseAddress: getFrameVramAddress(frame, vramAddress, results[0]?.result.unique_tile_count || 0),
+        batWidth,
+        batHeight,
+        offsetX,
+        offsetY,
+        paletteBandRows: state.paletteBandRows,
+        rasterBands: state.rasterBands,
+        sgxLayer: frame.sgxLayer || 0,
+        overlaySprites: state.spriteOverlay?.sprites || [],
+        spritePalettes: state.spriteOverlay?.palettes || [],
+        mapOrder: getMapOrder(),
+        pixelIndices: frame.pixelIndices || [],
+      });
+      results.push({ suffix: frame.suffix, result });
+    }
 
     // Show save dialog
     const { save } = window.__TAURI__.dialog;
@@ -3030,29 +3798,169 @@ async function exportPlainText() {
     });
 
     if (filePath) {
-      // Write file
+      // Write file(s)
+      const { writeTextFile } = window.__TAURI__.fs;
+      for (const { suffix, result } of results) {
+        await writeTextFile(withFileSuffix(filePath, suffix), result.plain_text);
+        console.info(`Exporté${suffix}: ${result.unique_tile_count} tuiles uniques (${result.tile_count} total)`);
+      }
+    }
+  } catch (error) {
+    console.error("Erreur d'export:", error);
+    alert("Erreur d'export : " + error);
+  }
+}
+
+async function exportSpritePlainText() {
+  try {
+    const text = await invoke("export_sprite_plain_text", {
+      sprites: state.spriteConversion.sprites,
+      palettes: state.spriteConversion.palettes,
+      vramBaseAddress: getVramAddress(),
+    });
+
+    const { save } = window.__TAURI__.dialog;
+    const filePath = await save({
+      defaultPath: "sprites.asm",
+      filters: [
+        { name: "Assembly", extensions: ["asm", "inc", "s"] },
+        { name: "Text", extensions: ["txt"] },
+      ],
+    });
+
+    if (filePath) {
       const { writeTextFile } = window.__TAURI__.fs;
-      await writeTextFile(filePath, result.plain_text);
-      console.info(`Exporté: ${result.unique_tile_count} tuiles uniques (${result.tile_count} total)`);
+      await writeTextFile(filePath, text);
+      console.info(`Exporté: ${state.spriteConversion.sprites.length} sprites`);
     }
   } catch (error) {
     console.error("Erreur d'export:", error);
   }
 }
 
+async function exportSpriteBinaries() {
+  try {
+    // Patterns follow the tiles endianness, the SATB the BAT one
+    const result = await invoke("export_sprite_binaries", {
+      sprites: state.spriteConversion.sprites,
+      palettes: state.spriteConversion.palettes,
+      vramBaseAddress: getVramAddress(),
+      patternsBigEndian: document.querySelector("#tiles-big-endian")?.checked || false,
+      satbBigEndian: document.querySelector("#bat-big-endian")?.checked || false,
+      palBigEndian: document.querySelector("#pal-big-endian")?.checked || false,
+    });
+
+    const { save } = window.__TAURI__.dialog;
+    const basePath = await save({
+      defaultPath: "sprites",
+      filters: [{ name: "Export name", extensions: ["bin"] }],
+    });
+    if (!basePath) {
+      return;
+    }
+
+    await invoke("save_binaries_to_disk", {
+      basePath,
+      files: { sprites: result.patterns, satb: result.satb, sprite_palettes: result.palettes },
+    });
+    console.info(`Sprites exportés : ${result.sprite_count} sprites`);
+  } catch (error) {
+    console.error("Erreur d'export:", error);
+  }
+}
+
+async function exportSpriteAnimationPlainText() {
+  try {
+    const text = await invoke("export_sprite_animation_plain_text", {
+      patterns: state.spriteAnimation.patterns,
+      frames: state.spriteAnimation.frames,
+      palettes: state.spriteAnimation.palettes,
+      vramBaseAddress: getVramAddress(),
+    });
+
+    const { save } = window.__TAURI__.dialog;
+    const filePath = await save({
+      defaultPath: "animation.asm",
+      filters: [
+        { name: "Assembly", extensions: ["asm", "inc", "s"] },
+        { name: "Text", extensions: ["txt"] },
+      ],
+    });
+
+    if (filePath) {
+      const { writeTextFile } = window.__TAURI__.fs;
+      await writeTextFile(filePath, text);
+      console.info(`Exporté: ${state.spriteAnimation.frames.length} images, ${state.spriteAnimation.patterns.length} motifs`);
+    }
+  } catch (error) {
+    console.error("Erreur d'export:", error);
+  }
+}
+
+async function exportSpriteAnimationBinaries() {
+  try {
+    // Patterns follow the tiles endianness, the metasprite tables the BAT one
+    const result = await invoke("export_sprite_animation_binaries", {
+      patterns: state.spriteAnimation.patterns,
+      frames: state.spriteAnimation.frames,
+      palettes: state.spriteAnimation.palettes,
+      vramBaseAddress: getVramAddress(),
+      patternsBigEndian: document.querySelector("#tiles-big-endian")?.checked || false,
+      metaspritesBigEndian: document.querySelector("#bat-big-endian")?.checked || false,
+      palBigEndian: document.querySelector("#pal-big-endian")?.checked || false,
+    });
+
+    const { save } = window.__TAURI__.dialog;
+    const basePath = await save({
+      defaultPath: "animation",
+      filters: [{ name: "Export name", extensions: ["bin"] }],
+    });
+    if (!basePath) {
+      return;
+    }
+
+    await invoke("save_binaries_to_disk", {
+      basePath,
+      files: { sprites: result.patterns, sprite_palettes: result.palettes, metasprites: result.metasprites },
+    });
+    console.info(`Animation exportée : ${result.frame_count} images, ${result.pattern_count} motifs`);
+  } catch (error) {
+    console.error("Erreur d'export:", error);
+  }
+}
+
+// Files of an export_binaries result for save_binaries_to_disk
+function binaryFiles(result) {
+  return {
+    bat: result.bat,
+    tiles: result.tiles,
+    palettes: result.palettes,
+    raster: result.raster,
+    vpc: result.vpc,
+    sprites: result.sprites,
+    satb: result.satb,
+    sprite_palettes: result.sprite_palettes,
+    map: result.map,
+  };
+}
+
 async function exportBinaries() {
+  if (state.spriteConversion) {
+    await exportSpriteBinaries();
+    return;
+  }
+  if (state.spriteAnimation) {
+    await exportSpriteAnimationBinaries();
+    return;
+  }
+
   if (!state.outputImageBase64 || state.palettes.length === 0) {
     console.warn("Aucune image convertie à exporter");
     return;
   }
 
   try {
-    // Convert base64 to byte array
-    const binaryString = atob(state.outputImageBase64);
-    const imageData = new Uint8Array(binaryString.length);
-    for (let i = 0; i < binaryString.length; i++) {
-      imageData[i] = binaryString.charCodeAt(i);
-    }
+    const imageData = base64ToBytes(state.outputImageBase64);
 
     const vramAddress = getVramAddress();
 
@@ -3080,6 +3988,7 @@ async function exportBinaries() {
     console.info(`DEBUG EXPORT: First 20 tiles (E=empty): ${nonEmptyIndices.join(', ')}`);
 
     // Call Rust export function to generate binary data
+    const frames = getExportFrames();
     const result = await invoke("export_binaries", {
       imageData: Array.from(imageData),
       palettes: state.palettes,
@@ -3093,6 +4002,13 @@ async function exportBinaries() {
       batHeight,
       offsetX,
       offsetY,
+      paletteBandRows: state.paletteBandRows,
+      rasterBands: state.rasterBands,
+      sgxLayer: frames[0].sgxLayer || 0,
+      overlaySprites: state.spriteOverlay?.sprites || [],
+      spritePalettes: state.spriteOverlay?.palettes || [],
+      mapOrder: getMapOrder(),
+      pixelIndices: frames[0].pixelIndices || [],
     });
 
     // Show save dialog - user picks base filename
@@ -3109,12 +4025,39 @@ async function exportBinaries() {
 
     // Call Rust to create directory and write files
     await invoke("save_binaries_to_disk", {
-      basePath,
-      batData: result.bat,
-      tilesData: result.tiles,
-      palData: result.palettes,
+      basePath: withFileSuffix(basePath, frames[0].suffix),
+      files: binaryFiles(result),
     });
 
+    // Flicker mode: second frame in its own directory (suffix _b), SuperGrafx: VDC2 layer (suffix _vdc2)
+    for (const frame of frames.slice(1)) {
+      const frameResult = await invoke("export_binaries", {
+        imageData: Array.from(base64ToBytes(frame.imageBase64)),
+        palettes: frame.palettes,
+        tilePaletteMap: frame.tilePaletteMap,
+        emptyTiles: frame.emptyTiles,
+        vramBaseAddress: getFrameVramAddress(frame, vramAddress, result.unique_tile_count),
+        batBigEndian,
+        palBigEndian,
+        tilesBigEndian,
+        batWidth,
+        batHeight,
+        offsetX,
+        offsetY,
+        paletteBandRows: state.paletteBandRows,
+        rasterBands: state.rasterBands,
+        sgxLayer: frame.sgxLayer || 0,
+        overlaySprites: [],
+        spritePalettes: [],
+        mapOrder: getMapOrder(),
+        pixelIndices: frame.pixelIndices || [],
+      });
+      await invoke("save_binaries_to_disk", {
+        basePath: withFileSuffix(basePath, frame.suffix),
+        files: binaryFiles(frameResult),
+      });
+    }
+
     console.info(`Binaires exportés dans le répertoire`);
     console.info(`${result.unique_tile_count} tuiles uniques (${result.tile_count} total)`);
     console.info(`DEBUG: Image ${result.image_width}x${result.image_height}, ${result.palette_count} palettes, ${result.empty_tile_count} tuiles vides`);
@@ -3125,6 +4068,7 @@ async function exportBinaries() {
     }
   } catch (error) {
     console.error("Erreur d'export binaire:", error);
+    alert("Erreur d'export : " + error);
   }
 }
 
@@ -3227,6 +4171,50 @@ function saveSettings() {
     batBigEndian: document.querySelector("#bat-big-endian")?.checked,
     palBigEndian: document.querySelector("#pal-big-endian")?.checked,
     tilesBigEndian: document.querySelector("#tiles-big-endian")?.checked,
+    tileReductionMode: document.querySelector("#tile-reduction-mode")?.value,
+    tileReductionThreshold: document.querySelector("#tile-reduction-threshold")?.value,
+    maxUniqueTiles: document.querySelector("#max-unique-tiles")?.value,
+    ditherSerpentine: document.querySelector("#dither-serpentine")?.checked,
+    ditherStrength: document.querySelector("#dither-strength")?.value,
+    ditherCrossTile: document.querySelector("#dither-cross-tile")?.checked,
+    ditherErrorClamp: document.querySelector("#dither-error-clamp")?.value,
+    orderedMatrix: document.querySelector("#ordered-matrix")?.value,
+    orderedSpread: document.querySelector("#ordered-spread")?.value,
+    orderedAlign: document.querySelector("#ordered-align")?.value,
+    patternReuseTolerance: document.querySelector("#pattern-reuse-tolerance")?.value,
+    flickerMode: document.querySelector("#flicker-mode")?.checked,
+    paletteBandRows: document.querySelector("#palette-band-rows")?.value,
+    maxBandPaletteChanges: document.querySelector("#max-band-palette-changes")?.value,
+    rasterBands: document.querySelector("#raster-bands")?.value,
+    sgxMode: document.querySelector("#sgx-mode")?.value,
+    sgxSplitColors: document.querySelector("#sgx-split-colors")?.value,
+    spriteOverlay: document.querySelector("#sprite-overlay")?.checked,
+    maxOverlaySprites: document.querySelector("#max-overlay-sprites")?.value,
+    conversionTarget: document.querySelector("#conversion-target")?.value,
+    spriteCellSize: document.querySelector("#sprite-cell-size")?.value,
+    animationSource: document.querySelector("#animation-source")?.value,
+    animationFrameWidth: document.querySelector("#animation-frame-width")?.value,
+    animationFrameHeight: document.querySelector("#animation-frame-height")?.value,
+    animationFrameCount: document.querySelector("#animation-frame-count")?.value,
+    largeMap: document.querySelector("#large-map")?.checked,
+    mapOrder: document.querySelector("#map-order")?.value,
+    videoMode: document.querySelector("#video-mode")?.value,
+    displayAspect: document.querySelector("#display-aspect")?.checked,
+    sourceCropX: document.querySelector("#source-crop-x")?.value,
+    sourceCropY: document.querySelector("#source-crop-y")?.value,
+    sourceCropWidth: document.querySelector("#source-crop-width")?.value,
+    sourceCropHeight: document.querySelector("#source-crop-height")?.value,
+    sourceScale: document.querySelector("#source-scale")?.value,
+    sourcePanX: document.querySelector("#source-pan-x")?.value,
+    sourcePanY: document.querySelector("#source-pan-y")?.value,
+    gridAlign: document.querySelector("#grid-align")?.value,
+    gridAlignStep: document.querySelector("#grid-align-step")?.value,
+    pixelArt: document.querySelector("#pixel-art")?.checked,
+    lossless: document.querySelector("#lossless")?.checked,
+    indexedPalettes: document.querySelector("#indexed-palettes")?.checked,
+    aseLayer: document.querySelector("#ase-layer")?.value,
+    aseFrame: document.querySelector("#ase-frame")?.value,
+    linearLight: document.querySelector("#linear-light")?.checked,
   };
 
   try {
@@ -3349,6 +4337,190 @@ function loadSettings() {
       if (el) el.checked = settings.tilesBigEndian;
     }
 
+    // Restore tile reduction settings
+    if (settings.tileReductionMode) {
+      const el = document.querySelector("#tile-reduction-mode");
+      if (el) el.value = settings.tileReductionMode;
+    }
+    if (settings.tileReductionThreshold) {
+      const el = document.querySelector("#tile-reduction-threshold");
+      if (el) el.value = settings.tileReductionThreshold;
+    }
+    if (settings.maxUniqueTiles) {
+      const el = document.querySelector("#max-unique-tiles");
+      if (el) el.value = settings.maxUniqueTiles;
+    }
+
+    // Restore error diffusion settings
+    if (settings.ditherSerpentine !== undefined) {
+      const el = document.querySelector("#dither-serpentine");
+      if (el) el.checked = settings.ditherSerpentine;
+    }
+    if (settings.ditherStrength) {
+      const el = document.querySelector("#dither-strength");
+      if (el) {
+        el.value = settings.ditherStrength;
+        document.querySelector("#dither-strength-value").textContent = `${settings.ditherStrength}%`;
+      }
+    }
+    if (settings.ditherCrossTile !== undefined) {
+      const el = document.querySelector("#dither-cross-tile");
+      if (el) el.checked = settings.ditherCrossTile;
+    }
+    if (settings.ditherErrorClamp) {
+      const el = document.querySelector("#dither-error-clamp");
+      if (el) el.value = settings.ditherErrorClamp;
+    }
+    if (settings.orderedMatrix) {
+      const el = document.querySelector("#ordered-matrix");
+      if (el) el.value = settings.orderedMatrix;
+    }
+    if (settings.orderedSpread) {
+      const el = document.querySelector("#ordered-spread");
+      if (el) el.value = settings.orderedSpread;
+    }
+    if (settings.orderedAlign) {
+      const el = document.querySelector("#ordered-align");
+      if (el) el.value = settings.orderedAlign;
+    }
+    if (settings.patternReuseTolerance) {
+      const el = document.querySelector("#pattern-reuse-tolerance");
+      if (el) el.value = settings.patternReuseTolerance;
+    }
+    if (settings.flickerMode !== undefined) {
+      const el = document.querySelector("#flicker-mode");
+      if (el) el.checked = settings.flickerMode;
+    }
+    if (settings.paletteBandRows) {
+      const el = document.querySelector("#palette-band-rows");
+      if (el) el.value = settings.paletteBandRows;
+    }
+    if (settings.maxBandPaletteChanges) {
+      const el = document.querySelector("#max-band-palette-changes");
+      if (el) el.value = settings.maxBandPaletteChanges;
+    }
+    if (settings.rasterBands) {
+      const el = document.querySelector("#raster-bands");
+      if (el) el.value = settings.rasterBands;
+    }
+    if (settings.sgxMode) {
+      const el = document.querySelector("#sgx-mode");
+      if (el) el.value = settings.sgxMode;
+    }
+    if (settings.sgxSplitColors) {
+      const el = document.querySelector("#sgx-split-colors");
+      if (el) el.value = settings.sgxSplitColors;
+    }
+    if (settings.spriteOverlay !== undefined) {
+      const el = document.querySelector("#sprite-overlay");
+      if (el) el.checked = settings.spriteOverlay;
+    }
+    if (settings.maxOverlaySprites) {
+      const el = document.querySelector("#max-overlay-sprites");
+      if (el) el.value = settings.maxOverlaySprites;
+    }
+    if (settings.conversionTarget) {
+      const el = document.querySelector("#conversion-target");
+      if (el) el.value = settings.conversionTarget;
+    }
+    if (settings.spriteCellSize) {
+      const el = document.querySelector("#sprite-cell-size");
+      if (el) el.value = settings.spriteCellSize;
+    }
+    if (settings.animationSource) {
+      const el = document.querySelector("#animation-source");
+      if (el) el.value = settings.animationSource;
+    }
+    if (settings.animationFrameWidth) {
+      const el = document.querySelector("#animation-frame-width");
+      if (el) el.value = settings.animationFrameWidth;
+    }
+    if (settings.animationFrameHeight) {
+      const el = document.querySelector("#animation-frame-height");
+      if (el) el.value = settings.animationFrameHeight;
+    }
+    if (settings.animationFrameCount) {
+      const el = document.querySelector("#animation-frame-count");
+      if (el) el.value = settings.animationFrameCount;
+    }
+    if (settings.largeMap !== undefined) {
+      const el = document.querySelector("#large-map");
+      if (el) el.checked = settings.largeMap;
+    }
+    if (settings.mapOrder) {
+      const el = document.querySelector("#map-order");
+      if (el) el.value = settings.mapOrder;
+    }
+    if (settings.videoMode) {
+      const el = document.querySelector("#video-mode");
+      if (el) el.value = settings.videoMode;
+    }
+    if (settings.displayAspect !== undefined) {
+      const el = document.querySelector("#display-aspect");
+      if (el) el.checked = settings.displayAspect;
+    }
+    if (settings.sourceCropX) {
+      const el = document.querySelector("#source-crop-x");
+      if (el) el.value = settings.sourceCropX;
+    }
+    if (settings.sourceCropY) {
+      const el = document.querySelector("#source-crop-y");
+      if (el) el.value = settings.sourceCropY;
+    }
+    if (settings.sourceCropWidth) {
+      const el = document.querySelector("#source-crop-width");
+      if (el) el.value = settings.sourceCropWidth;
+    }
+    if (settings.sourceCropHeight) {
+      const el = document.querySelector("#source-crop-height");
+      if (el) el.value = settings.sourceCropHeight;
+    }
+    if (settings.sourceScale) {
+      const el = document.querySelector("#source-scale");
+      if (el) el.value = settings.sourceScale;
+    }
+    if (settings.sourcePanX) {
+      const el = document.querySelector("#source-pan-x");
+      if (el) el.value = settings.sourcePanX;
+    }
+    if (settings.sourcePanY) {
+      const el = document.querySelector("#source-pan-y");
+      if (el) el.value = settings.sourcePanY;
+    }
+    if (settings.gridAlign) {
+      const el = document.querySelector("#grid-align");
+      if (el) el.value = settings.gridAlign;
+    }
+    if (settings.gridAlignStep) {
+      const el = document.querySelector("#grid-align-step");
+      if (el) el.value = settings.gridAlignStep;
+    }
+    if (settings.pixelArt !== undefined) {
+      const el = document.querySelector("#pixel-art");
+      if (el) el.checked = settings.pixelArt;
+    }
+    if (settings.lossless !== undefined) {
+      const el = document.querySelector("#lossless");
+      if (el) el.checked = settings.lossless;
+    }
+    if (settings.indexedPalettes !== undefined) {
+      const el = document.querySelector("#indexed-palettes");
+      if (el) el.checked = settings.indexedPalettes;
+    }
+    if (settings.aseLayer) {
+      // The layer options are only known once an Aseprite file is read
+      const el = document.querySelector("#ase-layer");
+      if (el) el.dataset.restore = settings.aseLayer;
+    }
+    if (settings.aseFrame) {
+      const el = document.querySelector("#ase-frame");
+      if (el) el.value = settings.aseFrame;
+    }
+    if (settings.linearLight !== undefined) {
+      const el = document.querySelector("#linear-light");
+      if (el) el.checked = settings.linearLight;
+    }
+
   } catch (e) {
     console.warn("Impossible de charger les réglages:", e);
   }
@@ -3374,6 +4546,50 @@ function setupSettingsAutoSave() {
     "#bat-big-endian",
     "#pal-big-endian",
     "#tiles-big-endian",
+    "#tile-reduction-mode",
+    "#tile-reduction-threshold",
+    "#max-unique-tiles",
+    "#dither-serpentine",
+    "#dither-strength",
+    "#dither-cross-tile",
+    "#dither-error-clamp",
+    "#ordered-matrix",
+    "#ordered-spread",
+    "#ordered-align",
+    "#pattern-reuse-tolerance",
+    "#flicker-mode",
+    "#palette-band-rows",
+    "#max-band-palette-changes",
+    "#raster-bands",
+    "#sgx-mode",
+    "#sgx-split-colors",
+    "#sprite-overlay",
+    "#max-overlay-sprites",
+    "#conversion-target",
+    "#sprite-cell-size",
+    "#animation-source",
+    "#animation-frame-width",
+    "#animation-frame-height",
+    "#animation-frame-count",
+    "#large-map",
+    "#map-order",
+    "#video-mode",
+    "#display-aspect",
+    "#source-crop-x",
+    "#source-crop-y",
+    "#source-crop-width",
+    "#source-crop-height",
+    "#source-scale",
+    "#source-pan-x",
+    "#source-pan-y",
+    "#grid-align",
+    "#grid-align-step",
+    "#pixel-art",
+    "#lossless",
+    "#indexed-palettes",
+    "#ase-layer",
+    "#ase-frame",
+    "#linear-light",
   ];
 
   inputs.forEach((selector) => {
@@ -3448,6 +4664,50 @@ async function saveProject() {
         batBigEndian: document.querySelector("#bat-big-endian")?.checked,
         palBigEndian: document.querySelector("#pal-big-endian")?.checked,
         tilesBigEndian: document.querySelector("#tiles-big-endian")?.checked,
+        tileReductionMode: document.querySelector("#tile-reduction-mode")?.value,
+        tileReductionThreshold: document.querySelector("#tile-reduction-threshold")?.value,
+        maxUniqueTiles: document.querySelector("#max-unique-tiles")?.value,
+        ditherSerpentine: document.querySelector("#dither-serpentine")?.checked,
+        ditherStrength: document.querySelector("#dither-strength")?.value,
+        ditherCrossTile: document.querySelector("#dither-cross-tile")?.checked,
+        ditherErrorClamp: document.querySelector("#dither-error-clamp")?.value,
+        orderedMatrix: document.querySelector("#ordered-matrix")?.value,
+        orderedSpread: document.querySelector("#ordered-spread")?.value,
+        orderedAlign: document.querySelector("#ordered-align")?.value,
+        patternReuseTolerance: document.querySelector("#pattern-reuse-tolerance")?.value,
+        flickerMode: document.querySelector("#flicker-mode")?.checked,
+        paletteBandRows: document.querySelector("#palette-band-rows")?.value,
+        maxBandPaletteChanges: document.querySelector("#max-band-palette-changes")?.value,
+        rasterBands: document.querySelector("#raster-bands")?.value,
+        sgxMode: document.querySelector("#sgx-mode")?.value,
+        sgxSplitColors: document.querySelector("#sgx-split-colors")?.value,
+        spriteOverlay: document.querySelector("#sprite-overlay")?.checked,
+        maxOverlaySprites: document.querySelector("#max-overlay-sprites")?.value,
+        conversionTarget: document.querySelector("#conversion-target")?.value,
+        spriteCellSize: document.querySelector("#sprite-cell-size")?.value,
+        animationSource: document.querySelector("#animation-source")?.value,
+        animationFrameWidth: document.querySelector("#animation-frame-width")?.value,
+        animationFrameHeight: document.querySelector("#animation-frame-height")?.value,
+        animationFrameCount: document.querySelector("#animation-frame-count")?.value,
+        largeMap: document.querySelector("#large-map")?.checked,
+        mapOrder: document.querySelector("#map-order")?.value,
+        videoMode: document.querySelector("#video-mode")?.value,
+        displayAspect: document.querySelector("#display-aspect")?.checked,
+        sourceCropX: document.querySelector("#source-crop-x")?.value,
+        sourceCropY: document.querySelector("#source-crop-y")?.value,
+        sourceCropWidth: document.querySelector("#source-crop-width")?.value,
+        sourceCropHeight: document.querySelector("#source-crop-height")?.value,
+        sourceScale: document.querySelector("#source-scale")?.value,
+        sourcePanX: document.querySelector("#source-pan-x")?.value,
+        sourcePanY: document.querySelector("#source-pan-y")?.value,
+        gridAlign: document.querySelector("#grid-align")?.value,
+        gridAlignStep: document.querySelector("#grid-align-step")?.value,
+        pixelArt: document.querySelector("#pixel-art")?.checked,
+        lossless: document.querySelector("#lossless")?.checked,
+        indexedPalettes: document.querySelector("#indexed-palettes")?.checked,
+        aseLayer: document.querySelector("#ase-layer")?.value,
+        aseFrame: document.querySelector("#ase-frame")?.value,
+        linearLight: document.querySelector("#linear-light")?.checked,
       },
       // Curve points
       curvePoints: state.curvePoints,
@@ -3602,6 +4862,186 @@ async function loadProject() {
         const el = document.querySelector("#tiles-big-endian");
         if (el) el.checked = s.tilesBigEndian;
       }
+      if (s.tileReductionMode) {
+        const el = document.querySelector("#tile-reduction-mode");
+        if (el) el.value = s.tileReductionMode;
+      }
+      if (s.tileReductionThreshold) {
+        const el = document.querySelector("#tile-reduction-threshold");
+        if (el) el.value = s.tileReductionThreshold;
+      }
+      if (s.maxUniqueTiles) {
+        const el = document.querySelector("#max-unique-tiles");
+        if (el) el.value = s.maxUniqueTiles;
+      }
+      if (s.ditherSerpentine !== undefined) {
+        const el = document.querySelector("#dither-serpentine");
+        if (el) el.checked = s.ditherSerpentine;
+      }
+      if (s.ditherStrength) {
+        const el = document.querySelector("#dither-strength");
+        if (el) {
+          el.value = s.ditherStrength;
+          document.querySelector("#dither-strength-value").textContent = `${s.ditherStrength}%`;
+        }
+      }
+      if (s.ditherCrossTile !== undefined) {
+        const el = document.querySelector("#dither-cross-tile");
+        if (el) el.checked = s.ditherCrossTile;
+      }
+      if (s.ditherErrorClamp) {
+        const el = document.querySelector("#dither-error-clamp");
+        if (el) el.value = s.ditherErrorClamp;
+      }
+      if (s.orderedMatrix) {
+        const el = document.querySelector("#ordered-matrix");
+        if (el) el.value = s.orderedMatrix;
+      }
+      if (s.orderedSpread) {
+        const el = document.querySelector("#ordered-spread");
+        if (el) el.value = s.orderedSpread;
+      }
+      if (s.orderedAlign) {
+        const el = document.querySelector("#ordered-align");
+        if (el) el.value = s.orderedAlign;
+      }
+      if (s.patternReuseTolerance) {
+        const el = document.querySelector("#pattern-reuse-tolerance");
+        if (el) el.value = s.patternReuseTolerance;
+      }
+      if (s.flickerMode !== undefined) {
+        const el = document.querySelector("#flicker-mode");
+        if (el) el.checked = s.flickerMode;
+      }
+      if (s.paletteBandRows) {
+        const el = document.querySelector("#palette-band-rows");
+        if (el) el.value = s.paletteBandRows;
+      }
+      if (s.maxBandPaletteChanges) {
+        const el = document.querySelector("#max-band-palette-changes");
+        if (el) el.value = s.maxBandPaletteChanges;
+      }
+      if (s.rasterBands) {
+        const el = document.querySelector("#raster-bands");
+        if (el) el.value = s.rasterBands;
+      }
+      if (s.sgxMode) {
+        const el = document.querySelector("#sgx-mode");
+        if (el) el.value = s.sgxMode;
+      }
+      if (s.sgxSplitColors) {
+        const el = document.querySelector("#sgx-split-colors");
+        if (el) el.value = s.sgxSplitColors;
+      }
+      if (s.spriteOverlay !== undefined) {
+        const el = document.querySelector("#sprite-overlay");
+        if (el) el.checked = s.spriteOverlay;
+      }
+      if (s.maxOverlaySprites) {
+        const el = document.querySelector("#max-overlay-sprites");
+        if (el) el.value = s.maxOverlaySprites;
+      }
+      if (s.conversionTarget) {
+        const el = document.querySelector("#conversion-target");
+        if (el) el.value = s.conversionTarget;
+      }
+      if (s.spriteCellSize) {
+        const el = document.querySelector("#sprite-cell-size");
+        if (el) el.value = s.spriteCellSize;
+      }
+      if (s.animationSource) {
+        const el = document.querySelector("#animation-source");
+        if (el) el.value = s.animationSource;
+      }
+      if (s.animationFrameWidth) {
+        const el = document.querySelector("#animation-frame-width");
+        if (el) el.value = s.animationFrameWidth;
+      }
+      if (s.animationFrameHeight) {
+        const el = document.querySelector("#animation-frame-height");
+        if (el) el.value = s.animationFrameHeight;
+      }
+      if (s.animationFrameCount) {
+        const el = document.querySelector("#animation-frame-count");
+        if (el) el.value = s.animationFrameCount;
+      }
+      if (s.largeMap !== undefined) {
+        const el = document.querySelector("#large-map");
+        if (el) el.checked = s.largeMap;
+      }
+      if (s.mapOrder) {
+        const el = document.querySelector("#map-order");
+        if (el) el.value = s.mapOrder;
+      }
+      if (s.videoMode) {
+        const el = document.querySelector("#video-mode");
+        if (el) el.value = s.videoMode;
+      }
+      if (s.displayAspect !== undefined) {
+        const el = document.querySelector("#display-aspect");
+        if (el) el.checked = s.displayAspect;
+      }
+      if (s.sourceCropX) {
+        const el = document.querySelector("#source-crop-x");
+        if (el) el.value = s.sourceCropX;
+      }
+      if (s.sourceCropY) {
+        const el = document.querySelector("#source-crop-y");
+        if (el) el.value = s.sourceCropY;
+      }
+      if (s.sourceCropWidth) {
+        const el = document.querySelector("#source-crop-width");
+        if (el) el.value = s.sourceCropWidth;
+      }
+      if (s.sourceCropHeight) {
+        const el = document.querySelector("#source-crop-height");
+        if (el) el.value = s.sourceCropHeight;
+      }
+      if (s.sourceScale) {
+        const el = document.querySelector("#source-scale");
+        if (el) el.value = s.sourceScale;
+      }
+      if (s.sourcePanX) {
+        const el = document.querySelector("#source-pan-x");
+        if (el) el.value = s.sourcePanX;
+      }
+      if (s.sourcePanY) {
+        const el = document.querySelector("#source-pan-y");
+        if (el) el.value = s.sourcePanY;
+      }
+      if (s.gridAlign) {
+        const el = document.querySelector("#grid-align");
+        if (el) el.value = s.gridAlign;
+      }
+      if (s.gridAlignStep) {
+        const el = document.querySelector("#grid-align-step");
+        if (el) el.value = s.gridAlignStep;
+      }
+      if (s.pixelArt !== undefined) {
+        const el = document.querySelector("#pixel-art");
+        if (el) el.checked = s.pixelArt;
+      }
+      if (s.lossless !== undefined) {
+        const el = document.querySelector("#lossless");
+        if (el) el.checked = s.lossless;
+      }
+      if (s.indexedPalettes !== undefined) {
+        const el = document.querySelector("#indexed-palettes");
+        if (el) el.checked = s.indexedPalettes;
+      }
+      if (s.aseLayer !== undefined) {
+        // The layer options are only known once the Aseprite file is read
+        const el = document.querySelector("#ase-layer");
+        if (el) el.dataset.restore = s.aseLayer;
+      }
+      if (s.aseFrame) {
+        const el = document.querySelector("#ase-frame");
+        if (el) el.value = s.aseFrame;
+      }
+      if (s.linearLight !== undefined) {
+        const el = document.querySelector("#linear-light");
+        if (el) el.checked = s.linearLight;
+      }
     }
 
     // Restore curve points
@@ -3632,6 +5072,10 @@ async function loadProject() {
         }
         await loadImageFromPath(imagePath);
         imageLoaded = true;
+        // A large map size can only be restored once the source size is known
+        if (project.settings?.largeMap) {
+          setLargeMapSize(parseInt(project.settings.outputWidthTiles, 10), parseInt(project.settings.outputHeightTiles, 10));
+        }
       } catch (e) {
         console.warn("Could not load source image from project:", e);
       }
@@ -3703,7 +5147,8 @@ async function loadImageFromPath(imagePath) {
   state.inputImage = imagePath;
   const inputMeta = document.querySelector("#input-meta");
   const inputCanvas = document.querySelector("#input-canvas");
-  const fileUrl = convertFileSrc(imagePath);
+  await loadAsepriteInfo(imagePath, true);
+  const fileUrl = await getSourceImageUrl(imagePath);
 
   inputCanvas.innerHTML = `
     <div class="viewer__stage">
@@ -4014,6 +5459,7 @@ function bindActions() {
   document.querySelector("#load-project").addEventListener("click", loadProject);
   document.querySelector("#save-project").addEventListener("click", saveProject);
   document.querySelector("#run-conversion").addEventListener("click", runConversion);
+  document.querySelector("#run-palette-sweep")?.addEventListener("click", runPaletteSweep);
   document.querySelector("#zoom-input").addEventListener("input", () => applyZoom("input"));
   document.querySelector("#zoom-output").addEventListener("input", () => applyZoom("output"));
   setupDrag("input");
@@ -4044,6 +5490,21 @@ function bindActions() {
 
   // BAT size and output size controls
   document.querySelector("#bat-size")?.addEventListener("change", updateSizeConstraints);
+  document.querySelector("#video-mode")?.addEventListener("change", applyVideoMode);
+  document.querySelector("#display-aspect")?.addEventListener("change", () => applyZoom("output"));
+  document.querySelector("#source-transform-reset")?.addEventListener("click", resetSourceTransform);
+  document.querySelector("#run-art-validation")?.addEventListener("click", runArtValidation);
+  document.querySelector("#ase-layer")?.addEventListener("change", refreshAsepriteSource);
+  document.querySelector("#ase-frame")?.addEventListener("change", refreshAsepriteSource);
+  document.querySelector("#ase-layer-groups")?.addEventListener("click", importAsepriteLayerGroups);
+  document.querySelector("#large-map")?.addEventListener("change", (e) => {
+    // Start from the full source size when switching to a large map
+    if (e.target.checked) {
+      setLargeMapSize(Math.ceil(state.inputWidth / 8), Math.ceil(state.inputHeight / 8));
+    } else {
+      updateSizeConstraints();
+    }
+  });
   document.querySelector("#output-width-tiles")?.addEventListener("input", (e) => {
     const tiles = e.target.value;
     document.querySelector("#output-width-value").textContent = `${tiles} (${tiles * 8} px)`;
@@ -4060,6 +5521,59 @@ function bindActions() {
   // Initialize size constraints on load
   updateSizeConstraints();
 
+  // Flicker mode: choose which frame is shown in the output viewer
+  document.querySelector("#flicker-view")?.addEventListener("change", (e) => {
+    if (!state.flicker) return;
+    const views = {
+      blend: state.flicker.blended_preview_base64,
+      a: state.outputImageBase64,
+      b: state.flicker.preview_base64,
+    };
+    drawOutputPreview(views[e.target.value] || views.blend);
+  });
+
+  // SuperGrafx mode: choose which layer is shown in the output viewer
+  document.querySelector("#sgx-view")?.addEventListener("change", (e) => {
+    if (!state.sgx) return;
+    const views = {
+      composite: state.sgx.composite_preview_base64,
+      vdc1: state.outputImageBase64,
+      vdc2: state.sgx.preview_base64,
+    };
+    drawOutputPreview(views[e.target.value] || views.composite);
+  });
+
+  // Color boost sprites: show the background with or without them
+  document.querySelector("#overlay-view")?.addEventListener("change", (e) => {
+    if (!state.spriteOverlay) return;
+    const views = {
+      composite: state.spriteOverlay.composite_preview_base64,
+      bg: state.outputImageBase64,
+    };
+    drawOutputPreview(views[e.target.value] || views.composite);
+  });
+
+  // SuperGrafx background layer image
+  document.querySelector("#sgx-layer-pick")?.addEventListener("click", async () => {
+    const selected = await invoke("open_image");
+    if (!selected) return;
+    state.sgxLayerPath = selected;
+    document.querySelector("#sgx-layer-name").textContent = selected.split(/[\\/]/).pop();
+  });
+
+  // Folder of frames for the sprite animation target
+  document.querySelector("#animation-folder-pick")?.addEventListener("click", async () => {
+    const selected = await invoke("open_folder");
+    if (!selected) return;
+    state.animationFolderPath = selected;
+    document.querySelector("#animation-folder-name").textContent = selected.split(/[\\/]/).pop();
+  });
+
+  // Error diffusion strength display
+  document.querySelector("#dither-strength")?.addEventListener("input", (e) => {
+    document.querySelector("#dither-strength-value").textContent = `${e.target.value}%`;
+  });
+
   // Mask editor controls
   document.querySelector("#mask-toggle")?.addEventListener("click", () => {
     toggleMaskEditing(!state.mask.isEditing);
@@ -4092,6 +5606,10 @@ function bindActions() {
     state.mask.brushSize = Number(e.target.value);
     document.querySelector("#mask-brush-size-value").textContent = e.target.value;
   });
+  document.querySelector("#mask-brush-strength")?.addEventListener("input", (e) => {
+    state.mask.brushStrength = Number(e.target.value);
+    document.querySelector("#mask-brush-strength-value").textContent = `${e.target.value}%`;
+  });
   document.querySelector("#mask-clear-white")?.addEventListener("click", () => {
     clearMask("#FFFFFF");
     saveMaskState();